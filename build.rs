@@ -28,6 +28,9 @@ fn main() {
 
   let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
   let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+  if env::var("CARGO_CFG_UNIX").is_ok() {
+    build_guard();
+  }
   if target_os != "android" {
     return;
   }
@@ -66,6 +69,15 @@ fn main() {
   provide_libgcc_shim();
 }
 
+/// Compile the signal guard used by `guarded` calls. `sigsetjmp` returns twice, which Rust can't
+/// express, so the jump point is written in C.
+fn build_guard() {
+  println!("cargo:rerun-if-changed=src/native/guard.c");
+  cc::Build::new()
+    .file("src/native/guard.c")
+    .compile("ffi_rs_guard");
+}
+
 /// Search a set of likely NDK locations for the per-architecture builtins
 /// archive and return its path if found.
 fn locate_ndk_builtins(target_arch: &str) -> Option<PathBuf> {
@@ -1,9 +1,12 @@
+#include <chrono>
+#include <cstddef>
 #include <cstdint>
 #include <cstdio>
 #include <cstdlib>
 #include <cstring>
 #include <iostream>
 #include <string>
+#include <thread>
 
 extern "C" int sum(int a, int b) { return a + b; }
 
@@ -54,6 +57,16 @@ extern "C" int *createArrayi32(const int *arr, int size)
   return vec;
 }
 
+extern "C" int64_t *createArrayi64(const int64_t *arr, int size)
+{
+  int64_t *vec = (int64_t *)malloc((size) * sizeof(int64_t));
+  for (int i = 0; i < size; i++)
+  {
+    vec[i] = arr[i];
+  }
+  return vec;
+}
+
 extern "C" double *createArrayDouble(const double *arr, int size)
 {
   double *vec = (double *)malloc((size) * sizeof(double));
@@ -304,6 +317,326 @@ extern "C" uint32_t doubleU32(uint32_t input)
 {
     return input * 2;
 }
+typedef struct
+{
+  double x;
+  double y;
+} Vec2;
+
+typedef struct
+{
+  Vec2 origin;
+  int id;
+} Anchor;
+
+typedef Vec2 (*Vec2Transform)(Vec2 p);
+typedef int (*AnchorVisitor)(Anchor a);
+
+extern "C" Vec2 applyVec2Transform(Vec2Transform transform, Vec2 p)
+{
+  return transform(p);
+}
+
+extern "C" int visitAnchor(AnchorVisitor visitor, Anchor a)
+{
+  return visitor(a);
+}
+
+typedef struct
+{
+  int head;
+  int tail;
+  Vec2 origin;
+  double scale;
+} RingHeader;
+
+extern "C" void advanceRing(RingHeader *ring, int n)
+{
+  ring->head += n;
+  ring->origin.x += n;
+}
+
+extern "C" int ringSpan(RingHeader *ring) { return ring->head - ring->tail; }
+
+extern "C" int cLayoutOf(int which)
+{
+  switch (which)
+  {
+  case 0:
+    return sizeof(Person);
+  case 1:
+    return alignof(Person);
+  case 2:
+    return offsetof(Person, stackStruct.age);
+  case 3:
+    return offsetof(Person, byte);
+  case 4:
+    return sizeof(MINUTIAE);
+  case 5:
+    return offsetof(MINUTIAE, item);
+  case 6:
+    return sizeof(Anchor);
+  case 7:
+    return offsetof(Anchor, id);
+  }
+  return -1;
+}
+
+extern "C" int delaySum(int a, int b, int ms)
+{
+  std::this_thread::sleep_for(std::chrono::milliseconds(ms));
+  return a + b;
+}
+
+typedef void (*IntCallback)(int value);
+static IntCallback registeredCallback = NULL;
+
+extern "C" void registerCallback(IntCallback callback)
+{
+  registeredCallback = callback;
+}
+
+typedef struct
+{
+  IntCallback callback;
+} CallbackHolder;
+
+extern "C" void registerCallbackHolder(CallbackHolder *holder)
+{
+  registeredCallback = holder->callback;
+}
+
+extern "C" int triggerCallback(int value)
+{
+  if (registeredCallback == NULL)
+  {
+    return -1;
+  }
+  registeredCallback(value);
+  return 0;
+}
+
+typedef int (*BinaryOp)(int a, int b);
+
+static int multiply(int a, int b) { return a * b; }
+
+extern "C" BinaryOp getBinaryOp(const char *name)
+{
+  if (strcmp(name, "sum") == 0)
+  {
+    return sum;
+  }
+  if (strcmp(name, "multiply") == 0)
+  {
+    return multiply;
+  }
+  return NULL;
+}
+
+typedef int (*IntOp)(int value);
+
+typedef struct
+{
+  IntOp read;
+  IntOp transform;
+} IntOps;
+
+extern "C" int runIntOps(IntOps *ops, int value)
+{
+  return ops->transform(ops->read(value));
+}
+
+typedef int (*U16StringCallback)(const char16_t *value);
+
+extern "C" int callWithNullU16String(U16StringCallback callback)
+{
+  return callback(NULL) + 1;
+}
+
+extern "C" int readIntAt(int64_t address)
+{
+  return *(volatile int *)address;
+}
+
+#if defined(__x86_64__) && !defined(_WIN32)
+extern "C" __attribute__((ms_abi)) int msAbiSum(int a, int b, int c, int d, int e)
+{
+  return a + b + c + d + e;
+}
+
+typedef int(__attribute__((ms_abi)) * MsAbiOp)(int a, int b);
+
+extern "C" int callMsAbiOp(MsAbiOp op, int a, int b)
+{
+  return op(a, b);
+}
+#endif
+
+static int handleDestroyedTotal = 0;
+
+extern "C" int *createHandle(int value)
+{
+  int *handle = (int *)malloc(sizeof(int));
+  *handle = value;
+  return handle;
+}
+
+extern "C" void destroyHandle(int *handle)
+{
+  handleDestroyedTotal += *handle;
+  free(handle);
+}
+
+extern "C" int destroyedHandleTotal()
+{
+  return handleDestroyedTotal;
+}
+
+static int customFreeCount = 0;
+static const int customAllocMagic = 0x5a5a5a5a;
+
+// memory from customAlloc starts after a header, libc free must not be called on it
+static void *customAlloc(size_t size)
+{
+  char *block = (char *)malloc(size + 16);
+  *(int *)block = customAllocMagic;
+  return block + 16;
+}
+
+extern "C" void customFree(void *ptr)
+{
+  char *block = (char *)ptr - 16;
+  if (*(int *)block != customAllocMagic)
+  {
+    abort();
+  }
+  *(int *)block = 0;
+  customFreeCount++;
+  free(block);
+}
+
+extern "C" int customFreedCount()
+{
+  return customFreeCount;
+}
+
+extern "C" char *customString(const char *str)
+{
+  char *copy = (char *)customAlloc(strlen(str) + 1);
+  strcpy(copy, str);
+  return copy;
+}
+
+extern "C" int *customI32Array(int len)
+{
+  int *array = (int *)customAlloc(sizeof(int) * len);
+  for (int i = 0; i < len; i++)
+  {
+    array[i] = i;
+  }
+  return array;
+}
+
+extern "C" char **customStringArray()
+{
+  char **array = (char **)customAlloc(sizeof(char *) * 2);
+  array[0] = customString("foo");
+  array[1] = customString("bar");
+  return array;
+}
+
+typedef struct CustomPerson
+{
+  char *name;
+  int age;
+} CustomPerson;
+
+extern "C" CustomPerson *customPerson(const char *name, int age)
+{
+  CustomPerson *person = (CustomPerson *)customAlloc(sizeof(CustomPerson));
+  person->name = customString(name);
+  person->age = age;
+  return person;
+}
+
+// writes len ints, more than the array holds when len is larger than its length
+extern "C" void fillI32Array(int *arr, int len)
+{
+  for (int i = 0; i < len; i++)
+  {
+    arr[i] = i * 2;
+  }
+}
+
+extern "C" void delayFillI32Array(int *arr, int len, int ms)
+{
+  std::this_thread::sleep_for(std::chrono::milliseconds(ms));
+  fillI32Array(arr, len);
+}
+
+extern "C" void fillString(char *str, int len)
+{
+  memset(str, 'x', len);
+}
+
+extern "C" char16_t *concatenateU16Strings(const char16_t *str1, const char16_t *str2)
+{
+  std::u16string result = std::u16string(str1) + std::u16string(str2);
+  size_t size = (result.length() + 1) * sizeof(char16_t);
+  char16_t *u16str = (char16_t *)malloc(size);
+  memcpy(u16str, result.c_str(), size);
+  return u16str;
+}
+
+extern "C" char32_t *concatenateU32Strings(const char32_t *str1, const char32_t *str2)
+{
+  std::u32string result = std::u32string(str1) + std::u32string(str2);
+  size_t size = (result.length() + 1) * sizeof(char32_t);
+  char32_t *u32str = (char32_t *)malloc(size);
+  memcpy(u32str, result.c_str(), size);
+  return u32str;
+}
+
+// the code units of a string as c received it
+extern "C" int byteAt(const unsigned char *str, int index) { return str[index]; }
+
+extern "C" int u16CodeUnitAt(const char16_t *str, int index) { return str[index]; }
+
+extern "C" const char16_t *unpairedSurrogateString()
+{
+  static const char16_t str[] = {u'a', 0xD800, 0};
+  return str;
+}
+
+extern "C" const char *invalidUtf8String() { return "a\xff"; }
+
+extern "C" const char *gbkString()
+{
+  // "中文" in GBK
+  return "\xd6\xd0\xce\xc4";
+}
+
+typedef struct EncodedStrings
+{
+  const char16_t *u16;
+  const char32_t *u32;
+  const char *latin1;
+  const char *gbk;
+  const char16_t **u16Array;
+} EncodedStrings;
+
+extern "C" EncodedStrings *getEncodedStrings(EncodedStrings *strings) { return strings; }
+
+extern "C" const char16_t **getU16StringArray(const char16_t **arr) { return arr; }
+
+// the param after the string checks that the length is passed as its own argument
+extern "C" int lengthDelimitedByteAt(const char *data, size_t len, int index)
+{
+  return index < len ? (unsigned char)data[index] : -1;
+}
+
+extern "C" size_t lengthDelimitedLength(const char *data, size_t len) { return len; }
+
 // typedef void (*CallbackType)(const char *);
 // extern "C" void call_callback_async() {
 //   dispatch_async(dispatch_get_main_queue(), ^{
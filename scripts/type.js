@@ -305,7 +305,7 @@ if (!nativeBinding) {
     (processParamsTypeForArray(params))
 }
 
-const { DataType, createPointer, restorePointer, unwrapPointer, wrapPointer, freePointer, open, close, load, isNullPointer, FFITypeTag, createExternalBuffer } = nativeBinding
+const { DataType, createPointer, restorePointer, unwrapPointer, wrapPointer, freePointer, open, close, load, callPointer, isNullPointer, FFITypeTag, createExternalBuffer, createExternalArrayBuffer, createCallback, Library, isTainted, sizeOf, alignOf, offsetOf, layout, createStructView } = nativeBinding
 DataType.StackStruct = 999
 DataType.Function = 998
 DataType.Array = 997
@@ -316,19 +316,41 @@ exports.open = open
 exports.close = close
 exports.load = load
 exports.isNullPointer = isNullPointer
+exports.isTainted = isTainted
+exports.sizeOf = sizeOf
+exports.alignOf = alignOf
+exports.offsetOf = offsetOf
+exports.layout = layout
 exports.FFITypeTag = FFITypeTag
-const arrayDataType = [DataType.I16Array, DataType.I32Array, DataType.StringArray, DataType.DoubleArray, DataType.U8Array, DataType.FloatArray]
+const arrayDataType = [DataType.I16Array, DataType.I32Array, DataType.I64Array, DataType.StringArray, DataType.DoubleArray, DataType.U8Array, DataType.FloatArray]
+const arrayItemSize = {
+  [DataType.I16Array]: 2,
+  [DataType.I32Array]: 4,
+  [DataType.I64Array]: 8,
+  [DataType.FloatArray]: 4,
+  [DataType.DoubleArray]: 8,
+}
 const arrayConstructor = (options) => ({
   ffiTypeTag: FFITypeTag.Array,
   ...options
 })
 
+const isArrayBuffer = (value) =>
+  value instanceof ArrayBuffer || (typeof SharedArrayBuffer !== 'undefined' && value instanceof SharedArrayBuffer)
+
+// Node-API can't read the memory of a SharedArrayBuffer, the pointer of a buffer is passed through a view of it
+const processParamsValue = (paramsType, paramsValue) => paramsValue?.map((value, index) =>
+  paramsType?.[index] === DataType.External && isArrayBuffer(value) ? new Uint8Array(value) : value)
+
 const processParamsTypeForArray = (params) => {
+  params.paramsValue = processParamsValue(params.paramsType, params.paramsValue)
   params.paramsType = params.paramsType?.map((paramType, index) => {
     if (arrayDataType.includes(paramType)) {
+      const value = params.paramsValue[index]
       return arrayConstructor({
         type: paramType,
-        length: params.paramsValue[index].length,
+        // a DataView is read as items of the array type
+        length: value instanceof DataView ? Math.floor(value.byteLength / arrayItemSize[paramType]) : value.length,
       })
     }
     return paramType
@@ -353,12 +375,69 @@ const wrapLoad = (params) => {
   return load(processParamsTypeForArray(params))
 }
 exports.load = wrapLoad
+exports.callPointer = (params) => {
+  if (params.freeResultMemory === undefined) {
+    params.freeResultMemory = false
+  }
+  return callPointer(processParamsTypeForArray(params))
+}
+const libraryFunc = Library.prototype.func
+Library.prototype.func = function (funcName, signature) {
+  // the signature is bound once, an array param without length takes the length of its value
+  const func = libraryFunc.call(this, funcName, {
+    freeResultMemory: false,
+    ...signature,
+    paramsType: signature.paramsType?.map((paramType) =>
+      arrayDataType.includes(paramType) ? arrayConstructor({ type: paramType }) : paramType),
+  })
+  return (paramsValue = []) => func.invoke(processParamsValue(signature.paramsType, paramsValue))
+}
+exports.Library = Library
 exports.createPointer = (params) => createPointer(processParamsTypeForArray(params))
 exports.restorePointer = (params) => restorePointer(processParamsTypeForArray(params))
 exports.unwrapPointer = (params) => unwrapPointer(processParamsTypeForArray(params))
 exports.wrapPointer = (params) => wrapPointer(processParamsTypeForArray(params))
 exports.freePointer = (params) => freePointer(setFreePointerTag(processParamsTypeForArray(params)))
 exports.createExternalBuffer = createExternalBuffer
+exports.createExternalArrayBuffer = createExternalArrayBuffer
+exports.createStructView = createStructView
+exports.readI8 = nativeBinding.readI8
+exports.writeI8 = nativeBinding.writeI8
+exports.readU8 = nativeBinding.readU8
+exports.writeU8 = nativeBinding.writeU8
+exports.readI16 = nativeBinding.readI16
+exports.writeI16 = nativeBinding.writeI16
+exports.readU16 = nativeBinding.readU16
+exports.writeU16 = nativeBinding.writeU16
+exports.readI32 = nativeBinding.readI32
+exports.writeI32 = nativeBinding.writeI32
+exports.readU32 = nativeBinding.readU32
+exports.writeU32 = nativeBinding.writeU32
+exports.readI64 = nativeBinding.readI64
+exports.writeI64 = nativeBinding.writeI64
+exports.readU64 = nativeBinding.readU64
+exports.writeU64 = nativeBinding.writeU64
+exports.readFloat = nativeBinding.readFloat
+exports.writeFloat = nativeBinding.writeFloat
+exports.readDouble = nativeBinding.readDouble
+exports.writeDouble = nativeBinding.writeDouble
+exports.readPointer = nativeBinding.readPointer
+exports.writePointer = nativeBinding.writePointer
+exports.readCString = nativeBinding.readCString
+exports.memcpy = nativeBinding.memcpy
+exports.memset = nativeBinding.memset
+exports.ptrAdd = nativeBinding.ptrAdd
+exports.pointerToAddress = nativeBinding.pointerToAddress
+exports.addressToPointer = nativeBinding.addressToPointer
+exports.pointerToString = nativeBinding.pointerToString
+exports.pointerEquals = nativeBinding.pointerEquals
+exports.pointerOffset = nativeBinding.pointerOffset
+exports.pointerDiff = nativeBinding.pointerDiff
+exports.dispose = nativeBinding.dispose
+exports.withScope = nativeBinding.withScope
+exports.setAllocationTracking = nativeBinding.setAllocationTracking
+exports.getLiveAllocations = nativeBinding.getLiveAllocations
+exports.setMemoryHardening = nativeBinding.setMemoryHardening
 exports.arrayConstructor = arrayConstructor
 
 exports.funcConstructor = (options) => ({
@@ -367,6 +446,11 @@ exports.funcConstructor = (options) => ({
   freeCFuncParamsMemory: false,
   ...options,
 })
+exports.stringConstructor = (options) => ({
+  ffiTypeTag: FFITypeTag.String,
+  ...options,
+})
+exports.createCallback = (fn, signature) => createCallback(fn, exports.funcConstructor(signature))
 exports.define = (obj) => {
   const res = {}
   Object.entries(obj).map(([funcName, funcDesc]) => {
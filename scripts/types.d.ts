@@ -7,11 +7,11 @@ export interface JsExternal {
 
 export enum DataType {
   /**
-   UTF-16 String, equivalent to char*
+   UTF-16 String, equivalent to char*. Passing a string with a null character throws
   */
   String = 0,
   /**
-   UTF-16 String, equivalent to wchar*
+   UTF-16 String, equivalent to wchar*. Passing a string with a null character throws
   */
   WString = 15,
   I32 = 1,
@@ -51,6 +51,26 @@ export enum DataType {
   StructArray = 18,
   I16Array = 19,
   U32 = 20,
+  /**
+   The items are passed as i64 and created from i64 as BigInt
+  */
+  I64Array = 21,
+  /**
+   UTF-16 String, equivalent to char16_t* whatever the size of wchar_t is
+  */
+  U16String = 22,
+  /**
+   UTF-32 String, equivalent to char32_t*
+  */
+  U32String = 23,
+  /**
+   ISO-8859-1 String, equivalent to char*. Passing a character above U+00FF throws
+  */
+  Latin1String = 24,
+  /**
+   UTF-8 String passed as two arguments, equivalent to (const char* data, size_t len). Null characters are kept, only usable in paramsType
+  */
+  StringWithLength = 25,
   StackStruct = 999, // reserve keyword
   StackArray = 996,
   Function = 998,
@@ -61,6 +81,14 @@ type DataTypeToType<T> = T extends DataType.String
   ? string
   : T extends DataType.WString
   ? string
+  : T extends DataType.U16String
+  ? string
+  : T extends DataType.U32String
+  ? string
+  : T extends DataType.Latin1String
+  ? string
+  : T extends DataType.StringWithLength
+  ? string
   : T extends DataType.U8
   ? number
   : T extends DataType.I32
@@ -83,6 +111,8 @@ type DataTypeToType<T> = T extends DataType.String
   ? number[]
   : T extends DataType.I32Array
   ? number[]
+  : T extends DataType.I64Array
+  ? BigInt[]
   : T extends DataType.StringArray
   ? string[]
   : T extends DataType.DoubleArray
@@ -98,6 +128,7 @@ type DataTypeToType<T> = T extends DataType.String
 export enum FFITypeTag {
   StackStruct = DataType.StackStruct,
   StackArray = DataType.StackArray,
+  String = 995,
 }
 
 export interface ArrayConstructorOptions {
@@ -106,31 +137,129 @@ export interface ArrayConstructorOptions {
   ffiTypeTag?: FFITypeTag;
   dynamicArray?: boolean
   structItemType?: RecordFieldType
+  // Default:false, return the items of I16Array, I32Array, I64Array, FloatArray and DoubleArray as a TypedArray.
+  // "copy" copies them into a new ArrayBuffer, "view" creates an ArrayBuffer over the native memory without
+  // copying, which must outlive the TypedArray and can't be used with freeResultMemory
+  typedArray?: "copy" | "view"
+  // the encoding of the strings of a StringArray, see StringConstructorOptions.encoding
+  encoding?: string
 }
 
+export interface StringConstructorOptions {
+  // Default:"utf8", "utf8", "utf16", "utf32", "latin1" or a name iconv knows like "GBK" or "SHIFT_JIS",
+  // legacy encodings are converted by iconv which is only available on unix except android.
+  // Unlike DataType.String, a string which can't be encoded or decoded throws an error with the code
+  // ERR_FFI_ENCODING instead of being replaced, so does a string containing a null character
+  encoding?: string
+}
+
+export interface StringType extends StringConstructorOptions {
+  ffiTypeTag: FFITypeTag.String;
+}
+
+type DataTypeToTypedArray<T> = T extends DataType.I16Array
+  ? Int16Array
+  : T extends DataType.I32Array
+  ? Int32Array
+  : T extends DataType.I64Array
+  ? BigInt64Array
+  : T extends DataType.FloatArray
+  ? Float32Array
+  : T extends DataType.DoubleArray
+  ? Float64Array
+  : never;
+
 export interface FuncConstructorOptions {
   paramsType: FieldType[];
   retType: FieldType;
   needFree?: boolean
   // Default:false, whether or not free function call params memory automatically which are allocated in c side
   freeCFuncParamsMemory?: boolean
+  // Default:"default", the calling convention the callback is called with, see FFIParams.abi
+  abi?: string
 }
 
 export function arrayConstructor(options: ArrayConstructorOptions): ArrayConstructorOptions;
 
 export function funcConstructor(options: FuncConstructorOptions): FuncConstructorOptions;
 
+export function stringConstructor(options: StringConstructorOptions): StringType;
+
+/**
+ * Turn a JS function into a C function pointer, e.g. to fill a vtable or a
+ * struct field declared as `DataType.External`.
+ *
+ * The callback is released once the returned External is garbage collected, so
+ * keep a reference to it for as long as C may call it. It does not keep the
+ * process alive on its own.
+ */
+export function createCallback(fn: (...args: any[]) => unknown, signature: FuncConstructorOptions): JsExternal;
+
 export interface OpenParams {
   library: string;
   path: string;
 }
 
-export function open(params: OpenParams): void;
-export function close(library: string): void;
+export type FuncSignature<T, E = undefined, R = undefined> = Omit<FFIParams<T, E, R>, 'library' | 'funcName' | 'paramsValue'>
+
+/**
+ * Handle of a dynamic library returned from `open`.
+ *
+ * The library is owned by its registration under the name given to `open`, the
+ * handle is closed as soon as the name is unregistered by `close(library)` or by
+ * `close()` of any handle of the library. Functions created by `func` and symbols
+ * returned from `symbol` keep the library loaded on their own, so the library is
+ * only unloaded once it is closed and every function and symbol derived from it
+ * has been garbage collected.
+ */
+export class Library {
+  /** The path the library was opened from */
+  readonly path: string;
+  /**
+   * Look up `funcName` and parse the signature once, then return a function which calls it
+   * with the given values. Throws if the symbol does not exist, the signature is invalid or
+   * the handle has been closed.
+   */
+  func<T extends FieldType, E = undefined, R extends boolean | undefined = undefined>(
+    funcName: string,
+    signature: FuncSignature<T, E, R>
+  ): (paramsValue?: unknown[]) => R extends true ? Promise<ResultWithErrno<FieldTypeToType<T>, E>> : ResultWithErrno<FieldTypeToType<T>, E>;
+  /** The raw address of `name` as `DataType.External`, the library stays loaded while it is alive */
+  symbol(name: string): JsExternal;
+  /** Whether the library exports `name` */
+  has(name: string): boolean;
+  /**
+   * Unregister the name of the library when it still refers to this library, which closes
+   * every handle of it. Functions created by `func` keep working until they are garbage collected.
+   */
+  close(params?: CloseParams): void;
+}
+
+export interface CloseParams {
+  /**
+   * Default:false. Callbacks which have been passed to the library keep it loaded
+   * since the library may still call them, `force` releases them so the library is
+   * unloaded right away. Pending `runInNewThread` calls still defer the unload
+   * until they return.
+   */
+  force?: boolean;
+}
+
+export function open(params: OpenParams): Library;
+/**
+ * Unregister `library` and close the handles returned from `open` for it. The library
+ * is unloaded once nothing else uses it: other worker threads, functions created by `Library.func`,
+ * symbols, pending `runInNewThread` calls and callbacks which have been passed to it
+ * until they are freed.
+ */
+export function close(library: string, params?: CloseParams): void;
 
 export function createPointer(params: {
   paramsType: FieldType[];
   paramsValue: unknown[];
+  // released when the returned External is garbage collected, a PointerType frees each pointer for its
+  // paramsType the way freePointer does
+  finalizer?: PointerType | Destructor;
 }): JsExternal[]
 
 export enum PointerType {
@@ -138,10 +267,17 @@ export enum PointerType {
   CPointer = 1
 }
 
+/**
+ * Free the memory of the pointers. The finalizer of an External created with one is cancelled, so the memory isn't
+ * released again when it is garbage collected, and the External wraps a null pointer afterwards.
+ */
 export function freePointer(params: {
   paramsType: FieldType[];
   paramsValue: JsExternal[];
   pointerType: PointerType
+  // only with PointerType.CPointer, frees the memory allocated by C instead of libc free, the storage
+  // created by wrapPointer or createPointer is still released by ffi-rs
+  deallocator?: Destructor
 }): void
 
 export function restorePointer<T>(params: {
@@ -149,12 +285,201 @@ export function restorePointer<T>(params: {
   paramsValue: JsExternal[];
 }): DataTypeToType<T>[]
 
+/**
+ * A native function taking the pointer to release, either a symbol of an opened library such as
+ * `{ library: "libsqlite3", funcName: "sqlite3_finalize" }` or a function pointer.
+ */
+export type Destructor = { library: string; funcName: string } | JsExternal
+
+/**
+ * Releases the memory of an external Buffer or ArrayBuffer once it is garbage collected, either
+ * `"free"` for the libc free, a destructor or a JS function called with the pointer.
+ */
+export type BufferFinalizer = "free" | Destructor | ((ptr: JsExternal) => void)
+
+/**
+ * Release the pointer of an External created with a `finalizer` now instead of when it is garbage
+ * collected, the finalizer won't run again. The External wraps a null pointer afterwards.
+ * Returns false when there is nothing to release.
+ */
+export function dispose(ptr: JsExternal): boolean
+
+/**
+ * Run `callback` in a scope owning the memory of every pointer `createPointer` creates while it runs.
+ * The memory is released at once when the callback returns or throws, so the pointers must not be used
+ * after it. `freePointer` does nothing for them. Only the synchronous part of the callback is in the scope.
+ */
+export function withScope<T>(callback: () => T): T
+
+/**
+ * Memory allocated by ffi-rs which hasn't been released yet, see getLiveAllocations.
+ * `kind` is "callback" for the closure of a JS function and "value" for anything else. `funcName` is the function the
+ * memory has been allocated for, or "createPointer"/"createCallback". The nested strings, arrays and structs of a value
+ * are listed on their own with the `type` of the value they belong to.
+ */
+export interface LiveAllocation {
+  address: bigint
+  kind: "value" | "callback"
+  type: FieldType
+  library?: string
+  funcName: string
+}
+
+/**
+ * Record the memory allocated for call arguments, `createPointer` and callbacks until it is released by the end of
+ * the call, `freePointer`, `dispose` or a finalizer. This is a debug mode meant to assert leaks in tests, it slows
+ * every call down. Disabling it drops the records.
+ */
+export function setAllocationTracking(enabled: boolean): void
+
+/**
+ * The allocations recorded since `setAllocationTracking(true)` which are still outstanding, in allocation order.
+ * Throws when the tracking is disabled.
+ */
+export function getLiveAllocations(): LiveAllocation[]
+
+/**
+ * Surround the arrays, structs and strings allocated for call arguments with canary bytes which are checked after the
+ * call, so a function writing out of their bounds throws `ERR_FFI_OUT_OF_BOUNDS` with the `argIndex` of the parameter
+ * instead of silently corrupting the heap. The memory of call arguments, `withScope` and `createPointer` is poisoned
+ * with `0xdd` bytes when it is released, so reading a freed pointer gives a recognizable value, and freeing it again
+ * throws `ERR_FFI_INVALID_ARG`. This is a debug mode, `createPointer` and `freePointer` are much slower.
+ */
+export function setMemoryHardening(enabled: boolean): void
+
 export function unwrapPointer(params: JsExternal[]): JsExternal[]
 
 export function wrapPointer(params: JsExternal[]): JsExternal[]
 
+/**
+ * Create a view over a struct in native memory. Reading a property reads the field at its
+ * offset and assigning a property writes it, so changes made by C are visible and writes from
+ * JS reach C without copying the struct. Fields of a `StackStruct` field are nested views.
+ *
+ * `ptr` is the pointer restorePointer takes for `structType`, e.g. the result of createPointer.
+ * The view doesn't own the memory, it must stay valid while the view is used. Assigning a
+ * string or a pointer field allocates new memory, the previous value is not freed.
+ */
+export function createStructView<T extends RecordFieldType>(
+  ptr: JsExternal,
+  structType: T,
+): FieldTypeToType<T>
+
+/**
+ * Errors thrown by ffi-rs carry a stable `code`, other errors keep the napi status as code
+ * (e.g. `NumberExpected` when an item of a number array isn't a number).
+ */
+export interface FFIError extends Error {
+  code:
+  | 'ERR_FFI_LIBRARY_NOT_FOUND' // the library hasn't been opened, has been closed or can't be loaded
+  | 'ERR_FFI_SYMBOL_NOT_FOUND'
+  | 'ERR_FFI_TYPE' // an unsupported type or option
+  | 'ERR_FFI_ARG_COUNT' // paramsValue and paramsType have a different length
+  | 'ERR_FFI_INVALID_ARG' // a value of paramsValue doesn't match its type or declared array length
+  | 'ERR_FFI_FAULT' // a guarded call received SIGSEGV/SIGBUS
+  | 'ERR_FFI_OUT_OF_BOUNDS' // memory hardening detected a write out of the bounds of an argument
+  | 'ERR_FFI_INTERNAL'
+  | string
+  library?: string
+  funcName?: string
+  // index of the param whose value couldn't be converted
+  argIndex?: number
+  // the error message of the dynamic loader when open failed
+  dlerror?: string
+}
+
 export function isNullPointer(params: JsExternal): boolean
 
+/**
+ * Read and write values at `ptr + offset` in native memory, `offset` is in bytes and defaults
+ * to 0. The address doesn't need to be aligned. 64 bit integers are read and written as bigint.
+ * Writing a value the type can't hold throws instead of truncating it.
+ */
+export function readI8(ptr: JsExternal, offset?: number): number
+export function writeI8(ptr: JsExternal, offset: number | undefined, value: number): void
+export function readU8(ptr: JsExternal, offset?: number): number
+export function writeU8(ptr: JsExternal, offset: number | undefined, value: number): void
+export function readI16(ptr: JsExternal, offset?: number): number
+export function writeI16(ptr: JsExternal, offset: number | undefined, value: number): void
+export function readU16(ptr: JsExternal, offset?: number): number
+export function writeU16(ptr: JsExternal, offset: number | undefined, value: number): void
+export function readI32(ptr: JsExternal, offset?: number): number
+export function writeI32(ptr: JsExternal, offset: number | undefined, value: number): void
+export function readU32(ptr: JsExternal, offset?: number): number
+export function writeU32(ptr: JsExternal, offset: number | undefined, value: number): void
+export function readI64(ptr: JsExternal, offset?: number): bigint
+export function writeI64(ptr: JsExternal, offset: number | undefined, value: bigint): void
+export function readU64(ptr: JsExternal, offset?: number): bigint
+export function writeU64(ptr: JsExternal, offset: number | undefined, value: bigint): void
+export function readFloat(ptr: JsExternal, offset?: number): number
+export function writeFloat(ptr: JsExternal, offset: number | undefined, value: number): void
+export function readDouble(ptr: JsExternal, offset?: number): number
+export function writeDouble(ptr: JsExternal, offset: number | undefined, value: number): void
+export function readPointer(ptr: JsExternal, offset?: number): JsExternal
+export function writePointer(ptr: JsExternal, offset: number | undefined, value: JsExternal): void
+/**
+ * Read a NUL terminated string. When `maxLen` is set at most `maxLen` bytes are read, for
+ * buffers which may not be terminated.
+ */
+export function readCString(ptr: JsExternal, offset?: number, maxLen?: number): string
+// copy `n` bytes from `src` to `dst`, the regions may overlap
+export function memcpy(dst: JsExternal, src: JsExternal, n: number | bigint): void
+export function memset(ptr: JsExternal, value: number, n: number | bigint): void
+// a new External pointing `bytes` bytes after `ptr`
+export function ptrAdd(ptr: JsExternal, bytes: number): JsExternal
+
+// the address of the pointer, e.g. to use a pointer as a Map key
+export function pointerToAddress(ptr: JsExternal): bigint
+export function addressToPointer(address: bigint): JsExternal
+// the address formatted like `0x7f12a4c0` for logs
+export function pointerToString(ptr: JsExternal): string
+export function pointerEquals(a: JsExternal, b: JsExternal): boolean
+/**
+ * A new External pointing `n` elements of `elementType` after `ptr`, e.g. the `n`th item of a
+ * struct array. `n` is in bytes when `elementType` is omitted.
+ */
+export function pointerOffset(ptr: JsExternal, n: number, elementType?: FieldType): JsExternal
+// the distance in bytes from `b` to `a`
+export function pointerDiff(a: JsExternal, b: JsExternal): number
+
+/**
+ * Size in bytes of the value described by a type, computed by the same layout code that
+ * ffi-rs uses to marshal structs. A struct type is measured by its fields, an `Array` is a
+ * pointer while a `StackArray` is measured by its items.
+ */
+export function sizeOf(type: FieldType): number
+
+export function alignOf(type: FieldType): number
+
+/**
+ * Offset in bytes of a field from the start of the struct. Fields of a nested `StackStruct`
+ * are reached with a dotted path like `header.flags`.
+ */
+export function offsetOf(structType: RecordFieldType, fieldPath: string): number
+
+export interface FieldLayout {
+  name: string
+  offset: number
+  size: number
+  align: number
+  // bytes inserted before the field to align it
+  padding: number
+}
+
+export interface StructLayout {
+  size: number
+  align: number
+  fields: FieldLayout[]
+}
+
+export function layout(structType: RecordFieldType): StructLayout
+
+/**
+ * Whether a `guarded` call has faulted in this process. A `FFITaintWarning` is emitted when it happens,
+ * the state of the process can't be trusted anymore afterwards.
+ */
+export function isTainted(): boolean
+
 /**
  * Wrap an existing native pointer into a Node.js `Buffer` **without copying**.
  *
@@ -166,13 +491,27 @@ export function isNullPointer(params: JsExternal): boolean
  * or any `JsExternal` from `createPointer`/`unwrapPointer`/`wrapPointer`).
  * `length` is the number of bytes to expose.
  *
- * ffi-rs does NOT free the underlying memory when the Buffer is garbage
- * collected; the caller owns the pointer's lifetime (e.g. a region returned by
- * `mmap` must stay mapped until you are done with the Buffer and call `munmap`
- * yourself). On runtimes that forbid external buffers (e.g. Electron) this
- * silently falls back to copying the data.
+ * Without a `finalizer` ffi-rs does NOT free the underlying memory when the
+ * Buffer is garbage collected; the caller owns the pointer's lifetime (e.g. a
+ * region returned by `mmap` must stay mapped until you are done with the Buffer
+ * and call `munmap` yourself). With a `finalizer` the Buffer owns the memory and
+ * the finalizer releases it once the Buffer is garbage collected, e.g. `"free"`
+ * for memory from `malloc`. On runtimes that forbid external buffers (e.g.
+ * Electron) this silently falls back to copying the data and the finalizer runs
+ * at once.
  */
-export function createExternalBuffer(external: JsExternal, length: number): Buffer
+export function createExternalBuffer(external: JsExternal, length: number, finalizer?: BufferFinalizer): Buffer
+
+/**
+ * Wrap an existing native pointer into an `ArrayBuffer` **without copying**, like
+ * `createExternalBuffer`. When a `finalizer` is given it releases the memory once the
+ * ArrayBuffer is garbage collected, otherwise the caller keeps owning the memory.
+ *
+ * Node-API can't create a SharedArrayBuffer over native memory, to share it between worker
+ * threads pass the pointer to each of them instead. On runtimes that forbid external buffers
+ * the data is copied and the finalizer runs at once.
+ */
+export function createExternalArrayBuffer(external: JsExternal, length: number, finalizer?: BufferFinalizer): ArrayBuffer
 
 type ResultWithErrno<T, E = undefined> = E extends true
   ? { value: T; errnoCode: number; errnoMessage: string }
@@ -186,14 +525,19 @@ export type FieldType =
   | DataType
   | ArrayConstructorOptions
   | FuncConstructorOptions
+  | StringType
   | RecordFieldType
 
 interface RecordFieldType extends Record<string, FieldType> { }
 
 type FieldTypeToType<T> = T extends DataType
   ? DataTypeToType<T>
+  : T extends ArrayConstructorOptions & { typedArray: "copy" | "view" }
+  ? DataTypeToTypedArray<T['type']>
   : T extends ArrayConstructorOptions
   ? DataTypeToType<T['type']>
+  : T extends StringType
+  ? string
   : T extends RecordFieldType
   ? { [K in keyof T]: FieldTypeToType<T[K]> }
   : never;
@@ -203,18 +547,52 @@ export interface FFIParams<T, E = undefined, R = undefined> {
   funcName: string;
   retType: T;
   paramsType: FieldType[];
+  // the items of I16Array, I32Array, I64Array, FloatArray and DoubleArray params can also be a TypedArray of the
+  // same item type or a DataView, C reads and writes their memory in place without copying.
+  // DataType.External params can also be an ArrayBuffer, a SharedArrayBuffer or a view of one, e.g.
+  // `new Uint8Array(sab, byteOffset, length)`, C receives a pointer to its memory which must stay
+  // referenced until C is done with it
   paramsValue: unknown[];
   // whether need output errno
   errno?: E
   runInNewThread?: R
   // Default:false, whether or not need to free the result of return value memory automatically
   freeResultMemory?: boolean
+  // Default:"default", the calling convention of the function. Supported values depend on the target,
+  // e.g. "sysv"/"unix64", "win64", "efi64" and "gnuw64" on x86_64, "stdcall", "fastcall" and "thiscall" on x86
+  abi?: string
+  // Default:false, unix only. Turn a SIGSEGV/SIGBUS raised by the call into a thrown Error with the faulting
+  // address instead of crashing the process. Meant for exploratory bindings and test suites: the native code
+  // was interrupted halfway, it may have left corrupted memory or held locks behind, so the process is marked
+  // as tainted (see isTainted) and should be restarted as soon as possible. The memory of the faulted call is
+  // leaked, and a stack overflow can't be recovered from.
+  guarded?: boolean
+  // only with retType DataType.External, the returned pointer is passed to the destructor when the External
+  // is garbage collected or disposed
+  finalizer?: Destructor
+  // frees the memory of the result and the strings, arrays and structs it points to instead of libc free
+  // when freeResultMemory is set, for libraries with their own allocator such as `g_free` or `sqlite3_free`
+  deallocator?: Destructor
 }
 
 export function load<T extends FieldType, E = undefined, R extends boolean | undefined = undefined>(
   params: FFIParams<T, E, R>
 ): R extends true ? Promise<ResultWithErrno<FieldTypeToType<T>, E>> : ResultWithErrno<FieldTypeToType<T>, E>
 
+export interface CallPointerParams<T, E = undefined, R = undefined> extends Omit<FFIParams<T, E, R>, 'library' | 'funcName'> {
+  // the address of the function, e.g. a function pointer returned as DataType.External or the result of Library.symbol
+  funcPtr: JsExternal;
+}
+
+/**
+ * Call a function through its address instead of looking it up by name, with the
+ * same marshalling as `load`. Useful for function pointers which are only known
+ * at runtime such as vtables or `GetProcAddress`-style registries.
+ */
+export function callPointer<T extends FieldType, E = undefined, R extends boolean | undefined = undefined>(
+  params: CallPointerParams<T, E, R>
+): R extends true ? Promise<ResultWithErrno<FieldTypeToType<T>, E>> : ResultWithErrno<FieldTypeToType<T>, E>
+
 type FuncObj<T, E = undefined, R = undefined> = Record<string, Omit<FFIParams<T, E, R>, 'paramsValue' | 'funcName'>>
 
 export function define<T extends FuncObj<FieldType, boolean | undefined, boolean | undefined>>(funcs: T): {
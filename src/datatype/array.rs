@@ -1,6 +1,7 @@
 use super::string::js_string_to_string;
+use crate::utils::error::Result;
 use napi::bindgen_prelude::*;
-use napi::{Error, JsNumber, JsObject, JsString, JsUnknown, NapiValue};
+use napi::{JsNumber, JsObject, JsString, JsUnknown, NapiValue};
 pub trait ToRsArray<T, U> {
   fn to_rs_array(self) -> Result<Vec<T>>
   where
@@ -23,8 +24,8 @@ impl ToRsArray<String, JsString> for JsObject {
 }
 fn convert_number_array<T, U>(obj: JsObject) -> Result<Vec<T>>
 where
-  U: TryFrom<JsUnknown, Error = Error> + NapiValue,
-  T: TryFrom<U, Error = Error>,
+  U: TryFrom<JsUnknown, Error = napi::Error> + NapiValue,
+  T: TryFrom<U, Error = napi::Error>,
 {
   (0..obj.get_array_length()?)
     .enumerate()
@@ -110,6 +111,15 @@ impl ToJsArray for Vec<i32> {
     Ok(js_array)
   }
 }
+impl ToJsArray for Vec<i64> {
+  fn to_js_array(self, env: &Env) -> Result<JsObject> {
+    let mut js_array = env.create_array_with_length(self.len())?;
+    let _ = self.into_iter().enumerate().try_for_each(|(index, item)| {
+      js_array.set_element(index as u32, env.create_bigint_from_i64(item)?)
+    });
+    Ok(js_array)
+  }
+}
 impl ToJsArray for Vec<i16> {
   fn to_js_array(self, env: &Env) -> Result<JsObject> {
     let mut js_array = env.create_array_with_length(self.len())?;
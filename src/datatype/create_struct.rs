@@ -1,12 +1,17 @@
-use super::string::{string_to_c_string, string_to_c_w_string};
 use crate::define::*;
+use crate::utils::arena::{
+  alloc_c_string, alloc_c_w_string, alloc_encoded_string, alloc_layout, alloc_vec, Arena,
+};
+use crate::utils::dataprocess::{create_closure, get_func_desc};
+use crate::utils::error::Result;
 use crate::utils::{
   calculate_struct_size, get_array_desc, get_ffi_tag, get_js_external_wrap_data, get_size_align,
+  get_struct_item_type,
 };
 use crate::RefDataType;
 use indexmap::IndexMap;
-use napi::{Env, Result};
-use std::alloc::{alloc, Layout};
+use napi::Env;
+use std::alloc::Layout;
 use std::ffi::{
   c_char, c_double, c_float, c_int, c_longlong, c_short, c_uchar, c_ulonglong, c_void,
 };
@@ -21,15 +26,16 @@ pub unsafe fn generate_c_struct(
   struct_type: &IndexMap<String, RsArgsValue>,
   struct_val: IndexMap<String, RsArgsValue>,
   initial_ptr: Option<*mut c_void>,
+  arena: Option<&Arena>,
 ) -> Result<*mut c_void> {
   let ptr = if initial_ptr.is_none() {
-    let (size, align) = calculate_struct_size(&struct_type);
+    let (size, align) = calculate_struct_size(&struct_type)?;
     let layout = if size > 0 {
       Layout::from_size_align(size, align).unwrap()
     } else {
       Layout::new::<i32>()
     };
-    alloc(layout) as *mut c_void
+    alloc_layout(arena, layout)
   } else {
     initial_ptr.unwrap()
   };
@@ -113,9 +119,7 @@ pub unsafe fn generate_c_struct(
         let (size, align) = get_size_align::<*mut c_void>();
         let padding = (align - (offset % align)) % align;
         field_ptr = field_ptr.offset(padding as isize);
-        let c_string = string_to_c_string(str);
-        (field_ptr as *mut *const c_char).write(c_string.as_ptr());
-        std::mem::forget(c_string);
+        (field_ptr as *mut *const c_char).write(alloc_c_string(arena, str)?);
         offset += size + padding;
         size
       }
@@ -123,9 +127,15 @@ pub unsafe fn generate_c_struct(
         let (size, align) = get_size_align::<*mut c_void>();
         let padding = (align - (offset % align)) % align;
         field_ptr = field_ptr.offset(padding as isize);
-        let c_string = string_to_c_w_string(str);
-        (field_ptr as *mut *const WideChar).write(c_string.as_ptr());
-        std::mem::forget(c_string);
+        (field_ptr as *mut *const WideChar).write(alloc_c_w_string(arena, str)?);
+        offset += size + padding;
+        size
+      }
+      RsArgsValue::EncodedString(str) => {
+        let (size, align) = get_size_align::<*mut c_void>();
+        let padding = (align - (offset % align)) % align;
+        field_ptr = field_ptr.add(padding);
+        (field_ptr as *mut *const c_void).write(alloc_encoded_string(arena, str));
         offset += size + padding;
         size
       }
@@ -147,7 +157,7 @@ pub unsafe fn generate_c_struct(
       }
       RsArgsValue::Object(mut obj_value) => {
         if let FFITypeTag::Array | FFITypeTag::StackArray = get_ffi_tag(&obj_value) {
-          let array_desc = get_array_desc(&mut obj_value);
+          let array_desc = get_array_desc(&mut obj_value)?;
           let array_value = get_array_value(&mut obj_value).unwrap();
           let FFIARRARYDESC {
             array_type,
@@ -176,7 +186,10 @@ pub unsafe fn generate_c_struct(
                   size
                 }
               } else {
-                return Err(FFIError::Panic(format!("error array type {:?}", array_type)).into());
+                return Err(
+                  FFIError::UnsupportedValueType(format!("error array type {:?}", array_type))
+                    .into(),
+                );
               }
             }
             RefDataType::I16Array => {
@@ -193,13 +206,15 @@ pub unsafe fn generate_c_struct(
                   let (size, align) = get_size_align::<*mut c_void>();
                   let padding = (align - (offset % align)) % align;
                   field_ptr = field_ptr.offset(padding as isize);
-                  (field_ptr as *mut *const i16).write(arr.as_ptr());
-                  std::mem::forget(arr);
+                  (field_ptr as *mut *const i16).write(alloc_vec(arena, arr));
                   offset += size + padding;
                   size
                 }
               } else {
-                return Err(FFIError::Panic(format!("error array type {:?}", array_type)).into());
+                return Err(
+                  FFIError::UnsupportedValueType(format!("error array type {:?}", array_type))
+                    .into(),
+                );
               }
             }
             RefDataType::I32Array => {
@@ -216,13 +231,40 @@ pub unsafe fn generate_c_struct(
                   let (size, align) = get_size_align::<*mut c_void>();
                   let padding = (align - (offset % align)) % align;
                   field_ptr = field_ptr.offset(padding as isize);
-                  (field_ptr as *mut *const c_int).write(arr.as_ptr());
-                  std::mem::forget(arr);
+                  (field_ptr as *mut *const c_int).write(alloc_vec(arena, arr));
+                  offset += size + padding;
+                  size
+                }
+              } else {
+                return Err(
+                  FFIError::UnsupportedValueType(format!("error array type {:?}", array_type))
+                    .into(),
+                );
+              }
+            }
+            RefDataType::I64Array => {
+              if let RsArgsValue::I64Array(arr) = array_value {
+                if get_ffi_tag(&obj_value) == FFITypeTag::StackArray {
+                  let (size, align) = get_size_align::<i64>();
+                  let field_size = size * array_len;
+                  let padding = (align - (offset % align)) % align;
+                  field_ptr = field_ptr.add(padding);
+                  std::ptr::copy(arr.as_ptr(), field_ptr as *mut i64, array_len);
+                  offset += field_size + padding;
+                  field_size
+                } else {
+                  let (size, align) = get_size_align::<*mut c_void>();
+                  let padding = (align - (offset % align)) % align;
+                  field_ptr = field_ptr.add(padding);
+                  (field_ptr as *mut *const c_longlong).write(alloc_vec(arena, arr));
                   offset += size + padding;
                   size
                 }
               } else {
-                return Err(FFIError::Panic(format!("error array type {:?}", array_type)).into());
+                return Err(
+                  FFIError::UnsupportedValueType(format!("error array type {:?}", array_type))
+                    .into(),
+                );
               }
             }
             RefDataType::DoubleArray => {
@@ -239,13 +281,15 @@ pub unsafe fn generate_c_struct(
                   let (size, align) = get_size_align::<*mut c_void>();
                   let padding = (align - (offset % align)) % align;
                   field_ptr = field_ptr.offset(padding as isize);
-                  (field_ptr as *mut *const c_double).write(arr.as_ptr());
-                  std::mem::forget(arr);
+                  (field_ptr as *mut *const c_double).write(alloc_vec(arena, arr));
                   offset += size + padding;
                   size
                 }
               } else {
-                return Err(FFIError::Panic(format!("error array type {:?}", array_type)).into());
+                return Err(
+                  FFIError::UnsupportedValueType(format!("error array type {:?}", array_type))
+                    .into(),
+                );
               }
             }
             RefDataType::FloatArray => {
@@ -262,84 +306,81 @@ pub unsafe fn generate_c_struct(
                   let (size, align) = get_size_align::<*mut c_void>();
                   let padding = (align - (offset % align)) % align;
                   field_ptr = field_ptr.offset(padding as isize);
-                  (field_ptr as *mut *const c_float).write(arr.as_ptr());
-                  std::mem::forget(arr);
+                  (field_ptr as *mut *const c_float).write(alloc_vec(arena, arr));
                   offset += size + padding;
                   size
                 }
               } else {
-                return Err(FFIError::Panic(format!("error array type {:?}", array_type)).into());
+                return Err(
+                  FFIError::UnsupportedValueType(format!("error array type {:?}", array_type))
+                    .into(),
+                );
               }
             }
             RefDataType::StringArray => {
-              if let RsArgsValue::StringArray(arr) = array_value {
-                if get_ffi_tag(&obj_value) == FFITypeTag::StackArray {
-                  panic!(
+              if get_ffi_tag(&obj_value) == FFITypeTag::StackArray {
+                return Err(
+                  FFIError::UnsupportedValueType(format!(
                     "write {:?} to static array in struct is unsupported",
                     array_type
-                  )
-                } else {
-                  let (size, align) = get_size_align::<*mut c_void>();
-                  let padding = (align - (offset % align)) % align;
-                  field_ptr = field_ptr.offset(padding as isize);
-                  let c_char_vec: Vec<*const c_char> = arr
-                    .into_iter()
-                    .map(|str| {
-                      let c_string = string_to_c_string(str);
-                      let ptr = c_string.as_ptr();
-                      std::mem::forget(c_string);
-                      ptr
-                    })
-                    .collect();
-                  (field_ptr as *mut *const *const c_char).write(c_char_vec.as_ptr());
-                  std::mem::forget(c_char_vec);
-                  offset += size + padding;
-                  size
-                }
-              } else {
-                return Err(FFIError::Panic(format!("error array type {:?}", array_type)).into());
+                  ))
+                  .into(),
+                );
               }
+              let str_vec: Vec<*const c_void> = match array_value {
+                RsArgsValue::StringArray(arr) => arr
+                  .into_iter()
+                  .map(|str| alloc_c_string(arena, str).map(|ptr| ptr as *const c_void))
+                  .collect::<Result<_>>()?,
+                RsArgsValue::EncodedStringArray(arr) => arr
+                  .into_iter()
+                  .map(|str| alloc_encoded_string(arena, str))
+                  .collect(),
+                _ => {
+                  return Err(
+                    FFIError::UnsupportedValueType(format!("error array type {:?}", array_type))
+                      .into(),
+                  );
+                }
+              };
+              let (size, align) = get_size_align::<*mut c_void>();
+              let padding = (align - (offset % align)) % align;
+              field_ptr = field_ptr.add(padding);
+              (field_ptr as *mut *const *const c_void).write(alloc_vec(arena, str_vec));
+              offset += size + padding;
+              size
             }
             RefDataType::StructArray => {
-              let is_stack_struct =
-                get_ffi_tag(struct_item_type.as_ref().unwrap()) == FFITypeTag::StackStruct;
+              let struct_item_type = get_struct_item_type(&struct_item_type)?;
+              let is_stack_struct = get_ffi_tag(struct_item_type) == FFITypeTag::StackStruct;
               if let RsArgsValue::StructArray(arr) = array_value {
                 if is_stack_struct {
-                  let (size, align) = calculate_struct_size(struct_item_type.as_ref().unwrap());
+                  let (size, align) = calculate_struct_size(struct_item_type)?;
                   let field_size = size * array_len;
-                  arr.into_iter().for_each(|struct_val| {
+                  for struct_val in arr {
                     let padding = (align - (offset % align)) % align;
                     field_ptr = field_ptr.offset(padding as isize);
-                    generate_c_struct(
-                      env,
-                      struct_item_type.as_ref().unwrap(),
-                      struct_val,
-                      Some(field_ptr),
-                    )
-                    .unwrap();
+                    generate_c_struct(env, struct_item_type, struct_val, Some(field_ptr), arena)?;
                     field_ptr = field_ptr.offset(size as isize);
                     offset += size;
-                  });
+                  }
                   field_size
                 } else {
                   let (size, align) = get_size_align::<*mut c_void>();
-                  arr.into_iter().for_each(|struct_val| {
+                  for struct_val in arr {
                     let padding = (align - (offset % align)) % align;
                     field_ptr = field_ptr.offset(padding as isize);
-                    generate_c_struct(
-                      env,
-                      struct_item_type.as_ref().unwrap(),
-                      struct_val,
-                      Some(field_ptr),
-                    )
-                    .unwrap();
+                    generate_c_struct(env, struct_item_type, struct_val, Some(field_ptr), arena)?;
                     field_ptr = field_ptr.offset(1);
                     offset += size;
-                  });
+                  }
                   array_len * std::mem::size_of::<*mut c_void>()
                 }
               } else {
-                return Err(FFIError::Panic(format!("error array type {:?}", array_type)).into());
+                return Err(
+                  FFIError::UnsupportedValueType(format!("error array type {:?}", array_type))
+                    .into(),
+                );
               }
             }
           };
@@ -359,13 +400,16 @@ pub unsafe fn generate_c_struct(
             } else if get_ffi_tag(struct_type) == FFITypeTag::StackStruct {
               struct_type
             } else {
-              return Err(FFIError::Panic(format!("unknown field type {:?}", struct_type)).into());
+              return Err(
+                FFIError::UnsupportedValueType(format!("unknown field type {:?}", struct_type))
+                  .into(),
+              );
             };
 
-            let (size, align) = calculate_struct_size(target_type);
+            let (size, align) = calculate_struct_size(target_type)?;
             let padding = (align - (offset % align)) % align;
             field_ptr = field_ptr.offset(padding as isize);
-            generate_c_struct(env, target_type, obj_value, Some(field_ptr))?;
+            generate_c_struct(env, target_type, obj_value, Some(field_ptr), arena)?;
             offset += size + padding;
             size
           } else {
@@ -373,7 +417,7 @@ pub unsafe fn generate_c_struct(
             let padding = (align - (offset % align)) % align;
             field_ptr = field_ptr.offset(padding as isize);
             if let RsArgsValue::Object(val_type) = struct_type.get(&field).unwrap() {
-              let start_ptr = generate_c_struct(env, val_type, obj_value, None)?;
+              let start_ptr = generate_c_struct(env, val_type, obj_value, None, arena)?;
               (field_ptr as *mut *const c_void).write(start_ptr);
             }
             offset += size + padding;
@@ -381,11 +425,26 @@ pub unsafe fn generate_c_struct(
           }
         }
       }
-      RsArgsValue::Function(_, _) => panic!("write_data error {:?}", field_val),
+      RsArgsValue::Function(func_desc, js_function) => {
+        let (size, align) = get_size_align::<*mut c_void>();
+        let padding = (align - (offset % align)) % align;
+        field_ptr = field_ptr.add(padding);
+        let need_free = get_func_desc(&func_desc).need_free;
+        let code_ptr = create_closure(env, func_desc, js_function)?;
+        if let (Some(arena), true) = (arena, need_free) {
+          arena.defer_closure(code_ptr);
+        }
+        (field_ptr as *mut *mut c_void).write(*(code_ptr as *mut *mut c_void));
+        offset += size + padding;
+        size
+      }
       RsArgsValue::StringArray(_)
+      | RsArgsValue::EncodedStringArray(_)
       | RsArgsValue::FloatArray(_)
       | RsArgsValue::I16Array(_)
       | RsArgsValue::I32Array(_)
+      | RsArgsValue::I64Array(_)
+      | RsArgsValue::ArrayView(_, _)
       | RsArgsValue::DoubleArray(_)
       | RsArgsValue::StructArray(_)
       | RsArgsValue::U8Array(_, _) => {
@@ -1,8 +1,12 @@
 use super::buffer::*;
 use super::pointer::*;
 use super::restore_struct::create_rs_struct_from_pointer;
-use super::string::{create_c_string_from_ptr, create_c_w_string_from_ptr};
+use super::string::{
+  create_c_string_from_ptr, create_c_w_string_from_ptr, decode_string, decode_string_array,
+  get_string_encoding,
+};
 use crate::define::*;
+use crate::utils::error::Result;
 use crate::utils::{get_array_desc, get_ffi_tag};
 use libc::c_float;
 use napi::Env;
@@ -15,10 +19,10 @@ pub unsafe fn get_rs_value_from_pointer(
   type_desc: &RsArgsValue,
   pointer: *mut c_void,
   need_thread_safe: bool,
-) -> RsArgsValue {
-  match type_desc {
+) -> Result<RsArgsValue> {
+  Ok(match type_desc {
     RsArgsValue::I32(number) => {
-      let data = match (*number).try_into().unwrap() {
+      let data = match (*number).try_into()? {
         BasicDataType::U8 => RsArgsValue::U8(*(pointer as *mut u8)),
         BasicDataType::I16 => RsArgsValue::I16(*(pointer as *mut i16)),
         BasicDataType::I32 => RsArgsValue::I32(*(pointer as *mut i32)),
@@ -35,6 +39,12 @@ pub unsafe fn get_rs_value_from_pointer(
         BasicDataType::WString => {
           RsArgsValue::WString(create_c_w_string_from_ptr(*(pointer as *mut *mut WideChar)))
         }
+        BasicDataType::U16String | BasicDataType::U32String | BasicDataType::Latin1String => {
+          RsArgsValue::String(decode_string(
+            *(pointer as *mut *const c_void),
+            &get_string_encoding(type_desc).unwrap(),
+          )?)
+        }
         BasicDataType::External => RsArgsValue::External(
           env
             .create_external(
@@ -48,16 +58,29 @@ pub unsafe fn get_rs_value_from_pointer(
       data
     }
     RsArgsValue::Object(obj) => {
-      if let FFITypeTag::Array = get_ffi_tag(obj) {
-        let array_desc = get_array_desc(obj);
+      if let FFITypeTag::String = get_ffi_tag(obj) {
+        RsArgsValue::String(decode_string(
+          *(pointer as *mut *const c_void),
+          &get_string_encoding(type_desc).unwrap(),
+        )?)
+      } else if let FFITypeTag::Array = get_ffi_tag(obj) {
+        let array_desc = get_array_desc(obj)?;
         let FFIARRARYDESC {
           array_type,
           array_len,
+          encoding,
           ..
         } = array_desc;
         match array_type {
           RefDataType::StringArray => {
-            let arr = create_array_from_pointer(*(pointer as *mut *mut *mut c_char), array_len);
+            let arr = match encoding {
+              Some(encoding) => decode_string_array(
+                *(pointer as *mut *const *const c_void),
+                array_len,
+                &encoding,
+              )?,
+              None => create_array_from_pointer(*(pointer as *mut *mut *mut c_char), array_len),
+            };
             RsArgsValue::StringArray(arr)
           }
           RefDataType::I16Array => {
@@ -68,6 +91,10 @@ pub unsafe fn get_rs_value_from_pointer(
             let arr = create_array_from_pointer(*(pointer as *mut *mut c_int), array_len);
             RsArgsValue::I32Array(arr)
           }
+          RefDataType::I64Array => {
+            let arr = create_array_from_pointer(*(pointer as *mut *mut i64), array_len);
+            RsArgsValue::I64Array(arr)
+          }
           RefDataType::U8Array => {
             let arr = create_array_from_pointer(*(pointer as *mut *mut c_uchar), array_len);
             get_safe_buffer(env, arr, need_thread_safe)
@@ -81,7 +108,12 @@ pub unsafe fn get_rs_value_from_pointer(
             RsArgsValue::FloatArray(arr)
           }
           RefDataType::StructArray => {
-            panic!("struct array not supported");
+            return Err(
+              FFIError::UnsupportedValueType(
+                "struct array is not supported as callback param".to_string(),
+              )
+              .into(),
+            );
           }
         }
       } else {
@@ -97,10 +129,14 @@ pub unsafe fn get_rs_value_from_pointer(
           },
           obj,
           true,
-        ))
+        )?)
       }
     }
 
-    _ => panic!("get_js_function_call_value{:?}", type_desc),
-  }
+    _ => {
+      return Err(
+        FFIError::UnsupportedValueType(format!("get_js_function_call_value{:?}", type_desc)).into(),
+      )
+    }
+  })
 }
@@ -5,3 +5,5 @@ pub mod function;
 pub mod pointer;
 pub mod restore_struct;
 pub mod string;
+pub mod struct_view;
+pub mod typed_array;
@@ -1,8 +1,15 @@
+use crate::datatype::string::{free_encoded_string, get_string_encoding};
+use crate::finalizer::Destructor;
+use crate::utils::error::Result;
+use crate::utils::instance::with_instance_data;
+use crate::utils::tracking::untrack_allocation;
 use crate::utils::{
-  calculate_struct_size, get_array_desc, get_ffi_tag, get_func_desc, get_size_align,
+  calculate_struct_size, get_array_desc, get_ffi_tag, get_field_size_align, get_func_desc,
+  get_size_align, get_struct_item_type,
 };
 use indexmap::IndexMap;
 use libc::{c_double, c_float, c_int, c_short, c_void, free};
+use napi::Env;
 use std::alloc::{dealloc, Layout};
 use std::ffi::{c_char, c_longlong, c_uchar, c_ulonglong, CStr, CString};
 use widestring::{WideCString, WideChar};
@@ -28,6 +35,7 @@ macro_rules! impl_array_pointer {
 impl_array_pointer!(*mut u8, u8);
 impl_array_pointer!(*mut i16, i16);
 impl_array_pointer!(*mut i32, i32);
+impl_array_pointer!(*mut i64, i64);
 impl_array_pointer!(*mut f64, f64);
 impl_array_pointer!(*mut f32, f32);
 
@@ -47,10 +55,12 @@ where
 }
 
 unsafe fn free_struct_memory(
+  env: &Env,
   ptr: *mut c_void,
   struct_desc: &IndexMap<String, RsArgsValue>,
   ptr_type: PointerType,
-) {
+  dealloc: Option<&Destructor>,
+) -> Result<()> {
   let mut field_ptr = ptr;
   let mut offset = 0;
   let mut field_size = 0;
@@ -59,7 +69,7 @@ unsafe fn free_struct_memory(
       continue;
     }
     if let RsArgsValue::I32(number) = val {
-      let data_type = (*number).try_into().unwrap();
+      let data_type = (*number).try_into()?;
       match data_type {
         BasicDataType::U8 => {
           let (size, align) = get_size_align::<c_uchar>();
@@ -137,8 +147,9 @@ unsafe fn free_struct_memory(
           field_ptr = field_ptr.offset(padding as isize);
           let type_field_ptr = field_ptr as *mut *mut c_char;
           match ptr_type {
-            PointerType::CPointer => free(*type_field_ptr as *mut c_void),
+            PointerType::CPointer => c_free(*type_field_ptr as *mut c_void, dealloc),
             PointerType::RsPointer => {
+              let _ = untrack_allocation(env, *type_field_ptr as *mut c_void);
               let _ = CString::from_raw(*(type_field_ptr as *mut *mut c_char));
             }
           }
@@ -151,14 +162,29 @@ unsafe fn free_struct_memory(
           field_ptr = field_ptr.offset(padding as isize);
           let type_field_ptr = field_ptr as *mut *mut WideChar;
           match ptr_type {
-            PointerType::CPointer => free(*type_field_ptr as *mut c_void),
+            PointerType::CPointer => c_free(*type_field_ptr as *mut c_void, dealloc),
             PointerType::RsPointer => {
+              let _ = untrack_allocation(env, *type_field_ptr as *mut c_void);
               let _ = WideCString::from_raw(*(type_field_ptr as *mut *mut WideChar));
             }
           }
           offset += size + padding;
           field_size = size;
         }
+        BasicDataType::U16String | BasicDataType::U32String | BasicDataType::Latin1String => {
+          let (size, align) = get_size_align::<*const c_void>();
+          let padding = (align - (offset % align)) % align;
+          field_ptr = field_ptr.add(padding);
+          free_string_memory(
+            env,
+            *(field_ptr as *mut *mut c_void),
+            val,
+            ptr_type,
+            dealloc,
+          );
+          offset += size + padding;
+          field_size = size;
+        }
         BasicDataType::External => {
           let (size, align) = get_size_align::<*const c_void>();
           let padding = (align - (offset % align)) % align;
@@ -170,13 +196,28 @@ unsafe fn free_struct_memory(
     }
     if let RsArgsValue::Object(obj) = val {
       match get_ffi_tag(&obj) {
+        FFITypeTag::String => {
+          let (size, align) = get_size_align::<*const c_void>();
+          let padding = (align - (offset % align)) % align;
+          field_ptr = field_ptr.add(padding);
+          free_string_memory(
+            env,
+            *(field_ptr as *mut *mut c_void),
+            val,
+            ptr_type,
+            dealloc,
+          );
+          offset += size + padding;
+          field_size = size;
+        }
         FFITypeTag::Array | FFITypeTag::StackArray => {
-          let array_desc = get_array_desc(&obj);
+          let array_desc = get_array_desc(&obj)?;
           // array
           let FFIARRARYDESC {
             array_type,
             array_len,
             struct_item_type,
+            encoding,
             ..
           } = array_desc;
           let dynamic_array = get_ffi_tag(&obj) == FFITypeTag::Array;
@@ -186,7 +227,7 @@ unsafe fn free_struct_memory(
               let padding = (align - (offset % align)) % align;
               field_ptr = field_ptr.offset(padding as isize);
               if dynamic_array {
-                free_dynamic_string_array(field_ptr, array_len);
+                free_dynamic_string_array(env, field_ptr, array_len, dealloc, encoding.as_ref());
               }
               offset += size + padding;
               field_size = size;
@@ -201,7 +242,7 @@ unsafe fn free_struct_memory(
               let padding = (align - (offset % align)) % align;
               field_ptr = field_ptr.offset(padding as isize);
               if dynamic_array {
-                free_dynamic_array::<f64>(field_ptr, array_len);
+                free_dynamic_array::<f64>(env, field_ptr, array_len, dealloc);
               }
               offset += size + padding;
               field_size = size;
@@ -216,7 +257,7 @@ unsafe fn free_struct_memory(
               let padding = (align - (offset % align)) % align;
               field_ptr = field_ptr.offset(padding as isize);
               if dynamic_array {
-                free_dynamic_array::<f32>(field_ptr, array_len);
+                free_dynamic_array::<f32>(env, field_ptr, array_len, dealloc);
               }
               offset += size + padding;
               field_size = size;
@@ -231,7 +272,7 @@ unsafe fn free_struct_memory(
               let padding = (align - (offset % align)) % align;
               field_ptr = field_ptr.offset(padding as isize);
               if dynamic_array {
-                free_dynamic_array::<i16>(field_ptr, array_len);
+                free_dynamic_array::<i16>(env, field_ptr, array_len, dealloc);
               }
               offset += size + padding;
               field_size = size;
@@ -246,7 +287,22 @@ unsafe fn free_struct_memory(
               let padding = (align - (offset % align)) % align;
               field_ptr = field_ptr.offset(padding as isize);
               if dynamic_array {
-                free_dynamic_array::<i32>(field_ptr, array_len);
+                free_dynamic_array::<i32>(env, field_ptr, array_len, dealloc);
+              }
+              offset += size + padding;
+              field_size = size;
+            }
+            RefDataType::I64Array => {
+              let (size, align) = if dynamic_array {
+                get_size_align::<*const c_void>()
+              } else {
+                let (size, align) = get_size_align::<c_longlong>();
+                (size * array_len, align)
+              };
+              let padding = (align - (offset % align)) % align;
+              field_ptr = field_ptr.add(padding);
+              if dynamic_array {
+                free_dynamic_array::<i64>(env, field_ptr, array_len, dealloc);
               }
               offset += size + padding;
               field_size = size;
@@ -255,19 +311,21 @@ unsafe fn free_struct_memory(
               let (size, align) = if dynamic_array {
                 get_size_align::<*const c_void>()
               } else {
-                let (size, align) = calculate_struct_size(&struct_item_type.as_ref().unwrap());
+                let (size, align) =
+                  calculate_struct_size(get_struct_item_type(&struct_item_type)?)?;
                 (size * array_len, align)
               };
               let padding = (align - (offset % align)) % align;
               field_ptr = field_ptr.offset(padding as isize);
               if dynamic_array {
                 // need to review
-                let (size, _) = calculate_struct_size(&struct_item_type.as_ref().unwrap());
+                let struct_item_type = get_struct_item_type(&struct_item_type)?;
+                let (size, _) = calculate_struct_size(struct_item_type)?;
                 let mut target_ptr = *(field_ptr as *mut *mut c_void);
-                (0..array_len).for_each(|_| {
-                  free_struct_memory(target_ptr, struct_item_type.as_ref().unwrap(), ptr_type);
+                for _ in 0..array_len {
+                  free_struct_memory(env, target_ptr, struct_item_type, ptr_type, dealloc)?;
                   target_ptr = target_ptr.offset(size as isize);
-                });
+                }
               }
               offset += size + padding;
               field_size = size;
@@ -285,7 +343,7 @@ unsafe fn free_struct_memory(
                 if let PointerType::CPointer = ptr_type {
                   // only free u8 pointer data when the pointer is allocated in c
                   // rust u8 pointer memory is buffer
-                  free_dynamic_array::<u8>(field_ptr, array_len);
+                  free_dynamic_array::<u8>(env, field_ptr, array_len, dealloc);
                 }
               }
               offset += size + padding;
@@ -294,28 +352,45 @@ unsafe fn free_struct_memory(
           }
         }
         FFITypeTag::Function => {
+          let (size, align) = get_size_align::<*const c_void>();
+          let padding = (align - (offset % align)) % align;
+          field_ptr = field_ptr.add(padding);
           let func_desc = get_func_desc(&obj);
           if func_desc.need_free {
             match ptr_type {
-              PointerType::CPointer => free(*(ptr as *mut *mut c_void)),
-              PointerType::RsPointer => free_closure(ptr),
+              PointerType::CPointer => c_free(*(field_ptr as *mut *mut c_void), dealloc),
+              PointerType::RsPointer => free_closure(env, field_ptr),
             }
           }
+          offset += size + padding;
+          field_size = size;
         }
         _ => {
           // struct
           if get_ffi_tag(obj) == FFITypeTag::StackStruct {
-            let (size, align) = calculate_struct_size(&obj);
+            let (size, align) = calculate_struct_size(&obj)?;
             let padding = (align - (offset % align)) % align;
             field_ptr = field_ptr.offset(padding as isize);
-            free_struct_memory(field_ptr, obj, ptr_type);
+            free_struct_memory(env, field_ptr, obj, ptr_type, dealloc)?;
             offset += size + padding;
             field_size = size;
           } else {
             let (size, align) = get_size_align::<*const c_void>();
             let padding = (align - (offset % align)) % align;
             field_ptr = field_ptr.offset(padding as isize);
-            free_struct_memory(*(field_ptr as *mut *mut c_void), obj, ptr_type);
+            let struct_ptr = *(field_ptr as *mut *mut c_void);
+            free_struct_memory(env, struct_ptr, obj, ptr_type, dealloc)?;
+            if let PointerType::RsPointer = ptr_type {
+              // allocated by generate_c_struct
+              let (struct_size, struct_align) = calculate_struct_size(obj)?;
+              let layout = if struct_size > 0 {
+                Layout::from_size_align(struct_size, struct_align).unwrap()
+              } else {
+                Layout::new::<i32>()
+              };
+              untrack_allocation(env, struct_ptr)?;
+              std::alloc::dealloc(struct_ptr as *mut u8, layout);
+            }
             offset += size + padding;
             field_size = size;
           }
@@ -324,20 +399,66 @@ unsafe fn free_struct_memory(
     }
     field_ptr = field_ptr.offset(field_size as isize) as *mut c_void;
   }
+  Ok(())
+}
+// the memory of a hardened pointer is kept in the quarantine once it has been freed, so freeing it
+// again is reported instead of releasing memory the allocator doesn't own
+unsafe fn check_not_freed(env: &Env, ptr: *mut c_void) -> Result<()> {
+  if with_instance_data(env, |data| data.quarantine.contains(ptr))? {
+    return Err(FFIError::InvalidArg(format!("pointer {:?} has already been freed", ptr)).into());
+  }
+  Ok(())
 }
-pub unsafe fn free_rs_pointer_memory(ptr: *mut c_void, ptr_desc: &RsArgsValue) {
+
+pub unsafe fn free_rs_pointer_memory(
+  env: &Env,
+  ptr: *mut c_void,
+  ptr_desc: &RsArgsValue,
+) -> Result<()> {
+  check_not_freed(env, ptr)?;
+  // closures are untracked by free_closure, a closure without needFree outlives freePointer
+  if !matches!(ptr_desc, RsArgsValue::Object(obj) if get_ffi_tag(obj) == FFITypeTag::Function) {
+    untrack_allocation(env, ptr)?;
+  }
+  // a pointer created by createPointer in hardened mode owns an arena
+  if let Some(arena) = with_instance_data(env, |data| data.hardened_pointers.remove(&ptr))? {
+    let out_of_bounds = arena.check_canaries().is_some();
+    arena.release(env);
+    if out_of_bounds {
+      return Err(
+        FFIError::OutOfBounds(format!(
+          "the memory of pointer {:?} has been written out of its bounds",
+          ptr
+        ))
+        .into(),
+      );
+    }
+    return Ok(());
+  }
   match ptr_desc {
     RsArgsValue::I32(number) => {
-      let basic_data_type = (*number).try_into().unwrap();
+      let basic_data_type = (*number).try_into()?;
       match basic_data_type {
         BasicDataType::String => {
+          untrack_allocation(env, *(ptr as *mut *mut c_void))?;
           let _ = CString::from_raw(*(ptr as *mut *mut c_char));
           free(ptr);
         }
         BasicDataType::WString => {
+          untrack_allocation(env, *(ptr as *mut *mut c_void))?;
           let _ = WideCString::from_raw(*(ptr as *mut *mut WideChar));
           free(ptr);
         }
+        BasicDataType::U16String | BasicDataType::U32String | BasicDataType::Latin1String => {
+          free_string_memory(
+            env,
+            *(ptr as *mut *mut c_void),
+            ptr_desc,
+            PointerType::RsPointer,
+            None,
+          );
+          free(ptr);
+        }
         BasicDataType::U8
         | BasicDataType::I16
         | BasicDataType::I32
@@ -357,13 +478,23 @@ pub unsafe fn free_rs_pointer_memory(ptr: *mut c_void, ptr_desc: &RsArgsValue) {
     RsArgsValue::Object(obj) => {
       let ffi_tag = get_ffi_tag(&obj);
 
-      if let FFITypeTag::Array | FFITypeTag::StackArray = ffi_tag {
-        let array_desc = get_array_desc(&obj);
+      if let FFITypeTag::String = ffi_tag {
+        free_string_memory(
+          env,
+          *(ptr as *mut *mut c_void),
+          ptr_desc,
+          PointerType::RsPointer,
+          None,
+        );
+        free(ptr);
+      } else if let FFITypeTag::Array | FFITypeTag::StackArray = ffi_tag {
+        let array_desc = get_array_desc(&obj)?;
         // array
         let FFIARRARYDESC {
           array_type,
           array_len,
           struct_item_type,
+          encoding,
           ..
         } = array_desc;
         match array_type {
@@ -371,48 +502,56 @@ pub unsafe fn free_rs_pointer_memory(ptr: *mut c_void, ptr_desc: &RsArgsValue) {
             let _ = Box::from_raw(ptr as *mut *mut u8);
           }
           RefDataType::I16Array => {
-            free_dynamic_array::<i16>(ptr, array_len);
+            free_dynamic_array::<i16>(env, ptr, array_len, None);
             free(ptr);
           }
           RefDataType::I32Array => {
-            free_dynamic_array::<i32>(ptr, array_len);
+            free_dynamic_array::<i32>(env, ptr, array_len, None);
+            free(ptr);
+          }
+          RefDataType::I64Array => {
+            free_dynamic_array::<i64>(env, ptr, array_len, None);
             free(ptr);
           }
           RefDataType::DoubleArray => {
-            free_dynamic_array::<f64>(ptr, array_len);
+            free_dynamic_array::<f64>(env, ptr, array_len, None);
             free(ptr);
           }
           RefDataType::FloatArray => {
-            free_dynamic_array::<f32>(ptr, array_len);
+            free_dynamic_array::<f32>(env, ptr, array_len, None);
             free(ptr);
           }
           RefDataType::StringArray => {
-            free_dynamic_string_array(ptr, array_len);
+            free_dynamic_string_array(env, ptr, array_len, None, encoding.as_ref());
             free(ptr);
           }
           RefDataType::StructArray => {
-            let is_stack_struct =
-              get_ffi_tag(struct_item_type.as_ref().unwrap()) == FFITypeTag::StackStruct;
-            let (size, align) = calculate_struct_size(&struct_item_type.as_ref().unwrap());
+            let struct_item_type = get_struct_item_type(&struct_item_type)?;
+            let is_stack_struct = get_ffi_tag(struct_item_type) == FFITypeTag::StackStruct;
+            let (size, align) = calculate_struct_size(struct_item_type)?;
             if size <= 0 {
-              return;
+              return Ok(());
             }
             if is_stack_struct {
               let arr_size = size * array_len;
               let layout = Layout::from_size_align(arr_size, align).unwrap();
+              untrack_allocation(env, *(ptr as *mut *mut c_void))?;
               dealloc(*(ptr as *mut *mut u8), layout);
               free(ptr);
             } else {
               let layout = Layout::from_size_align(size, align).unwrap();
               let mut start_ptr = ptr;
-              (0..array_len).for_each(|_| {
+              for _ in 0..array_len {
                 free_struct_memory(
+                  env,
                   *(start_ptr as *mut *mut c_void),
-                  struct_item_type.as_ref().unwrap(),
+                  struct_item_type,
                   PointerType::RsPointer,
-                );
+                  None,
+                )?;
                 start_ptr = start_ptr.offset(size as isize);
-              });
+              }
+              untrack_allocation(env, *(ptr as *mut *mut c_void))?;
               dealloc(*(ptr as *mut *mut u8), layout);
               free(ptr);
             }
@@ -421,120 +560,261 @@ pub unsafe fn free_rs_pointer_memory(ptr: *mut c_void, ptr_desc: &RsArgsValue) {
       } else if let FFITypeTag::Function = ffi_tag {
         let func_desc = get_func_desc(&obj);
         if func_desc.need_free {
-          free_closure(ptr)
+          free_closure(env, ptr)
         }
       } else {
         let is_stack_struct = get_ffi_tag(&obj) == FFITypeTag::StackStruct;
-        let (size, align) = calculate_struct_size(&obj);
+        let (size, align) = calculate_struct_size(&obj)?;
         if size > 0 {
           let layout = Layout::from_size_align(size, align).unwrap();
           if !is_stack_struct {
-            free_struct_memory(*(ptr as *mut *mut c_void), obj, PointerType::RsPointer);
+            free_struct_memory(
+              env,
+              *(ptr as *mut *mut c_void),
+              obj,
+              PointerType::RsPointer,
+              None,
+            )?;
+            untrack_allocation(env, *(ptr as *mut *mut c_void))?;
             dealloc(*(ptr as *mut *mut u8), layout);
           } else {
-            free_struct_memory(ptr, obj, PointerType::RsPointer);
+            free_struct_memory(env, ptr, obj, PointerType::RsPointer, None)?;
           }
         }
       }
     }
-    _ => panic!("free rust pointer memory error"),
+    _ => {
+      return Err(
+        FFIError::UnsupportedValueType(format!("free rust pointer memory error {:?}", ptr_desc))
+          .into(),
+      )
+    }
   }
+  Ok(())
 }
 
-unsafe fn free_closure(ptr: *mut c_void) {
-  let p = CLOSURE_MAP.as_ref().unwrap().get(&ptr).unwrap();
-  let _ = Box::from_raw(*p as *mut TsFnCallContext);
+// ptr points to the storage of the C function pointer, either the storage owned by the closure
+// itself or a copy of it such as a struct field
+pub unsafe fn free_closure(env: &Env, ptr: *mut c_void) {
+  let func = *(ptr as *mut *mut c_void);
+  // this runs in GC finalizers where an error can't be thrown, the closure is leaked when the
+  // instance data is unavailable
+  let closure = with_instance_data(env, |data| {
+    let code_ptr = data
+      .closure_map
+      .keys()
+      .find(|code_ptr| **code_ptr == ptr || *(**code_ptr as *mut *mut c_void) == func)
+      .copied()?;
+    Some((code_ptr, data.closure_map.remove(&code_ptr)?))
+  });
+  // the closure may already have been released, e.g. when the env is torn down
+  if let Ok(Some((code_ptr, tsfn_call_context))) = closure {
+    let _ = Box::from_raw(tsfn_call_context as *mut TsFnCallContext);
+    let _ = untrack_allocation(env, code_ptr);
+  }
 }
-pub unsafe fn free_c_pointer_memory(ptr: *mut c_void, ptr_desc: &RsArgsValue) {
+/// Free the memory allocated by C for a value of `ptr_desc` stored at `ptr`, the storage itself
+/// included
+pub unsafe fn free_c_pointer_memory(
+  env: &Env,
+  ptr: *mut c_void,
+  ptr_desc: &RsArgsValue,
+  dealloc: Option<&Destructor>,
+) -> Result<()> {
+  // an External is the pointer returned by C itself
+  if let RsArgsValue::I32(number) = ptr_desc {
+    if let BasicDataType::External = (*number).try_into()? {
+      c_free(ptr, dealloc);
+      return Ok(());
+    }
+  }
+  check_not_freed(env, ptr)?;
+  free_c_pointee_memory(env, ptr, ptr_desc, dealloc)?;
+  free_storage(env, ptr, ptr_desc)
+}
+
+// the storage of the other types has been allocated by ffi-rs, e.g. by wrapPointer or
+// createPointer, so it's never released by the deallocator of the library
+unsafe fn free_storage(env: &Env, ptr: *mut c_void, ptr_desc: &RsArgsValue) -> Result<()> {
+  untrack_allocation(env, ptr)?;
+  if let Some(arena) = with_instance_data(env, |data| data.hardened_pointers.remove(&ptr))? {
+    arena.release(env);
+    return Ok(());
+  }
+  let (size, align) = match ptr_desc {
+    // a void pointer is stored as a null pointer
+    RsArgsValue::I32(number) if matches!((*number).try_into()?, BasicDataType::Void) => {
+      get_size_align::<*const c_void>()
+    }
+    _ => get_field_size_align(ptr_desc)?,
+  };
+  if size > 0 {
+    dealloc(
+      ptr as *mut u8,
+      Layout::from_size_align(size, align).unwrap(),
+    );
+  }
+  Ok(())
+}
+
+/// Free the memory allocated by C which the value stored at `ptr` points to, but not the storage,
+/// e.g. the result of a call whose storage has been allocated by ffi-rs
+pub unsafe fn free_c_pointee_memory(
+  env: &Env,
+  ptr: *mut c_void,
+  ptr_desc: &RsArgsValue,
+  dealloc: Option<&Destructor>,
+) -> Result<()> {
   match ptr_desc {
     RsArgsValue::I32(number) => {
-      let basic_data_type = (*number).try_into().unwrap();
-      match basic_data_type {
-        BasicDataType::String => {
-          free(*(ptr as *mut *mut i8) as *mut c_void);
-          free(ptr);
-        }
-        _ => {
-          free(ptr);
-        }
+      let basic_data_type = (*number).try_into()?;
+      if let BasicDataType::String
+      | BasicDataType::U16String
+      | BasicDataType::U32String
+      | BasicDataType::Latin1String = basic_data_type
+      {
+        c_free(*(ptr as *mut *mut c_void), dealloc);
       }
     }
     RsArgsValue::Object(obj) => {
       let ffi_tag = get_ffi_tag(&obj);
-      if let FFITypeTag::Array = ffi_tag {
-        let array_desc = get_array_desc(&obj);
+      if let FFITypeTag::String = ffi_tag {
+        c_free(*(ptr as *mut *mut c_void), dealloc);
+      } else if let FFITypeTag::Array = ffi_tag {
+        let array_desc = get_array_desc(&obj)?;
         // array
         let FFIARRARYDESC {
           array_type,
           array_len,
           struct_item_type,
+          encoding,
           ..
         } = array_desc;
         match array_type {
-          RefDataType::U8Array => {
-            free_dynamic_array::<u8>(ptr, array_len);
-            free(ptr);
-          }
-          RefDataType::FloatArray => {
-            free_dynamic_array::<f32>(ptr, array_len);
-            free(ptr);
-          }
-          RefDataType::I16Array => {
-            free_dynamic_array::<i16>(ptr, array_len);
-            free(ptr);
-          }
-          RefDataType::I32Array => {
-            free_dynamic_array::<i32>(ptr, array_len);
-            free(ptr);
-          }
-          RefDataType::DoubleArray => {
-            free_dynamic_array::<f64>(ptr, array_len);
-            free(ptr);
+          RefDataType::U8Array => free_dynamic_array::<u8>(env, ptr, array_len, dealloc),
+          RefDataType::FloatArray => free_dynamic_array::<f32>(env, ptr, array_len, dealloc),
+          RefDataType::I16Array => free_dynamic_array::<i16>(env, ptr, array_len, dealloc),
+          RefDataType::I32Array => free_dynamic_array::<i32>(env, ptr, array_len, dealloc),
+          RefDataType::I64Array => free_dynamic_array::<i64>(env, ptr, array_len, dealloc),
+          RefDataType::DoubleArray => free_dynamic_array::<f64>(env, ptr, array_len, dealloc),
+          RefDataType::StringArray => {
+            free_dynamic_string_array(env, ptr, array_len, dealloc, encoding.as_ref())
           }
-          RefDataType::StringArray => free_dynamic_string_array(ptr, array_len),
           RefDataType::StructArray => {
+            let struct_item_type = get_struct_item_type(&struct_item_type)?;
             let mut target_ptr = *(ptr as *mut *mut c_void);
-            let (size, _) = calculate_struct_size(struct_item_type.as_ref().unwrap());
-            (0..array_len).for_each(|_| {
+            let (size, _) = calculate_struct_size(struct_item_type)?;
+            for _ in 0..array_len {
               free_struct_memory(
+                env,
                 target_ptr,
-                struct_item_type.as_ref().unwrap(),
+                struct_item_type,
                 PointerType::CPointer,
-              );
+                dealloc,
+              )?;
               target_ptr = target_ptr.offset(size as isize);
-            });
+            }
           }
         }
       } else if let FFITypeTag::Function = ffi_tag {
         let func_desc = get_func_desc(&obj);
         if func_desc.need_free {
-          free(*(ptr as *mut *mut c_void));
-          free(ptr);
+          c_free(*(ptr as *mut *mut c_void), dealloc);
         }
       } else {
         // struct
         let is_stack_struct = get_ffi_tag(&obj) == FFITypeTag::StackStruct;
-        let target_ptr = if is_stack_struct {
-          ptr
+        if is_stack_struct {
+          // a StackStruct is stored inline in ptr, there is no struct pointer to free
+          free_struct_memory(env, ptr, obj, PointerType::CPointer, dealloc)?;
         } else {
-          *(ptr as *mut *mut c_void)
-        };
-        free_struct_memory(target_ptr, obj, PointerType::CPointer);
-        free(*(ptr as *mut *mut c_void));
-        free(ptr);
+          free_struct_memory(
+            env,
+            *(ptr as *mut *mut c_void),
+            obj,
+            PointerType::CPointer,
+            dealloc,
+          )?;
+          c_free(*(ptr as *mut *mut c_void), dealloc);
+        }
       }
     }
-    _ => panic!("free c pointer memory error"),
+    _ => {
+      return Err(
+        FFIError::UnsupportedValueType(format!("free c pointer memory error {:?}", ptr_desc))
+          .into(),
+      )
+    }
   }
+  Ok(())
 }
 
-pub unsafe fn free_dynamic_string_array(ptr: *mut c_void, array_len: usize) {
-  let v = Vec::from_raw_parts(*(ptr as *mut *mut *mut c_char), array_len, array_len);
-  v.into_iter().for_each(|str_ptr| {
-    let _ = CString::from_raw(str_ptr);
-  });
+// memory allocated by C is released by the deallocator of the library when it has its own one
+unsafe fn c_free(ptr: *mut c_void, dealloc: Option<&Destructor>) {
+  match dealloc {
+    Some(dealloc) => dealloc.call(ptr),
+    None => free(ptr),
+  }
 }
 
-pub unsafe fn free_dynamic_array<T>(ptr: *mut c_void, array_len: usize) {
-  let _ = Vec::from_raw_parts(*(ptr as *mut *mut T), array_len, array_len);
+// the strings of an array are encoded when the array type has an encoding
+pub unsafe fn free_dynamic_string_array(
+  env: &Env,
+  ptr: *mut c_void,
+  array_len: usize,
+  dealloc: Option<&Destructor>,
+  encoding: Option<&StringEncoding>,
+) {
+  match dealloc {
+    Some(dealloc) => {
+      let array = *(ptr as *mut *mut *mut c_char);
+      (0..array_len).for_each(|index| dealloc.call(*array.add(index) as *mut c_void));
+      dealloc.call(array as *mut c_void);
+    }
+    None => {
+      let _ = untrack_allocation(env, *(ptr as *mut *mut c_void));
+      let v = Vec::from_raw_parts(*(ptr as *mut *mut *mut c_char), array_len, array_len);
+      v.into_iter().for_each(|str_ptr| {
+        let _ = untrack_allocation(env, str_ptr as *mut c_void);
+        match encoding {
+          Some(encoding) => free_encoded_string(str_ptr as *mut c_void, encoding),
+          None => {
+            let _ = CString::from_raw(str_ptr);
+          }
+        }
+      });
+    }
+  }
+}
+
+// free a string of U16String, U32String, Latin1String or a string type with an encoding
+unsafe fn free_string_memory(
+  env: &Env,
+  ptr: *mut c_void,
+  type_desc: &RsArgsValue,
+  ptr_type: PointerType,
+  dealloc: Option<&Destructor>,
+) {
+  match ptr_type {
+    PointerType::CPointer => c_free(ptr, dealloc),
+    PointerType::RsPointer => {
+      let _ = untrack_allocation(env, ptr);
+      free_encoded_string(ptr, &get_string_encoding(type_desc).unwrap())
+    }
+  }
+}
+
+pub unsafe fn free_dynamic_array<T>(
+  env: &Env,
+  ptr: *mut c_void,
+  array_len: usize,
+  dealloc: Option<&Destructor>,
+) {
+  match dealloc {
+    Some(dealloc) => dealloc.call(*(ptr as *mut *mut c_void)),
+    None => {
+      let _ = untrack_allocation(env, *(ptr as *mut *mut c_void));
+      let _ = Vec::from_raw_parts(*(ptr as *mut *mut T), array_len, array_len);
+    }
+  }
 }
@@ -1,12 +1,16 @@
 use super::array::*;
 use super::buffer::*;
 use super::pointer::*;
-use super::string::{create_c_string_from_ptr, create_c_w_string_from_ptr};
+use super::string::{
+  create_c_string_from_ptr, create_c_w_string_from_ptr, decode_string, decode_string_array,
+  get_string_encoding,
+};
 use crate::define::*;
+use crate::utils::error::Result;
 use crate::utils::*;
 use indexmap::IndexMap;
 use libc::c_float;
-use napi::{Env, JsObject, JsUnknown, Result};
+use napi::{Env, JsObject, JsUnknown};
 use std::ffi::{c_char, c_double, c_int, c_longlong, c_short, c_uchar, c_ulonglong, c_void};
 use widestring::WideChar;
 
@@ -15,7 +19,7 @@ pub unsafe fn create_rs_struct_from_pointer(
   ptr: *mut c_void,
   ret_object: &IndexMap<String, RsArgsValue>,
   need_thread_safe: bool,
-) -> IndexMap<String, RsArgsValue> {
+) -> Result<IndexMap<String, RsArgsValue>> {
   let mut rs_struct: IndexMap<String, RsArgsValue> = IndexMap::new();
   let mut field_ptr = ptr;
   let mut offset = 0;
@@ -26,7 +30,7 @@ pub unsafe fn create_rs_struct_from_pointer(
     }
     if let RsArgsValue::I32(number) = val {
       let field = field.clone();
-      match (*number).try_into().unwrap() {
+      match (*number).try_into()? {
         BasicDataType::U8 => {
           let (size, align) = get_size_align::<c_uchar>();
           let padding = (align - (offset % align)) % align;
@@ -145,6 +149,16 @@ pub unsafe fn create_rs_struct_from_pointer(
           offset += size + padding;
           field_size = size;
         }
+        BasicDataType::U16String | BasicDataType::U32String | BasicDataType::Latin1String => {
+          let (size, align) = get_size_align::<*const c_void>();
+          let padding = (align - (offset % align)) % align;
+          field_ptr = field_ptr.add(padding);
+          let type_field_ptr = field_ptr as *mut *const c_void;
+          let js_string = decode_string(*type_field_ptr, &get_string_encoding(val).unwrap())?;
+          rs_struct.insert(field, RsArgsValue::String(js_string));
+          offset += size + padding;
+          field_size = size;
+        }
         BasicDataType::External => {
           let (size, align) = get_size_align::<*const c_void>();
           let padding = (align - (offset % align)) % align;
@@ -167,13 +181,23 @@ pub unsafe fn create_rs_struct_from_pointer(
       };
     } else if let RsArgsValue::Object(sub_obj_type) = val {
       let field = field.clone();
-      if let FFITypeTag::Array | FFITypeTag::StackArray = get_ffi_tag(sub_obj_type) {
-        let array_desc = get_array_desc(sub_obj_type);
+      if let FFITypeTag::String = get_ffi_tag(sub_obj_type) {
+        let (size, align) = get_size_align::<*const c_void>();
+        let padding = (align - (offset % align)) % align;
+        field_ptr = field_ptr.add(padding);
+        let type_field_ptr = field_ptr as *mut *const c_void;
+        let js_string = decode_string(*type_field_ptr, &get_string_encoding(val).unwrap())?;
+        rs_struct.insert(field, RsArgsValue::String(js_string));
+        offset += size + padding;
+        field_size = size;
+      } else if let FFITypeTag::Array | FFITypeTag::StackArray = get_ffi_tag(sub_obj_type) {
+        let array_desc = get_array_desc(sub_obj_type)?;
         // array
         let FFIARRARYDESC {
           array_type,
           array_len,
           struct_item_type,
+          encoding,
           ..
         } = &array_desc;
         let dynamic_array = get_ffi_tag(sub_obj_type) == FFITypeTag::Array;
@@ -182,8 +206,14 @@ pub unsafe fn create_rs_struct_from_pointer(
             let (size, align) = get_size_align::<*const c_void>();
             let padding = (align - (offset % align)) % align;
             field_ptr = field_ptr.offset(padding as isize);
-            let type_field_ptr = field_ptr as *mut *mut *mut c_char;
-            let arr = create_array_from_pointer(*type_field_ptr, *array_len);
+            let arr = match encoding {
+              Some(encoding) => decode_string_array(
+                *(field_ptr as *mut *const *const c_void),
+                *array_len,
+                encoding,
+              )?,
+              None => create_array_from_pointer(*(field_ptr as *mut *mut *mut c_char), *array_len),
+            };
             rs_struct.insert(field, RsArgsValue::StringArray(arr));
             offset += size + padding;
             field_size = size;
@@ -202,7 +232,7 @@ pub unsafe fn create_rs_struct_from_pointer(
               let arr = create_array_from_pointer(*type_field_ptr, *array_len);
               rs_struct.insert(field, RsArgsValue::DoubleArray(arr));
             } else {
-              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc);
+              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc)?;
               rs_struct.insert(field, arr);
             }
             offset += size + padding;
@@ -222,7 +252,7 @@ pub unsafe fn create_rs_struct_from_pointer(
               let arr = create_array_from_pointer(*type_field_ptr, *array_len);
               rs_struct.insert(field, RsArgsValue::FloatArray(arr));
             } else {
-              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc);
+              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc)?;
               rs_struct.insert(field, arr);
             }
             offset += size + padding;
@@ -242,7 +272,7 @@ pub unsafe fn create_rs_struct_from_pointer(
               let arr = create_array_from_pointer(*type_field_ptr, *array_len);
               rs_struct.insert(field, RsArgsValue::I16Array(arr));
             } else {
-              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc);
+              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc)?;
               rs_struct.insert(field, arr);
             }
             offset += size + padding;
@@ -262,17 +292,37 @@ pub unsafe fn create_rs_struct_from_pointer(
               let arr = create_array_from_pointer(*type_field_ptr, *array_len);
               rs_struct.insert(field, RsArgsValue::I32Array(arr));
             } else {
-              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc);
+              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc)?;
+              rs_struct.insert(field, arr);
+            }
+            offset += size + padding;
+            field_size = size;
+          }
+          RefDataType::I64Array => {
+            let (size, align) = if dynamic_array {
+              get_size_align::<*const c_void>()
+            } else {
+              let (size, align) = get_size_align::<c_longlong>();
+              (size * array_len, align)
+            };
+            let padding = (align - (offset % align)) % align;
+            field_ptr = field_ptr.add(padding);
+            if dynamic_array {
+              let type_field_ptr = field_ptr as *mut *mut c_longlong;
+              let arr = create_array_from_pointer(*type_field_ptr, *array_len);
+              rs_struct.insert(field, RsArgsValue::I64Array(arr));
+            } else {
+              let arr = create_static_array_from_pointer(field_ptr, &array_desc)?;
               rs_struct.insert(field, arr);
             }
             offset += size + padding;
             field_size = size;
           }
           RefDataType::StructArray => {
-            let is_stack_struct =
-              get_ffi_tag(struct_item_type.as_ref().unwrap()) == FFITypeTag::StackStruct;
+            let struct_item_type = get_struct_item_type(struct_item_type)?;
+            let is_stack_struct = get_ffi_tag(struct_item_type) == FFITypeTag::StackStruct;
             let (size, align) = if is_stack_struct {
-              calculate_struct_size(struct_item_type.as_ref().unwrap())
+              calculate_struct_size(struct_item_type)?
             } else {
               let (size, align) = get_size_align::<*const c_void>();
               (size, align)
@@ -281,12 +331,8 @@ pub unsafe fn create_rs_struct_from_pointer(
               .map(|_| {
                 let padding = (align - (offset % align)) % align;
                 field_ptr = field_ptr.offset(padding as isize);
-                let rs_struct = create_rs_struct_from_pointer(
-                  env,
-                  field_ptr,
-                  struct_item_type.as_ref().unwrap(),
-                  need_thread_safe,
-                );
+                let rs_struct =
+                  create_rs_struct_from_pointer(env, field_ptr, struct_item_type, need_thread_safe);
                 if is_stack_struct {
                   field_ptr = field_ptr.offset(size as isize);
                 } else {
@@ -295,7 +341,7 @@ pub unsafe fn create_rs_struct_from_pointer(
                 offset += size;
                 rs_struct
               })
-              .collect::<Vec<_>>();
+              .collect::<Result<Vec<_>>>()?;
             field_size = size * *array_len;
             rs_struct.insert(field, RsArgsValue::StructArray(arr));
           }
@@ -313,7 +359,7 @@ pub unsafe fn create_rs_struct_from_pointer(
               let arr = create_array_from_pointer(*type_field_ptr, array_desc.array_len);
               rs_struct.insert(field, get_safe_buffer(env, arr, need_thread_safe));
             } else {
-              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc);
+              let arr = create_static_array_from_pointer(field_ptr as *mut c_void, &array_desc)?;
               if let RsArgsValue::U8Array(_, arr) = arr {
                 rs_struct.insert(field, get_safe_buffer(env, arr.unwrap(), need_thread_safe));
               }
@@ -322,10 +368,28 @@ pub unsafe fn create_rs_struct_from_pointer(
             field_size = size;
           }
         }
+      } else if get_ffi_tag(sub_obj_type) == FFITypeTag::Function {
+        let (size, align) = get_size_align::<*const c_void>();
+        let padding = (align - (offset % align)) % align;
+        field_ptr = field_ptr.add(padding);
+        let type_field_ptr = field_ptr as *mut *mut c_void;
+        rs_struct.insert(
+          field,
+          RsArgsValue::External(
+            env
+              .create_external(
+                *type_field_ptr,
+                Some(std::mem::size_of::<*mut c_void>() as i64),
+              )
+              .unwrap(),
+          ),
+        );
+        offset += size + padding;
+        field_size = size;
       } else {
         // raw object
         if get_ffi_tag(&sub_obj_type) == FFITypeTag::StackStruct {
-          let (size, align) = calculate_struct_size(&sub_obj_type);
+          let (size, align) = calculate_struct_size(&sub_obj_type)?;
           let padding = (align - (offset % align)) % align;
           field_ptr = field_ptr.offset(padding as isize);
           let sub_object = RsArgsValue::Object(create_rs_struct_from_pointer(
@@ -333,7 +397,7 @@ pub unsafe fn create_rs_struct_from_pointer(
             field_ptr,
             sub_obj_type,
             need_thread_safe,
-          ));
+          )?);
           rs_struct.insert(field, sub_object);
           offset += size + padding;
           field_size = size;
@@ -349,7 +413,7 @@ pub unsafe fn create_rs_struct_from_pointer(
               *type_field_ptr,
               sub_obj_type,
               need_thread_safe,
-            )),
+            )?),
           );
           offset += size + padding;
           field_size = size;
@@ -358,7 +422,7 @@ pub unsafe fn create_rs_struct_from_pointer(
     }
     field_ptr = field_ptr.offset(field_size as isize) as *mut c_void;
   }
-  rs_struct
+  Ok(rs_struct)
 }
 
 pub fn create_js_object_from_rs_map(
@@ -385,7 +449,7 @@ pub fn rs_value_to_js_unknown(env: &Env, data: RsArgsValue) -> Result<JsUnknown>
     RsArgsValue::U64(number) => env.create_int64(number as i64)?.into_unknown(),
     RsArgsValue::U32(number) => env.create_uint32(number)?.into_unknown(),
     RsArgsValue::BigInt(number) => {
-      return env.create_bigint_from_i64(number)?.into_unknown();
+      return Ok(env.create_bigint_from_i64(number)?.into_unknown()?);
     }
     RsArgsValue::Boolean(val) => env.get_boolean(val)?.into_unknown(),
     RsArgsValue::String(val) | RsArgsValue::WString(val) => env.create_string(&val)?.into_unknown(),
@@ -399,6 +463,7 @@ pub fn rs_value_to_js_unknown(env: &Env, data: RsArgsValue) -> Result<JsUnknown>
     }
     RsArgsValue::I16Array(val) => val.to_js_array(env)?.into_unknown(),
     RsArgsValue::I32Array(val) => val.to_js_array(env)?.into_unknown(),
+    RsArgsValue::I64Array(val) => val.to_js_array(env)?.into_unknown(),
     RsArgsValue::StringArray(val) => val.to_js_array(env)?.into_unknown(),
     RsArgsValue::DoubleArray(val) => val.to_js_array(env)?.into_unknown(),
     RsArgsValue::StructArray(val) => {
@@ -414,8 +479,21 @@ pub fn rs_value_to_js_unknown(env: &Env, data: RsArgsValue) -> Result<JsUnknown>
     RsArgsValue::Object(obj) => create_js_object_from_rs_map(env, obj)?.into_unknown(),
     RsArgsValue::External(val) => val.into_unknown(),
     RsArgsValue::Void(_) => env.get_undefined()?.into_unknown(),
+    RsArgsValue::EncodedString(_) | RsArgsValue::EncodedStringArray(_) => {
+      return Err(
+        FFIError::UnsupportedValueType("an encoded string is only passed to c".to_string()).into(),
+      );
+    }
+    RsArgsValue::ArrayView(_, _) => {
+      return Err(
+        FFIError::UnsupportedValueType("the memory of a TypedArray is not a js value".to_string())
+          .into(),
+      );
+    }
     RsArgsValue::Function(_, _) | RsArgsValue::Float(_) | RsArgsValue::FloatArray(_) => {
-      return Err(FFIError::Panic(format!("{}", "JsNumber can only be double type")).into());
+      return Err(
+        FFIError::UnsupportedValueType(format!("{}", "JsNumber can only be double type")).into(),
+      );
     }
   };
   Ok(res)
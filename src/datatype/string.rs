@@ -1,6 +1,10 @@
-use napi::{JsString, Result};
+use crate::define::*;
+use crate::utils::error::{Error, Result};
+use crate::utils::get_ffi_tag;
+use napi::JsString;
 use std::ffi::CString;
-use std::ffi::{c_char, CStr};
+use std::ffi::{c_char, c_void, CStr};
+use std::iter::once;
 use widestring::{WideCString, WideChar};
 
 pub fn js_string_to_string(js_string: JsString) -> Result<String> {
@@ -8,14 +12,20 @@ pub fn js_string_to_string(js_string: JsString) -> Result<String> {
   Ok(s)
 }
 
-pub unsafe fn string_to_c_string(s: String) -> CString {
-  let bytes = s.into_bytes();
-  let c_string = CString::from_vec_unchecked(bytes);
-  c_string
+pub fn string_to_c_string(s: String) -> Result<CString> {
+  check_null_character(&s)?;
+  Ok(unsafe { CString::from_vec_unchecked(s.into_bytes()) })
 }
 
-pub fn string_to_c_w_string(s: String) -> WideCString {
-  WideCString::from_str(&s).unwrap()
+pub fn string_to_c_w_string(s: String) -> Result<WideCString> {
+  check_null_character(&s)?;
+  Ok(unsafe { WideCString::from_str_unchecked(&s) })
+}
+
+/// The utf8 bytes of a StringWithLength with a null terminator which isn't part of its length, null
+/// characters are kept since c receives the length
+pub fn string_to_bytes_with_length(s: String) -> EncodedString {
+  EncodedString::U8(s.into_bytes().into_iter().chain(once(0)).collect())
 }
 
 pub unsafe fn create_c_string_from_ptr(pointer: *mut c_char) -> String {
@@ -27,3 +37,270 @@ pub unsafe fn create_c_w_string_from_ptr(pointer: *mut WideChar) -> String {
     .to_string_lossy()
     .to_string()
 }
+
+fn encoding_error(reason: String) -> Error {
+  FFIError::Encoding(reason).into()
+}
+
+pub fn parse_string_encoding(name: &str) -> StringEncoding {
+  match name.to_ascii_lowercase().as_str() {
+    "utf8" | "utf-8" => StringEncoding::Utf8,
+    "utf16" | "utf-16" => StringEncoding::Utf16,
+    "utf32" | "utf-32" => StringEncoding::Utf32,
+    "latin1" | "iso-8859-1" => StringEncoding::Latin1,
+    _ => StringEncoding::Legacy(name.to_string()),
+  }
+}
+
+fn get_encoding_name(encoding: &StringEncoding) -> &str {
+  match encoding {
+    StringEncoding::Utf8 => "utf8",
+    StringEncoding::Utf16 => "utf16",
+    StringEncoding::Utf32 => "utf32",
+    StringEncoding::Latin1 => "latin1",
+    StringEncoding::Legacy(name) => name,
+  }
+}
+
+/// The encoding of U16String, U32String, Latin1String and of a string type with an encoding, None
+/// for the other types
+pub fn get_string_encoding(type_desc: &RsArgsValue) -> Option<StringEncoding> {
+  match type_desc {
+    RsArgsValue::I32(number) => match BasicDataType::from_repr(*number as usize)? {
+      BasicDataType::U16String => Some(StringEncoding::Utf16),
+      BasicDataType::U32String => Some(StringEncoding::Utf32),
+      BasicDataType::Latin1String => Some(StringEncoding::Latin1),
+      _ => None,
+    },
+    RsArgsValue::Object(obj) if get_ffi_tag(obj) == FFITypeTag::String => {
+      // checked by check_type_desc
+      match obj.get(STRING_ENCODING_TAG) {
+        Some(RsArgsValue::String(name)) => Some(parse_string_encoding(name)),
+        _ => Some(StringEncoding::Utf8),
+      }
+    }
+    _ => None,
+  }
+}
+
+pub fn js_string_to_encoded_string(
+  js_string: JsString,
+  encoding: &StringEncoding,
+) -> Result<EncodedString> {
+  encode_string(&js_string_to_string(js_string)?, encoding)
+}
+
+/// Check that strings can be converted from and to the encoding
+pub fn check_string_encoding(encoding: &StringEncoding) -> Result<()> {
+  match encoding {
+    StringEncoding::Legacy(name) => unsafe {
+      iconv_convert(&[], "UTF-8", name)?;
+      iconv_convert(&[], name, "UTF-8")?;
+      Ok(())
+    },
+    _ => Ok(()),
+  }
+}
+
+// c would stop reading the string at the first null character, StringWithLength keeps them
+fn check_null_character(s: &str) -> Result<()> {
+  match s.chars().position(|c| c == '\0') {
+    Some(index) => Err(encoding_error(format!(
+      "string has a null character at index {} where c would end it",
+      index
+    ))),
+    None => Ok(()),
+  }
+}
+
+/// Encode a string with a null terminator, a character the encoding can't represent is an error
+pub fn encode_string(s: &str, encoding: &StringEncoding) -> Result<EncodedString> {
+  check_null_character(s)?;
+  Ok(match encoding {
+    StringEncoding::Utf8 => EncodedString::U8(s.bytes().chain(once(0)).collect()),
+    StringEncoding::Utf16 => EncodedString::U16(s.encode_utf16().chain(once(0)).collect()),
+    StringEncoding::Utf32 => EncodedString::U32(s.chars().map(u32::from).chain(once(0)).collect()),
+    StringEncoding::Latin1 => EncodedString::U8(
+      s.chars()
+        .enumerate()
+        .map(|(index, c)| {
+          u8::try_from(c).map_err(|_| {
+            encoding_error(format!(
+              "character {:?} at index {} can't be encoded as latin1",
+              c, index
+            ))
+          })
+        })
+        .chain(once(Ok(0)))
+        .collect::<Result<Vec<u8>>>()?,
+    ),
+    StringEncoding::Legacy(name) => {
+      let mut bytes = unsafe { iconv_convert(s.as_bytes(), "UTF-8", name)? };
+      bytes.push(0);
+      EncodedString::U8(bytes)
+    }
+  })
+}
+
+// the code units before the null terminator
+unsafe fn get_code_units<'a, T: Copy + Default + PartialEq>(ptr: *const T) -> &'a [T] {
+  let mut len = 0;
+  while *ptr.add(len) != T::default() {
+    len += 1;
+  }
+  std::slice::from_raw_parts(ptr, len)
+}
+
+/// Read the null terminated string at `ptr`, data which isn't valid in the encoding is an error
+/// instead of being replaced
+pub unsafe fn decode_string(ptr: *const c_void, encoding: &StringEncoding) -> Result<String> {
+  if ptr.is_null() {
+    return Err(encoding_error(format!(
+      "can't read a {} string from a null pointer",
+      get_encoding_name(encoding)
+    )));
+  }
+  match encoding {
+    StringEncoding::Utf8 => {
+      String::from_utf8(get_code_units(ptr as *const u8).to_vec()).map_err(|err| {
+        encoding_error(format!(
+          "invalid utf8 data at byte {}",
+          err.utf8_error().valid_up_to()
+        ))
+      })
+    }
+    StringEncoding::Utf16 => {
+      let mut index = 0;
+      char::decode_utf16(get_code_units(ptr as *const u16).iter().copied())
+        .map(|c| match c {
+          Ok(c) => {
+            index += c.len_utf16();
+            Ok(c)
+          }
+          Err(err) => Err(encoding_error(format!(
+            "unpaired surrogate 0x{:04x} at code unit {}",
+            err.unpaired_surrogate(),
+            index
+          ))),
+        })
+        .collect()
+    }
+    StringEncoding::Utf32 => get_code_units(ptr as *const u32)
+      .iter()
+      .enumerate()
+      .map(|(index, code_point)| {
+        char::from_u32(*code_point).ok_or_else(|| {
+          encoding_error(format!(
+            "invalid code point 0x{:x} at code unit {}",
+            code_point, index
+          ))
+        })
+      })
+      .collect(),
+    StringEncoding::Latin1 => Ok(
+      get_code_units(ptr as *const u8)
+        .iter()
+        .map(|byte| char::from(*byte))
+        .collect(),
+    ),
+    StringEncoding::Legacy(name) => {
+      let bytes = iconv_convert(get_code_units(ptr as *const u8), name, "UTF-8")?;
+      String::from_utf8(bytes)
+        .map_err(|_| encoding_error(format!("iconv returned invalid utf8 data for {}", name)))
+    }
+  }
+}
+
+/// Read `len` strings from an array of string pointers
+pub unsafe fn decode_string_array(
+  ptr: *const *const c_void,
+  len: usize,
+  encoding: &StringEncoding,
+) -> Result<Vec<String>> {
+  (0..len)
+    .map(|index| decode_string(*ptr.add(index), encoding))
+    .collect()
+}
+
+unsafe fn free_code_units<T: Copy + Default + PartialEq>(ptr: *mut T) {
+  let len = get_code_units(ptr).len() + 1;
+  let _ = Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len));
+}
+
+/// Free a string allocated by alloc_encoded_string without an arena
+pub unsafe fn free_encoded_string(ptr: *mut c_void, encoding: &StringEncoding) {
+  if ptr.is_null() {
+    return;
+  }
+  match encoding {
+    StringEncoding::Utf16 => free_code_units(ptr as *mut u16),
+    StringEncoding::Utf32 => free_code_units(ptr as *mut u32),
+    StringEncoding::Utf8 | StringEncoding::Latin1 | StringEncoding::Legacy(_) => {
+      free_code_units(ptr as *mut u8)
+    }
+  }
+}
+
+// bionic only provides iconv from api level 28 and libc doesn't declare it for android
+#[cfg(all(unix, not(target_os = "android")))]
+#[allow(deprecated)]
+unsafe fn iconv_convert(input: &[u8], from: &str, to: &str) -> Result<Vec<u8>> {
+  let (from_code, to_code) = (
+    CString::new(from).map_err(|_| encoding_error(format!("invalid encoding name {:?}", from)))?,
+    CString::new(to).map_err(|_| encoding_error(format!("invalid encoding name {:?}", to)))?,
+  );
+  let cd = libc::iconv_open(to_code.as_ptr(), from_code.as_ptr());
+  if cd as isize == -1 {
+    return Err(encoding_error(format!(
+      "iconv can't convert {} to {}",
+      from, to
+    )));
+  }
+  let mut output = vec![0u8; input.len() * 4 + 16];
+  let mut in_ptr = input.as_ptr() as *mut c_char;
+  let mut in_left = input.len();
+  let mut written = 0;
+  let result = loop {
+    let mut out_ptr = output.as_mut_ptr().add(written) as *mut c_char;
+    let mut out_left = output.len() - written;
+    let res = libc::iconv(cd, &mut in_ptr, &mut in_left, &mut out_ptr, &mut out_left);
+    written = output.len() - out_left;
+    if res != usize::MAX {
+      break Ok(());
+    }
+    let offset = input.len() - in_left;
+    match std::io::Error::last_os_error().raw_os_error() {
+      Some(libc::E2BIG) => output.resize(output.len() * 2, 0),
+      Some(libc::EINVAL) => {
+        break Err(encoding_error(format!(
+          "truncated {} data at byte {}",
+          from, offset
+        )))
+      }
+      _ => {
+        // a character from js is named instead of its utf8 bytes
+        let data = match std::str::from_utf8(&input[offset..]) {
+          Ok(rest) if from == "UTF-8" => format!("character {:?}", rest.chars().next().unwrap()),
+          _ => format!("{} data", from),
+        };
+        break Err(encoding_error(format!(
+          "{} at byte {} can't be converted to {}",
+          data, offset, to
+        )));
+      }
+    }
+  };
+  libc::iconv_close(cd);
+  result.map(|_| {
+    output.truncate(written);
+    output
+  })
+}
+
+#[cfg(any(not(unix), target_os = "android"))]
+unsafe fn iconv_convert(_input: &[u8], from: &str, to: &str) -> Result<Vec<u8>> {
+  Err(encoding_error(format!(
+    "converting {} to {} requires iconv which is only available on unix except android",
+    from, to
+  )))
+}
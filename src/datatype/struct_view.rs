@@ -0,0 +1,77 @@
+use super::create_struct::generate_c_struct;
+use super::restore_struct::{create_rs_struct_from_pointer, rs_value_to_js_unknown};
+use crate::define::*;
+use crate::utils::error::Result;
+use crate::utils::error::{with_error_code, ErrorContext};
+use crate::utils::{calculate_struct_layout, get_ffi_tag, get_params_value_rs_struct};
+use indexmap::IndexMap;
+use napi::bindgen_prelude::This;
+use napi::{Env, JsObject, JsUnknown, Property, PropertyAttributes};
+use std::ffi::c_void;
+
+/// Create an object whose properties read and write the fields of the struct at `ptr` on access.
+/// Fields of a StackStruct field are exposed as a nested view.
+pub unsafe fn create_struct_view(
+  env: &Env,
+  ptr: *mut c_void,
+  struct_type: &IndexMap<String, RsArgsValue>,
+) -> Result<JsObject> {
+  let (fields, _, _) = calculate_struct_layout(struct_type)?;
+  let properties = fields
+    .into_iter()
+    .map(|field| {
+      let field_name = field.name.to_string();
+      let field_ptr = ptr.add(field.offset) as usize;
+      // the field is read and written as the only field of a struct starting at its offset, so
+      // the marshaller's own code converts it
+      let field_type: IndexMap<String, RsArgsValue> =
+        IndexMap::from([(field_name.clone(), field.field_type.clone())]);
+      let nested_struct_type = match field.field_type {
+        RsArgsValue::Object(obj) if get_ffi_tag(obj) == FFITypeTag::StackStruct => {
+          Some(obj.clone())
+        }
+        _ => None,
+      };
+      let getter_name = field_name.clone();
+      let getter_type = field_type.clone();
+      Ok(
+        Property::new(&field_name)?
+          .with_getter_closure(move |env: Env, _: This| -> napi::Result<JsUnknown> {
+            with_error_code(&env, ErrorContext::default(), || {
+              let field_ptr = field_ptr as *mut c_void;
+              if let Some(nested_struct_type) = &nested_struct_type {
+                return Ok(create_struct_view(&env, field_ptr, nested_struct_type)?.into_unknown());
+              }
+              let mut rs_struct =
+                create_rs_struct_from_pointer(&env, field_ptr, &getter_type, false)?;
+              let value = rs_struct.swap_remove(&getter_name).ok_or_else(|| {
+                FFIError::Panic(format!("read struct field {} error", getter_name))
+              })?;
+              rs_value_to_js_unknown(&env, value)
+            })
+          })
+          .with_setter_closure(
+            move |env: Env, _: This, value: JsUnknown| -> napi::Result<()> {
+              with_error_code(&env, ErrorContext::default(), || {
+                let mut value_object = env.create_object()?;
+                value_object.set_named_property(&field_name, value)?;
+                let struct_val = get_params_value_rs_struct(&field_type, &value_object)?;
+                generate_c_struct(
+                  &env,
+                  &field_type,
+                  struct_val,
+                  Some(field_ptr as *mut c_void),
+                  None,
+                )?;
+                Ok(())
+              })
+            },
+          )
+          .with_property_attributes(PropertyAttributes::Enumerable),
+      )
+    })
+    .collect::<Result<Vec<Property>>>()?;
+  let mut view = env.create_object()?;
+  view.define_properties(&properties)?;
+  Ok(view)
+}
@@ -0,0 +1,165 @@
+use crate::define::*;
+use crate::utils::arena::{alloc_vec, Arena};
+use crate::utils::error::Result;
+use napi::{check_status, sys, Env, JsUnknown, NapiRaw, TypedArrayType};
+use std::ffi::c_void;
+
+/// The TypedArray holding the same items as a numeric array type
+pub fn get_typed_array_type(array_type: &RefDataType) -> Option<TypedArrayType> {
+  match array_type {
+    RefDataType::I16Array => Some(TypedArrayType::Int16),
+    RefDataType::I32Array => Some(TypedArrayType::Int32),
+    RefDataType::I64Array => Some(TypedArrayType::BigInt64),
+    RefDataType::FloatArray => Some(TypedArrayType::Float32),
+    RefDataType::DoubleArray => Some(TypedArrayType::Float64),
+    _ => None,
+  }
+}
+
+pub fn get_item_size(typed_array_type: TypedArrayType) -> usize {
+  match typed_array_type {
+    TypedArrayType::Int16 | TypedArrayType::Uint16 => 2,
+    TypedArrayType::Int32 | TypedArrayType::Uint32 | TypedArrayType::Float32 => 4,
+    TypedArrayType::Float64 | TypedArrayType::BigInt64 | TypedArrayType::BigUint64 => 8,
+    _ => 1,
+  }
+}
+
+/// The memory of a TypedArray or DataView argument, C reads and writes it in place
+pub struct ArrayView {
+  /// None for a DataView
+  pub typed_array_type: Option<TypedArrayType>,
+  pub data: *mut c_void,
+  pub byte_length: usize,
+}
+
+/// Get the memory of `value` when it is a TypedArray or a DataView, the data of a detached buffer
+/// is null
+pub unsafe fn get_array_view(env: &Env, value: &JsUnknown) -> Result<Option<ArrayView>> {
+  let mut data = std::ptr::null_mut();
+  let mut arraybuffer = std::ptr::null_mut();
+  let mut byte_offset = 0;
+  if value.is_typedarray()? {
+    let mut typed_array_type = 0;
+    let mut length = 0;
+    check_status!(sys::napi_get_typedarray_info(
+      env.raw(),
+      value.raw(),
+      &mut typed_array_type,
+      &mut length,
+      &mut data,
+      &mut arraybuffer,
+      &mut byte_offset,
+    ))?;
+    let typed_array_type = TypedArrayType::from(typed_array_type);
+    return Ok(Some(ArrayView {
+      typed_array_type: Some(typed_array_type),
+      data,
+      byte_length: length * get_item_size(typed_array_type),
+    }));
+  }
+  if value.is_dataview()? {
+    let mut byte_length = 0;
+    check_status!(sys::napi_get_dataview_info(
+      env.raw(),
+      value.raw(),
+      &mut byte_length,
+      &mut data,
+      &mut arraybuffer,
+      &mut byte_offset,
+    ))?;
+    return Ok(Some(ArrayView {
+      typed_array_type: None,
+      data,
+      byte_length,
+    }));
+  }
+  Ok(None)
+}
+
+unsafe fn copy_items<T: Copy>(
+  arena: Option<&Arena>,
+  data: *mut c_void,
+  byte_length: usize,
+) -> *mut c_void {
+  let len = byte_length / std::mem::size_of::<T>();
+  let mut items = Vec::<T>::with_capacity(len);
+  // the data of a DataView may be unaligned
+  std::ptr::copy_nonoverlapping(
+    data as *const u8,
+    items.as_mut_ptr() as *mut u8,
+    byte_length,
+  );
+  items.set_len(len);
+  alloc_vec(arena, items) as *mut c_void
+}
+
+/// Copy the memory of a view into the same allocation a js array of `array_type` is converted to,
+/// for pointers which own their memory
+pub unsafe fn copy_array_view(
+  arena: Option<&Arena>,
+  array_type: &RefDataType,
+  data: *mut c_void,
+  byte_length: usize,
+) -> Result<*mut c_void> {
+  Ok(match array_type {
+    RefDataType::I16Array => copy_items::<i16>(arena, data, byte_length),
+    RefDataType::I32Array => copy_items::<i32>(arena, data, byte_length),
+    RefDataType::I64Array => copy_items::<i64>(arena, data, byte_length),
+    RefDataType::FloatArray => copy_items::<f32>(arena, data, byte_length),
+    RefDataType::DoubleArray => copy_items::<f64>(arena, data, byte_length),
+    _ => {
+      return Err(
+        FFIError::UnsupportedValueType(format!("{:?} can't be a TypedArray", array_type)).into(),
+      )
+    }
+  })
+}
+
+/// Create a TypedArray of the `len` items of `array_type` at `data`, either over a copy of them or
+/// over the native memory which must stay valid while the TypedArray is used
+pub unsafe fn create_typed_array(
+  env: &Env,
+  array_type: &RefDataType,
+  data: *mut c_void,
+  len: usize,
+  output: TypedArrayOutput,
+) -> Result<JsUnknown> {
+  let typed_array_type = get_typed_array_type(array_type).ok_or_else(|| {
+    FFIError::UnsupportedValueType(format!("{:?} can't be a TypedArray", array_type))
+  })?;
+  let byte_length = len * get_item_size(typed_array_type);
+  let arraybuffer = match output {
+    TypedArrayOutput::Copy => {
+      let mut arraybuffer = env.create_arraybuffer(byte_length)?;
+      if byte_length > 0 {
+        std::ptr::copy_nonoverlapping(
+          data as *const u8,
+          arraybuffer.as_mut().as_mut_ptr(),
+          byte_length,
+        );
+      }
+      arraybuffer
+    }
+    // falls back to a copy on runtimes which forbid external buffers
+    TypedArrayOutput::View => {
+      env.create_arraybuffer_with_borrowed_data(data as *mut u8, byte_length, (), |_, _| {})?
+    }
+  };
+  Ok(
+    arraybuffer
+      .into_raw()
+      .into_typedarray(typed_array_type, len, 0)?
+      .into_unknown(),
+  )
+}
+
+/// The number of items of `typed_array_type` in a view, None when its bytes can't be split into them
+pub fn get_view_len(view: &ArrayView, typed_array_type: TypedArrayType) -> Option<usize> {
+  let item_size = get_item_size(typed_array_type);
+  if view.byte_length.is_multiple_of(item_size) {
+    Some(view.byte_length / item_size)
+  } else {
+    None
+  }
+}
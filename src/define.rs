@@ -1,28 +1,38 @@
+use super::finalizer::{Destructor, Finalizer};
+use super::utils::arena::{Arena, Quarantine};
+use super::utils::error::{Error, Result};
 use super::utils::get_ffi_tag;
+use super::utils::tracking::AllocationTracker;
+use dlopen::symbor::Library;
 use indexmap::IndexMap;
 use libc::c_void;
 use libffi::middle::Closure;
-use libffi_sys::{ffi_cif, ffi_type};
-use napi::bindgen_prelude::{Error, Result, Status as NapiStatus};
+use libffi_sys::{ffi_abi, ffi_cif, ffi_type};
 use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction};
 use napi::{bindgen_prelude::*, JsBufferValue};
-use napi::{Env, JsExternal, JsObject, JsUnknown};
+use napi::{Env, JsExternal, JsObject, JsUnknown, Ref};
 use std::cmp::PartialEq;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::rc::Rc;
+use std::sync::Arc;
 use strum_macros::FromRepr;
 
 type StandardResult<T, E> = std::result::Result<T, E>;
 
 #[derive(Debug)]
 pub enum FFIError {
-  NapiError(Error<NapiStatus>),
+  NapiError(napi::Error),
   UnExpectedError,
   Panic(String),
   LibraryNotFound(String),
   FunctionNotFound(String),
   UnsupportedValueType(String),
+  ArgCountMismatch(String),
+  InvalidArg(String),
+  Fault(String),
+  OutOfBounds(String),
+  Encoding(String),
 }
 impl AsRef<str> for FFIError {
   fn as_ref(&self) -> &str {
@@ -32,19 +42,47 @@ impl AsRef<str> for FFIError {
       FFIError::Panic(desc) => desc,
       FFIError::LibraryNotFound(desc) | FFIError::FunctionNotFound(desc) => desc,
       FFIError::UnsupportedValueType(desc) => desc,
+      FFIError::ArgCountMismatch(desc) | FFIError::InvalidArg(desc) | FFIError::Fault(desc) => desc,
+      FFIError::OutOfBounds(desc) => desc,
+      FFIError::Encoding(desc) => desc,
+    }
+  }
+}
+impl FFIError {
+  /// Stable `code` of the js error
+  pub fn code(&self) -> &'static str {
+    match self {
+      FFIError::NapiError(_) => "ERR_FFI_NAPI",
+      FFIError::UnExpectedError => "ERR_FFI_UNEXPECTED",
+      FFIError::Panic(_) => "ERR_FFI_INTERNAL",
+      FFIError::LibraryNotFound(_) => "ERR_FFI_LIBRARY_NOT_FOUND",
+      FFIError::FunctionNotFound(_) => "ERR_FFI_SYMBOL_NOT_FOUND",
+      FFIError::UnsupportedValueType(_) => "ERR_FFI_TYPE",
+      FFIError::ArgCountMismatch(_) => "ERR_FFI_ARG_COUNT",
+      FFIError::InvalidArg(_) => "ERR_FFI_INVALID_ARG",
+      FFIError::Fault(_) => "ERR_FFI_FAULT",
+      FFIError::OutOfBounds(_) => "ERR_FFI_OUT_OF_BOUNDS",
+      FFIError::Encoding(_) => "ERR_FFI_ENCODING",
     }
   }
 }
 impl From<FFIError> for Error {
   fn from(err: FFIError) -> Self {
-    Error::new(napi::Status::Unknown, format!("{}", err.as_ref()))
+    Error::new(
+      napi::Status::Unknown,
+      err.as_ref().to_string(),
+      Some(err.code()),
+    )
   }
 }
 
 pub struct TsFnCallContext<'a> {
-  pub tsfn: ThreadsafeFunction<Vec<RsArgsValue>, ErrorStrategy::Fatal>,
+  // an Err is thrown on the js thread as an uncaught exception
+  pub tsfn: ThreadsafeFunction<Result<Vec<RsArgsValue>>, ErrorStrategy::Fatal>,
   pub lambda: Option<Box<dyn Fn((Vec<*mut c_void>, *mut c_void)) + 'a>>,
   pub closure: Option<Closure<'a>>,
+  // the library this callback has been passed to, keeps it loaded while the callback is alive
+  pub library: Option<Arc<Library>>,
 }
 
 #[derive(Clone)]
@@ -61,7 +99,7 @@ where
   K: From<String> + Eq + Hash,
   V: FromNapiValue,
 {
-  unsafe fn from_napi_value(env: sys::napi_env, napi_val: sys::napi_value) -> Result<Self> {
+  unsafe fn from_napi_value(env: sys::napi_env, napi_val: sys::napi_value) -> napi::Result<Self> {
     let obj = unsafe { JsObject::from_napi_value(env, napi_val)? };
     let mut map = IndexMap::new();
     for key in JsObject::keys(&obj)?.into_iter() {
@@ -78,7 +116,7 @@ where
   K: AsRef<str>,
   V: ToNapiValue,
 {
-  unsafe fn to_napi_value(raw_env: sys::napi_env, val: Self) -> Result<sys::napi_value> {
+  unsafe fn to_napi_value(raw_env: sys::napi_env, val: Self) -> napi::Result<sys::napi_value> {
     let env = Env::from(raw_env);
     let mut obj = env.create_object()?;
     let map = val.0;
@@ -94,6 +132,46 @@ pub struct FFIARRARYDESC {
   pub array_type: RefDataType,
   pub array_len: usize,
   pub struct_item_type: Option<IndexMap<String, RsArgsValue>>,
+  pub typed_array: Option<TypedArrayOutput>,
+  pub encoding: Option<StringEncoding>,
+}
+
+/// How a numeric array is returned as a TypedArray
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypedArrayOutput {
+  /// a TypedArray owning a copy of the items
+  Copy,
+  /// a TypedArray over the native memory
+  View,
+}
+
+/// The encoding of a string in native memory, String and WString aren't described by one since they
+/// keep converting invalid data lossily
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringEncoding {
+  Utf8,
+  /// char16_t whatever the size of wchar_t is
+  Utf16,
+  /// char32_t
+  Utf32,
+  Latin1,
+  /// a byte oriented legacy encoding like GBK or Shift_JIS which is converted by iconv
+  Legacy(String),
+}
+
+/// The code units of an encoded string, the null terminator included
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodedString {
+  U8(Vec<u8>),
+  U16(Vec<u16>),
+  U32(Vec<u32>),
+}
+
+/// The memory of a StringWithLength param, its fields are passed as two arguments
+#[repr(C)]
+pub struct CStringWithLength {
+  pub data: *const c_void,
+  pub len: usize,
 }
 
 pub struct FFIFUNCDESC {
@@ -124,6 +202,11 @@ pub enum DataType {
   StructArray = 18,
   I16Array = 19,
   U32 = 20,
+  I64Array = 21,
+  U16String = 22,
+  U32String = 23,
+  Latin1String = 24,
+  StringWithLength = 25,
 }
 #[derive(Debug, FromRepr)]
 pub enum BasicDataType {
@@ -141,6 +224,9 @@ pub enum BasicDataType {
   BigInt = 16,
   I16 = 17,
   U32 = 20,
+  U16String = 22,
+  U32String = 23,
+  Latin1String = 24,
 }
 
 #[derive(Debug, FromRepr)]
@@ -152,6 +238,7 @@ pub enum RefDataType {
   FloatArray = 13,
   StructArray = 18,
   I16Array = 19,
+  I64Array = 21,
 }
 
 impl TryFrom<i32> for DataType {
@@ -172,6 +259,12 @@ impl TryFrom<i32> for BasicDataType {
         "In the latest ffi-rs version, please use ffi-rs.arrayConstrutor to describe array type"
       )));
     }
+    if value == DataType::StringWithLength as i32 {
+      return Err(FFIError::UnsupportedValueType(
+        "StringWithLength is passed as two arguments, it can only be in the paramsType of a call"
+          .to_string(),
+      ));
+    }
     BasicDataType::from_repr(value as usize).ok_or(FFIError::UnsupportedValueType(format!(
       "Invalid BasicDataType value: {}",
       value
@@ -190,16 +283,19 @@ impl TryFrom<i32> for RefDataType {
 use libffi::middle::Type;
 
 pub trait RsArgsTrait {
-  fn to_ffi_type(&self) -> Type;
+  fn to_ffi_type(&self) -> Result<Type>;
 }
 impl RsArgsTrait for RsArgsValue {
-  fn to_ffi_type(&self) -> Type {
+  fn to_ffi_type(&self) -> Result<Type> {
     match self {
       RsArgsValue::I32(number) => {
-        let data_type = (*number).try_into().unwrap();
-        match data_type {
+        let data_type = (*number).try_into()?;
+        Ok(match data_type {
           BasicDataType::String => Type::pointer(),
-          BasicDataType::WString => Type::pointer(),
+          BasicDataType::WString
+          | BasicDataType::U16String
+          | BasicDataType::U32String
+          | BasicDataType::Latin1String => Type::pointer(),
           BasicDataType::U8 | BasicDataType::Boolean => Type::u8(),
           BasicDataType::I32 => Type::i32(),
           BasicDataType::I64 | BasicDataType::BigInt => Type::i64(),
@@ -210,23 +306,25 @@ impl RsArgsTrait for RsArgsValue {
           BasicDataType::External => Type::pointer(),
           BasicDataType::I16 => Type::i16(),
           BasicDataType::U32 => Type::u32(),
-        }
+        })
       }
       RsArgsValue::Object(obj_type) => {
         let is_stack_struct = get_ffi_tag(&obj_type) == FFITypeTag::StackStruct;
         if is_stack_struct {
-          Type::structure(
+          Ok(Type::structure(
             obj_type
               .iter()
               .filter(|(k, _)| k.as_str() != FFI_TAG_FIELD)
               .map(|(_, v)| v.to_ffi_type())
-              .collect::<Vec<Type>>(),
-          )
+              .collect::<Result<Vec<Type>>>()?,
+          ))
         } else {
-          Type::pointer()
+          Ok(Type::pointer())
         }
       }
-      _ => panic!("parse function params type err {:?}", self),
+      _ => Err(
+        FFIError::UnsupportedValueType(format!("parse function params type err {:?}", self)).into(),
+      ),
     }
   }
 }
@@ -234,6 +332,8 @@ impl RsArgsTrait for RsArgsValue {
 pub enum RsArgsValue {
   String(String),
   WString(String),
+  // a string of U16String, U32String, Latin1String or String with an encoding
+  EncodedString(EncodedString),
   U8(u8),
   I16(i16),
   I32(i32),
@@ -246,7 +346,9 @@ pub enum RsArgsValue {
   U8Array(Option<JsBufferValue>, Option<Vec<u8>>),
   I16Array(Vec<i16>),
   I32Array(Vec<i32>),
+  I64Array(Vec<i64>),
   StringArray(Vec<String>),
+  EncodedStringArray(Vec<EncodedString>),
   DoubleArray(Vec<f64>),
   FloatArray(Vec<f32>),
   StructArray(Vec<IndexMap<String, RsArgsValue>>),
@@ -255,12 +357,15 @@ pub enum RsArgsValue {
   Void(()),
   Function(IndexMap<String, RsArgsValue>, JsFunction),
   External(JsExternal),
+  // the data and byte length of a TypedArray or DataView whose memory is passed without copying
+  ArrayView(*mut c_void, usize),
 }
 impl Clone for RsArgsValue {
   fn clone(&self) -> Self {
     match self {
       RsArgsValue::String(s) => RsArgsValue::String(s.clone()),
       RsArgsValue::WString(s) => RsArgsValue::WString(s.clone()),
+      RsArgsValue::EncodedString(s) => RsArgsValue::EncodedString(s.clone()),
       RsArgsValue::U8(u) => RsArgsValue::U8(*u),
       RsArgsValue::I16(i) => RsArgsValue::I16(*i),
       RsArgsValue::I32(i) => RsArgsValue::I32(*i),
@@ -272,7 +377,9 @@ impl Clone for RsArgsValue {
       RsArgsValue::Double(d) => RsArgsValue::Double(*d),
       RsArgsValue::I16Array(vec) => RsArgsValue::I16Array(vec.clone()),
       RsArgsValue::I32Array(vec) => RsArgsValue::I32Array(vec.clone()),
+      RsArgsValue::I64Array(vec) => RsArgsValue::I64Array(vec.clone()),
       RsArgsValue::StringArray(vec) => RsArgsValue::StringArray(vec.clone()),
+      RsArgsValue::EncodedStringArray(vec) => RsArgsValue::EncodedStringArray(vec.clone()),
       RsArgsValue::DoubleArray(vec) => RsArgsValue::DoubleArray(vec.clone()),
       RsArgsValue::FloatArray(vec) => RsArgsValue::FloatArray(vec.clone()),
       RsArgsValue::StructArray(vec) => RsArgsValue::StructArray(vec.clone()),
@@ -282,6 +389,7 @@ impl Clone for RsArgsValue {
       RsArgsValue::U8Array(_, _) => panic!("U8Array is buffer cannot be cloned"),
       RsArgsValue::Function(_, _) => panic!("Function cannot be cloned"),
       RsArgsValue::External(_) => panic!("External cannot be cloned"),
+      RsArgsValue::ArrayView(data, len) => RsArgsValue::ArrayView(*data, *len),
     }
   }
 }
@@ -291,6 +399,7 @@ impl PartialEq for RsArgsValue {
     match (self, other) {
       (RsArgsValue::String(a), RsArgsValue::String(b)) => a == b,
       (RsArgsValue::WString(a), RsArgsValue::WString(b)) => a == b,
+      (RsArgsValue::EncodedString(a), RsArgsValue::EncodedString(b)) => a == b,
       (RsArgsValue::U8(a), RsArgsValue::U8(b)) => a == b,
       (RsArgsValue::I32(a), RsArgsValue::I32(b)) => a == b,
       (RsArgsValue::I64(a), RsArgsValue::I64(b)) => a == b,
@@ -301,7 +410,9 @@ impl PartialEq for RsArgsValue {
       (RsArgsValue::Double(a), RsArgsValue::Double(b)) => a == b,
       (RsArgsValue::I16Array(a), RsArgsValue::I16Array(b)) => a == b,
       (RsArgsValue::I32Array(a), RsArgsValue::I32Array(b)) => a == b,
+      (RsArgsValue::I64Array(a), RsArgsValue::I64Array(b)) => a == b,
       (RsArgsValue::StringArray(a), RsArgsValue::StringArray(b)) => a == b,
+      (RsArgsValue::EncodedStringArray(a), RsArgsValue::EncodedStringArray(b)) => a == b,
       (RsArgsValue::DoubleArray(a), RsArgsValue::DoubleArray(b)) => a == b,
       (RsArgsValue::FloatArray(a), RsArgsValue::FloatArray(b)) => a == b,
       (RsArgsValue::Object(a), RsArgsValue::Object(b)) => a == b,
@@ -324,6 +435,7 @@ impl std::fmt::Debug for RsArgsValue {
     match self {
       RsArgsValue::String(s) => write!(f, "String({})", s),
       RsArgsValue::WString(s) => write!(f, "WString({})", s),
+      RsArgsValue::EncodedString(s) => write!(f, "EncodedString({:?})", s),
       RsArgsValue::U8(i) => write!(f, "U8({})", i),
       RsArgsValue::I16(i) => write!(f, "I16({})", i),
       RsArgsValue::I32(i) => write!(f, "I32({})", i),
@@ -343,7 +455,9 @@ impl std::fmt::Debug for RsArgsValue {
       }
       RsArgsValue::I16Array(arr) => write!(f, "I16Array({:?})", arr),
       RsArgsValue::I32Array(arr) => write!(f, "I32Array({:?})", arr),
+      RsArgsValue::I64Array(arr) => write!(f, "I64Array({:?})", arr),
       RsArgsValue::StringArray(arr) => write!(f, "StringArray({:?})", arr),
+      RsArgsValue::EncodedStringArray(arr) => write!(f, "EncodedStringArray({:?})", arr),
       RsArgsValue::DoubleArray(arr) => write!(f, "DoubleArray({:?})", arr),
       RsArgsValue::FloatArray(arr) => write!(f, "FloatArray({:?})", arr),
       RsArgsValue::StructArray(arr) => write!(f, "StructArray({:?})", arr),
@@ -352,6 +466,7 @@ impl std::fmt::Debug for RsArgsValue {
       RsArgsValue::Void(_) => write!(f, "Void"),
       RsArgsValue::External(_) => write!(f, "JsExternal"),
       RsArgsValue::Function(_, _) => write!(f, "JsFunction"),
+      RsArgsValue::ArrayView(data, len) => write!(f, "ArrayView({:?}, {})", data, len),
     }
   }
 }
@@ -365,22 +480,78 @@ pub struct FFIParams {
   pub errno: Option<bool>,
   pub run_in_new_thread: Option<bool>,
   pub free_result_memory: bool,
+  pub abi: Option<String>,
+  pub guarded: Option<bool>,
+  pub finalizer: Option<JsUnknown>,
+  pub deallocator: Option<JsUnknown>,
+}
+
+#[napi(object)]
+pub struct FuncSignatureParams {
+  pub ret_type: JsUnknown,
+  pub params_type: Vec<JsUnknown>,
+  pub errno: Option<bool>,
+  pub run_in_new_thread: Option<bool>,
+  pub free_result_memory: bool,
+  pub abi: Option<String>,
+  pub guarded: Option<bool>,
+  pub finalizer: Option<JsUnknown>,
+  pub deallocator: Option<JsUnknown>,
+}
+
+/// A parsed FuncSignatureParams, `Library.func` parses it once for all of the calls of a function
+pub struct CallSignature {
+  pub params_type: Rc<Vec<RsArgsValue>>,
+  pub ret_type: RsArgsValue,
+  pub errno: Option<bool>,
+  pub run_in_new_thread: Option<bool>,
+  pub free_result_memory: bool,
+  pub abi: ffi_abi,
+  pub guarded: bool,
+  pub finalizer: Option<Finalizer>,
+  pub deallocator: Option<Destructor>,
+}
+
+#[napi(object)]
+pub struct CallPointerParams {
+  // checked to be an External by callPointer
+  pub func_ptr: JsUnknown,
+  pub ret_type: JsUnknown,
+  pub params_type: Vec<JsUnknown>,
+  pub params_value: Vec<JsUnknown>,
+  pub errno: Option<bool>,
+  pub run_in_new_thread: Option<bool>,
+  pub free_result_memory: bool,
+  pub abi: Option<String>,
+  pub guarded: Option<bool>,
+  pub finalizer: Option<JsUnknown>,
+  pub deallocator: Option<JsUnknown>,
 }
 
 pub struct FFICALLPARAMS {
   pub cif: ffi_cif,
   pub fn_pointer: unsafe extern "C" fn(),
+  pub library: Option<Arc<Library>>,
   pub arg_types: Vec<*mut ffi_type>,
+  pub r_type: Box<ffi_type>,
   pub arg_values_c_void: Vec<*mut c_void>,
   pub ret_type_rs: RsArgsValue,
   pub errno: Option<bool>,
   pub free_result_memory: bool,
-  pub params_type_rs: Rc<Vec<RsArgsValue>>,
+  pub func_name: String,
+  pub guarded: bool,
+  pub finalizer: Option<Finalizer>,
+  pub deallocator: Option<Destructor>,
+  pub arena: Option<Arena>,
+  // the TypedArrays and DataViews whose memory c reads and writes in place on the other thread
+  pub view_refs: Vec<Ref<()>>,
 }
 pub struct BarePointerWrap {
   pub data: *mut c_void,
   pub errno_code: i32,
   pub errno_message: String,
+  // the message of a guarded call which faulted, the error is created on the js thread
+  pub fault: Option<String>,
 }
 unsafe impl Send for FFICALL {}
 unsafe impl Send for BarePointerWrap {}
@@ -399,6 +570,7 @@ impl FFICALL {
 pub struct CreatePointerParams {
   pub params_type: Vec<JsUnknown>,
   pub params_value: Vec<JsUnknown>,
+  pub finalizer: Option<JsUnknown>,
 }
 #[derive(Debug)]
 #[napi]
@@ -412,6 +584,7 @@ pub struct FreePointerParams {
   pub params_type: Vec<JsUnknown>,
   pub params_value: Vec<JsExternal>,
   pub pointer_type: PointerType,
+  pub deallocator: Option<JsUnknown>,
 }
 
 #[napi(object)]
@@ -426,10 +599,33 @@ pub struct OpenParams {
   pub path: String,
 }
 
+#[napi(object)]
+pub struct CloseParams {
+  pub force: Option<bool>,
+}
+
+#[napi(object)]
+pub struct FieldLayout {
+  pub name: String,
+  pub offset: u32,
+  pub size: u32,
+  pub align: u32,
+  pub padding: u32,
+}
+
+#[napi(object)]
+pub struct StructLayout {
+  pub size: u32,
+  pub align: u32,
+  pub fields: Vec<FieldLayout>,
+}
+
 pub const ARRAY_LENGTH_TAG: &str = "length";
 pub const ARRAY_TYPE_TAG: &str = "type";
 pub const ARRAY_STRUCT_ITEM_TYPE_TAG: &str = "structItemType";
 pub const ARRAY_VALUE_TAG: &str = "value";
+pub const ARRAY_TYPED_ARRAY_TAG: &str = "typedArray";
+pub const STRING_ENCODING_TAG: &str = "encoding";
 
 pub const FFI_TAG_FIELD: &str = "ffiTypeTag";
 pub const FUNCTION_FREE_TAG: &str = "needFree";
@@ -437,11 +633,13 @@ pub const FUNCTION_FREE_TAG: &str = "needFree";
 pub const PARAMS_TYPE: &str = "paramsType";
 pub const RET_TYPE: &str = "retType";
 pub const FREE_FUNCTION_TAG: &str = "freeCFuncParamsMemory";
+pub const ABI_TAG: &str = "abi";
 
 #[napi]
 #[derive(PartialEq, Eq)]
 pub enum FFITypeTag {
   Unknown = 0,
+  String = 995,
   StackArray = 996,
   Array = 997,
   Function = 998,
@@ -451,6 +649,7 @@ impl From<FFITypeTag> for i32 {
   fn from(tag: FFITypeTag) -> i32 {
     match tag {
       FFITypeTag::Unknown => 0,
+      FFITypeTag::String => 995,
       FFITypeTag::StackArray => 996,
       FFITypeTag::Array => 997,
       FFITypeTag::Function => 998,
@@ -459,4 +658,24 @@ impl From<FFITypeTag> for i32 {
   }
 }
 
-pub static mut CLOSURE_MAP: Option<HashMap<*mut c_void, *mut c_void>> = None;
+pub struct InstanceData {
+  pub library_map: HashMap<
+    String,
+    (
+      Arc<Library>,
+      HashMap<String, Option<unsafe extern "C" fn()>>,
+    ),
+  >,
+  pub closure_map: HashMap<*mut c_void, *mut c_void>,
+  // the napi data of an External created with a finalizer -> its ExternalFinalizer
+  pub finalizer_map: HashMap<*mut c_void, *mut c_void>,
+  // the arenas of the running withScope callbacks, the innermost one last
+  pub scopes: Vec<Rc<Arena>>,
+  // the live allocations when the tracking is enabled by setAllocationTracking
+  pub allocation_tracker: Option<AllocationTracker>,
+  // whether setMemoryHardening is enabled
+  pub hardened: bool,
+  pub quarantine: Quarantine,
+  // the pointers created by createPointer in hardened mode -> the arena owning their memory
+  pub hardened_pointers: HashMap<*mut c_void, Arena>,
+}
@@ -0,0 +1,288 @@
+// a finalizer is attached to the JsExternal values the pointer api creates, napi's typed External
+// wraps rust data instead of a raw pointer
+#![allow(deprecated)]
+
+use crate::datatype::pointer::{free_c_pointer_memory, free_rs_pointer_memory};
+use crate::define::*;
+use crate::get_symbol;
+use crate::utils::error::{
+  throw_js_uncaught, throw_uncaught, with_error_code, ErrorContext, Result,
+};
+use crate::utils::instance::with_instance_data;
+use crate::utils::{get_js_external_tagged_object, get_js_external_wrap_data};
+use dlopen::symbor::Library;
+use napi::bindgen_prelude::FromNapiValue;
+use napi::{
+  check_status, Env, JsExternal, JsFunction, JsObject, JsString, JsUnknown, NapiRaw, Ref, ValueType,
+};
+use std::ffi::c_void;
+use std::sync::Arc;
+
+/// A native function called with a pointer to release it, e.g. `sqlite3_finalize` or `g_free`
+#[derive(Clone)]
+pub struct Destructor {
+  func: unsafe extern "C" fn(*mut c_void),
+  // the library stays loaded until the destructor is dropped
+  _library: Option<Arc<Library>>,
+}
+
+impl Destructor {
+  /// `value` is either a `{ library, funcName }` pair naming a symbol of an opened library or the
+  /// External of a function pointer
+  pub unsafe fn from_js_value(env: &Env, value: JsUnknown, path: &str) -> Result<Destructor> {
+    match value.get_type()? {
+      ValueType::External => {
+        let func = get_js_external_wrap_data(env, value.cast())?;
+        if func.is_null() {
+          return Err(FFIError::InvalidArg(format!("{} is a null function pointer", path)).into());
+        }
+        Ok(Destructor {
+          func: std::mem::transmute::<*mut c_void, unsafe extern "C" fn(*mut c_void)>(func),
+          _library: None,
+        })
+      }
+      ValueType::Object => {
+        let value: JsObject = value.cast();
+        let get_string = |key: &str| -> Result<String> {
+          match value.get_named_property::<JsUnknown>(key)? {
+            name if name.get_type()? == ValueType::String => {
+              Ok(name.cast::<JsString>().into_utf8()?.into_owned()?)
+            }
+            _ => Err(FFIError::InvalidArg(format!("{}.{} should be a string", path, key)).into()),
+          }
+        };
+        let (library, func) = get_symbol(env, &get_string("library")?, &get_string("funcName")?)?;
+        Ok(Destructor {
+          func: std::mem::transmute::<unsafe extern "C" fn(), unsafe extern "C" fn(*mut c_void)>(
+            func,
+          ),
+          _library: Some(library),
+        })
+      }
+      _ => Err(
+        FFIError::InvalidArg(format!(
+          "{} should be a {{ library, funcName }} pair or a function pointer",
+          path
+        ))
+        .into(),
+      ),
+    }
+  }
+
+  pub unsafe fn call(&self, ptr: *mut c_void) {
+    (self.func)(ptr)
+  }
+}
+
+/// Releases the native memory of an external Buffer or ArrayBuffer once it is garbage collected
+pub enum BufferFinalizer {
+  /// the libc free
+  Free,
+  Destructor(Destructor),
+  /// a js function called with the pointer
+  Callback(Ref<()>),
+}
+
+impl BufferFinalizer {
+  /// `value` is `"free"`, a destructor or a js function
+  pub unsafe fn from_js_value(env: &Env, value: JsUnknown) -> Result<BufferFinalizer> {
+    match value.get_type()? {
+      ValueType::Function => {
+        let callback: JsFunction = value.cast();
+        Ok(BufferFinalizer::Callback(env.create_reference(callback)?))
+      }
+      ValueType::String if value.cast::<JsString>().into_utf8()?.as_str()? == "free" => {
+        Ok(BufferFinalizer::Free)
+      }
+      ValueType::String => Err(
+        FFIError::InvalidArg(
+          "finalizer should be \"free\", a destructor or a function".to_string(),
+        )
+        .into(),
+      ),
+      _ => Ok(BufferFinalizer::Destructor(Destructor::from_js_value(
+        env,
+        value,
+        "finalizer",
+      )?)),
+    }
+  }
+
+  pub unsafe fn call(self, env: Env, ptr: *mut c_void) {
+    match self {
+      BufferFinalizer::Free => libc::free(ptr),
+      BufferFinalizer::Destructor(destructor) => destructor.call(ptr),
+      BufferFinalizer::Callback(mut callback) => {
+        let result = (|| {
+          let js_function: JsFunction = env.get_reference_value(&callback)?;
+          let js_external =
+            env.create_external(ptr, Some(std::mem::size_of::<*mut c_void>() as i64))?;
+          js_function.call(None, &[js_external])?;
+          Ok::<_, napi::Error>(())
+        })();
+        let _ = callback.unref(env);
+        if let Err(err) = result {
+          throw_js_uncaught(&env, err);
+        }
+      }
+    }
+  }
+}
+
+/// How the pointer wrapped by an External is released
+pub enum Finalizer {
+  /// free the memory the same way freePointer does for the type
+  FreePointer(PointerType, RsArgsValue),
+  Destructor(Destructor),
+}
+
+impl Finalizer {
+  /// The same finalizer for a pointer of `ptr_desc`
+  pub fn for_type(&self, ptr_desc: &RsArgsValue) -> Finalizer {
+    match self {
+      Finalizer::FreePointer(pointer_type, _) => {
+        Finalizer::FreePointer(*pointer_type, ptr_desc.clone())
+      }
+      Finalizer::Destructor(destructor) => Finalizer::Destructor(destructor.clone()),
+    }
+  }
+}
+
+/// The finalizer option is either a PointerType, then the memory is freed for `ptr_desc` the way
+/// freePointer does, or a destructor
+pub unsafe fn parse_finalizer(
+  env: &Env,
+  value: JsUnknown,
+  ptr_desc: Option<&RsArgsValue>,
+) -> Result<Finalizer> {
+  if value.get_type()? != ValueType::Number {
+    return Ok(Finalizer::Destructor(Destructor::from_js_value(
+      env,
+      value,
+      "finalizer",
+    )?));
+  }
+  let ptr_desc = ptr_desc.ok_or_else(|| {
+    FFIError::InvalidArg(
+      "finalizer of a returned pointer should be a { library, funcName } pair or a function pointer"
+        .to_string(),
+    )
+  })?;
+  let pointer_type = PointerType::from_napi_value(env.raw(), value.raw())
+    .map_err(|_| FFIError::InvalidArg("finalizer is not a PointerType".to_string()))?;
+  Ok(Finalizer::FreePointer(pointer_type, ptr_desc.clone()))
+}
+
+pub struct ExternalFinalizer {
+  ptr: *mut c_void,
+  // taken when the pointer is disposed
+  finalizer: Option<Finalizer>,
+  tagged_object: *mut c_void,
+}
+
+unsafe fn run_finalizer(env: &Env, ptr: *mut c_void, finalizer: &Finalizer) -> Result<()> {
+  match finalizer {
+    Finalizer::FreePointer(PointerType::CPointer, ptr_desc) => {
+      free_c_pointer_memory(env, ptr, ptr_desc, None)
+    }
+    Finalizer::FreePointer(PointerType::RsPointer, ptr_desc) => {
+      free_rs_pointer_memory(env, ptr, ptr_desc)
+    }
+    Finalizer::Destructor(destructor) => {
+      destructor.call(ptr);
+      Ok(())
+    }
+  }
+}
+
+/// Create an External wrapping `ptr` which is released by `finalizer` when the External is garbage
+/// collected, unless it has been disposed before
+pub unsafe fn create_external_with_finalizer(
+  env: &Env,
+  ptr: *mut c_void,
+  finalizer: Finalizer,
+) -> Result<JsExternal> {
+  let js_external = env.create_external(ptr, Some(std::mem::size_of::<*mut c_void>() as i64))?;
+  let tagged_object = get_js_external_tagged_object(env, &js_external) as *mut c_void;
+  let external_finalizer = Box::into_raw(Box::new(ExternalFinalizer {
+    ptr,
+    finalizer: Some(finalizer),
+    tagged_object,
+  }));
+  check_status!(napi::sys::napi_add_finalizer(
+    env.raw(),
+    js_external.raw(),
+    external_finalizer as *mut c_void,
+    Some(finalize_external),
+    std::ptr::null_mut(),
+    std::ptr::null_mut(),
+  ))?;
+  with_instance_data(env, |data| {
+    data
+      .finalizer_map
+      .insert(tagged_object, external_finalizer as *mut c_void)
+  })?;
+  Ok(js_external)
+}
+
+unsafe extern "C" fn finalize_external(
+  env: napi::sys::napi_env,
+  external_finalizer: *mut c_void,
+  _hint: *mut c_void,
+) {
+  let env = Env::from_raw(env);
+  let ExternalFinalizer {
+    ptr,
+    finalizer,
+    tagged_object,
+  } = *Box::from_raw(external_finalizer as *mut ExternalFinalizer);
+  // the address of a collected External may be reused by a new one before this runs, the pointer
+  // is still finalized when the instance data is unavailable
+  let _ = with_instance_data(&env, |data| {
+    if data.finalizer_map.get(&tagged_object) == Some(&external_finalizer) {
+      data.finalizer_map.remove(&tagged_object);
+    }
+  });
+  if let Some(finalizer) = finalizer {
+    if let Err(err) = run_finalizer(&env, ptr, &finalizer) {
+      throw_uncaught(&env, err);
+    }
+  }
+}
+
+/// Take the finalizer of an External created with one, it won't run when the External is garbage
+/// collected. The External wraps a null pointer afterwards. Returns the wrapped pointer along with
+/// the finalizer, or None when the External has no finalizer or it has already been taken.
+pub unsafe fn take_finalizer(
+  env: &Env,
+  js_external: &JsExternal,
+) -> Result<Option<(*mut c_void, Finalizer)>> {
+  let tagged_object = get_js_external_tagged_object(env, js_external);
+  let external_finalizer = match with_instance_data(env, |data| {
+    data.finalizer_map.remove(&(tagged_object as *mut c_void))
+  })? {
+    Some(external_finalizer) => &mut *(external_finalizer as *mut ExternalFinalizer),
+    None => return Ok(None),
+  };
+  (*tagged_object).object = Some(std::ptr::null_mut());
+  Ok(
+    external_finalizer
+      .finalizer
+      .take()
+      .map(|finalizer| (external_finalizer.ptr, finalizer)),
+  )
+}
+
+/// Release the pointer of an External created with a finalizer now instead of when it is garbage
+/// collected. The External wraps a null pointer afterwards. Returns false when there is nothing to
+/// release, e.g. the External has no finalizer or has already been disposed.
+#[napi]
+unsafe fn dispose(env: Env, ptr: JsExternal) -> napi::Result<bool> {
+  with_error_code(&env, ErrorContext::default(), || {
+    match take_finalizer(&env, &ptr)? {
+      Some((ptr, finalizer)) => run_finalizer(&env, ptr, &finalizer)?,
+      None => return Ok(false),
+    }
+    Ok(true)
+  })
+}
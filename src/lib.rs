@@ -3,102 +3,279 @@ extern crate napi_derive;
 
 mod datatype;
 mod define;
+mod finalizer;
+mod library;
+mod memory;
 mod utils;
-use datatype::pointer::{free_c_pointer_memory, free_rs_pointer_memory};
+use datatype::pointer::{
+  free_c_pointee_memory, free_c_pointer_memory, free_closure, free_rs_pointer_memory,
+};
 use define::*;
-use dlopen::symbor::{Library, Symbol};
+use dlopen::symbor;
+use finalizer::{
+  create_external_with_finalizer, parse_finalizer, take_finalizer, BufferFinalizer, Destructor,
+  Finalizer,
+};
+use indexmap::IndexMap;
 use libffi_sys::ffi_type;
-use libffi_sys::{ffi_abi_FFI_DEFAULT_ABI, ffi_call, ffi_cif, ffi_prep_cif};
-use napi::{Env, JsExternal, JsUnknown, Result};
+use libffi_sys::{ffi_call, ffi_cif, ffi_prep_cif, ffi_status_FFI_OK};
+use library::Library;
+use napi::{
+  check_status, Env, JsExternal, JsFunction, JsObject, JsUnknown, NapiRaw, NapiValue, Ref,
+  ValueType,
+};
 use std::collections::HashMap;
 use std::ffi::c_void;
 use std::rc::Rc;
+use std::sync::Arc;
+use utils::arena::Arena;
 use utils::dataprocess::{
-  get_arg_values, get_js_external_wrap_data, get_js_unknown_from_pointer, get_value_pointer,
-  type_define_to_rs_args,
+  create_closure, get_arg_values, get_call_arg_values, get_ffi_tag, get_js_external_wrap_data,
+  get_js_unknown_from_pointer, get_value_pointer, parse_call_param_type, parse_type_define,
+};
+use utils::error::{add_error_context, to_js_error, with_error_code, Error, ErrorContext, Result};
+use utils::get_array_desc;
+use utils::guard::{check_guard_supported, fault_message, guarded_ffi_call, report_fault};
+use utils::instance::{
+  open_shared_library, pin_library_callbacks, unpin_library_callbacks, with_instance_data,
 };
-use utils::pointer::get_ffi_type;
-
-static mut LIBRARY_MAP: Option<
-  HashMap<
-    String,
-    (
-      Library,
-      HashMap<String, Result<Symbol<unsafe extern "C" fn()>>>,
-    ),
-  >,
-> = None;
+use utils::object_utils::{calculate_struct_layout, get_type_size_align};
+use utils::pointer::{get_ffi_abi, get_ffi_type, get_param_ffi_types};
+use utils::tracking::{with_allocation_site, AllocationSite};
 
 #[napi]
-unsafe fn create_pointer(env: Env, params: CreatePointerParams) -> Result<Vec<JsExternal>> {
+unsafe fn create_pointer(env: Env, params: CreatePointerParams) -> napi::Result<Vec<JsExternal>> {
   let CreatePointerParams {
     params_type,
     params_value,
+    finalizer,
   } = params;
-  let params_type_rs: Rc<Vec<RsArgsValue>> = Rc::new(
-    params_type
+  with_error_code(&env, ErrorContext::default(), || {
+    let params_type_rs: Rc<Vec<RsArgsValue>> = Rc::new(parse_params_type(&env, params_type)?);
+    let scope = with_instance_data(&env, |data| data.scopes.last().cloned())?;
+    if scope.is_some() && finalizer.is_some() {
+      return Err(
+        FFIError::InvalidArg(
+          "finalizer can't be used in withScope, the pointers are released with the scope"
+            .to_string(),
+        )
+        .into(),
+      );
+    }
+    let finalizer = finalizer
+      .map(|finalizer| parse_finalizer(&env, finalizer, params_type_rs.first()))
+      .transpose()?;
+    let finalizers: Vec<_> = params_type_rs
+      .iter()
+      .map(|ptr_desc| {
+        finalizer
+          .as_ref()
+          .map(|finalizer| finalizer.for_type(ptr_desc))
+      })
+      .collect();
+    let arg_values = get_arg_values(&env, Rc::clone(&params_type_rs), params_value)?;
+    let arg_values_c_void = with_allocation_site(
+      &env,
+      || AllocationSite {
+        library: None,
+        func_name: "createPointer".to_string(),
+      },
+      || match scope {
+        None if with_instance_data(&env, |data| data.hardened)? => {
+          get_hardened_value_pointer(&env, &params_type_rs, arg_values)
+        }
+        _ => get_value_pointer(
+          &env,
+          Rc::clone(&params_type_rs),
+          arg_values,
+          scope.as_deref(),
+        ),
+      },
+    )?;
+
+    arg_values_c_void
       .into_iter()
-      .map(|param| type_define_to_rs_args(&env, param).unwrap())
-      .collect(),
-  );
-  let arg_values = get_arg_values(Rc::clone(&params_type_rs), params_value)?;
-  let arg_values_c_void = get_value_pointer(&env, Rc::clone(&params_type_rs), arg_values)?;
+      .zip(finalizers)
+      .map(|(p, finalizer)| match finalizer {
+        Some(finalizer) => create_external_with_finalizer(&env, p, finalizer),
+        None => Ok(env.create_external(p, Some(std::mem::size_of::<*mut c_void>() as i64))?),
+      })
+      .collect()
+  })
+}
+
+// every pointer owns an arena in hardened mode so freePointer can poison its memory
+unsafe fn get_hardened_value_pointer(
+  env: &Env,
+  params_type_rs: &[RsArgsValue],
+  arg_values: Vec<RsArgsValue>,
+) -> Result<Vec<*mut c_void>> {
+  params_type_rs
+    .iter()
+    .zip(arg_values)
+    .map(|(ptr_desc, val)| {
+      let arena = Arena::new(true);
+      let ptr = get_value_pointer(
+        env,
+        Rc::new(vec![ptr_desc.clone()]),
+        vec![val],
+        Some(&arena),
+      )?[0];
+      with_instance_data(env, |data| data.hardened_pointers.insert(ptr, arena))?;
+      Ok(ptr)
+    })
+    .collect()
+}
+
+/// Run `callback` in a scope owning the memory of the pointers createPointer creates while it runs,
+/// the memory is released at once when the callback returns or throws
+#[napi]
+unsafe fn with_scope(env: Env, callback: JsFunction) -> napi::Result<JsUnknown> {
+  with_instance_data(&env, |data| {
+    let hardened = data.hardened;
+    data.scopes.push(Rc::new(Arena::new(hardened)))
+  })?;
+  let result = callback.call_without_args(None);
+  let mut out_of_bounds = false;
+  if let Some(scope) = with_instance_data(&env, |data| data.scopes.pop())? {
+    out_of_bounds = scope.check_canaries().is_some();
+    // createPointer only holds the scope while it runs
+    if let Ok(scope) = Rc::try_unwrap(scope) {
+      scope.release(&env);
+    }
+  }
+  let result = result?;
+  with_error_code(&env, ErrorContext::default(), || {
+    if out_of_bounds {
+      return Err(
+        FFIError::OutOfBounds(
+          "the memory of a pointer created in withScope has been written out of its bounds"
+            .to_string(),
+        )
+        .into(),
+      );
+    }
+    Ok(result)
+  })
+}
+
+unsafe fn parse_params_type(env: &Env, params_type: Vec<JsUnknown>) -> Result<Vec<RsArgsValue>> {
+  params_type
+    .into_iter()
+    .enumerate()
+    .map(|(index, param)| parse_type_define(env, param, &format!("{}[{}]", PARAMS_TYPE, index)))
+    .collect()
+}
 
-  arg_values_c_void
+unsafe fn parse_call_params_type(
+  env: &Env,
+  params_type: Vec<JsUnknown>,
+) -> Result<Vec<RsArgsValue>> {
+  params_type
     .into_iter()
-    .map(|p| env.create_external(p, Some(std::mem::size_of::<*mut c_void>() as i64)))
+    .enumerate()
+    .map(|(index, param)| parse_call_param_type(env, param, &format!("{}[{}]", PARAMS_TYPE, index)))
     .collect()
 }
 
 #[napi]
-unsafe fn free_pointer(env: Env, params: FreePointerParams) {
+unsafe fn free_pointer(env: Env, params: FreePointerParams) -> napi::Result<()> {
   let FreePointerParams {
     params_type,
     params_value,
     pointer_type,
+    deallocator,
   } = params;
-  let params_type_rs: Vec<RsArgsValue> = params_type
-    .into_iter()
-    .map(|param| type_define_to_rs_args(&env, param).unwrap())
-    .collect();
-  params_value
-    .into_iter()
-    .zip(params_type_rs.iter())
-    .for_each(|(js_external, ptr_desc)| {
-      let ptr = get_js_external_wrap_data(&env, js_external).unwrap();
-      match pointer_type {
-        PointerType::CPointer => free_c_pointer_memory(ptr, ptr_desc),
-        PointerType::RsPointer => free_rs_pointer_memory(ptr, ptr_desc),
-      }
-    });
+  with_error_code(&env, ErrorContext::default(), || {
+    let params_type_rs = parse_params_type(&env, params_type)?;
+    let deallocator = parse_deallocator(&env, deallocator)?;
+    if deallocator.is_some() && matches!(pointer_type, PointerType::RsPointer) {
+      return Err(
+        FFIError::InvalidArg(
+          "deallocator can only be used when pointerType is PointerType.CPointer".to_string(),
+        )
+        .into(),
+      );
+    }
+    params_value
+      .into_iter()
+      .zip(params_type_rs.iter())
+      .try_for_each(|(js_external, ptr_desc)| {
+        // the finalizer of the External is cancelled, its memory is released here instead
+        let ptr = match take_finalizer(&env, &js_external)? {
+          Some((ptr, _)) => ptr,
+          None => get_js_external_wrap_data(&env, js_external)?,
+        };
+        // the memory of a pointer created in withScope is released with its scope
+        if with_instance_data(&env, |data| {
+          data.scopes.iter().any(|scope| scope.contains(ptr))
+        })? {
+          return Ok(());
+        }
+        match pointer_type {
+          PointerType::CPointer => free_c_pointer_memory(&env, ptr, ptr_desc, deallocator.as_ref()),
+          PointerType::RsPointer => free_rs_pointer_memory(&env, ptr, ptr_desc),
+        }
+      })
+  })
 }
 
 #[napi]
-unsafe fn is_null_pointer(env: Env, js_external: JsExternal) -> Result<bool> {
+unsafe fn is_null_pointer(env: Env, js_external: JsExternal) -> napi::Result<bool> {
   let ptr = get_js_external_wrap_data(&env, js_external)?;
   Ok(ptr.is_null())
 }
 
 #[napi]
-unsafe fn restore_pointer(env: Env, params: StorePointerParams) -> Result<Vec<JsUnknown>> {
+unsafe fn restore_pointer(env: Env, params: StorePointerParams) -> napi::Result<Vec<JsUnknown>> {
   let StorePointerParams {
     ret_type,
     params_value,
   } = params;
 
-  ret_type
-    .into_iter()
-    .zip(params_value.into_iter())
-    .map(|(ret_type_item, js_external)| {
-      let ptr = get_js_external_wrap_data(&env, js_external)?;
-      let ret_type_rs = type_define_to_rs_args(&env, ret_type_item)?;
-      get_js_unknown_from_pointer(&env, &ret_type_rs, ptr)
-    })
-    .collect()
+  with_error_code(&env, ErrorContext::default(), || {
+    ret_type
+      .into_iter()
+      .zip(params_value.into_iter())
+      .enumerate()
+      .map(|(index, (ret_type_item, js_external))| {
+        let ptr = get_js_external_wrap_data(&env, js_external)?;
+        let ret_type_rs =
+          parse_type_define(&env, ret_type_item, &format!("{}[{}]", RET_TYPE, index))?;
+        get_js_unknown_from_pointer(&env, &ret_type_rs, ptr)
+      })
+      .collect()
+  })
+}
+
+/// Create a view whose properties read and write the fields of a struct in native memory on
+/// access. `ptr` is the same pointer restorePointer takes for the struct type.
+#[napi]
+#[allow(deprecated)]
+unsafe fn create_struct_view(
+  env: Env,
+  ptr: JsExternal,
+  struct_type: JsUnknown,
+) -> napi::Result<JsObject> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let struct_type = parse_struct_type(&env, struct_type)?;
+    let ptr = get_js_external_wrap_data(&env, ptr)?;
+    let struct_ptr = if get_ffi_tag(&struct_type) == FFITypeTag::StackStruct {
+      ptr
+    } else {
+      *(ptr as *mut *mut c_void)
+    };
+    if struct_ptr.is_null() {
+      return Err(
+        FFIError::UnsupportedValueType("createStructView: pointer is null".to_string()).into(),
+      );
+    }
+    datatype::struct_view::create_struct_view(&env, struct_ptr, &struct_type)
+  })
 }
 
 #[napi]
-unsafe fn unwrap_pointer(env: Env, params: Vec<JsExternal>) -> Result<Vec<JsExternal>> {
+unsafe fn unwrap_pointer(env: Env, params: Vec<JsExternal>) -> napi::Result<Vec<JsExternal>> {
   params
     .into_iter()
     .map(|js_external| {
@@ -112,7 +289,7 @@ unsafe fn unwrap_pointer(env: Env, params: Vec<JsExternal>) -> Result<Vec<JsExte
     .collect()
 }
 #[napi]
-unsafe fn wrap_pointer(env: Env, params: Vec<JsExternal>) -> Result<Vec<JsExternal>> {
+unsafe fn wrap_pointer(env: Env, params: Vec<JsExternal>) -> napi::Result<Vec<JsExternal>> {
   params
     .into_iter()
     .map(|js_external| {
@@ -138,11 +315,20 @@ unsafe fn wrap_pointer(env: Env, params: Vec<JsExternal>) -> Result<Vec<JsExtern
 /// region returned by `mmap` must remain mapped until you are done with the
 /// Buffer and call `munmap` yourself).
 ///
+/// An optional `finalizer` hands the ownership of the memory over to the
+/// Buffer: it is `"free"`, a destructor symbol or a JS function called with
+/// the pointer once the Buffer is garbage collected.
+///
 /// On runtimes that forbid external buffers (e.g. Electron), this silently
 /// falls back to copying the data, so the Buffer is still usable but loses
-/// the zero-copy/shared-mutability property.
+/// the zero-copy/shared-mutability property. The finalizer then runs at once.
 #[napi]
-unsafe fn create_external_buffer(env: Env, external: JsExternal, length: i64) -> Result<JsUnknown> {
+unsafe fn create_external_buffer(
+  env: Env,
+  external: JsExternal,
+  length: i64,
+  finalizer: Option<JsUnknown>,
+) -> napi::Result<JsUnknown> {
   let ptr = get_js_external_wrap_data(&env, external)?;
   let len = length as usize;
   if ptr.is_null() {
@@ -150,98 +336,322 @@ unsafe fn create_external_buffer(env: Env, external: JsExternal, length: i64) ->
       "create_external_buffer: pointer is null",
     ));
   }
+  let finalizer = with_error_code(&env, ErrorContext::default(), || {
+    finalizer
+      .map(|finalizer| BufferFinalizer::from_js_value(&env, finalizer))
+      .transpose()
+  })?;
   if len == 0 {
     // A zero-length external buffer is not allowed; hand back an empty buffer.
+    if let Some(finalizer) = finalizer {
+      finalizer.call(env, ptr);
+    }
     return Ok(env.create_buffer(0)?.into_unknown());
   }
-  // Borrow the native memory: a no-op finalizer means ffi-rs will never free
-  // it. The caller owns the memory (e.g. it came from mmap/malloc) and is
+  // Borrow the native memory: without a finalizer ffi-rs will never free it.
+  // The caller owns the memory (e.g. it came from mmap/malloc) and is
   // responsible for keeping it valid while the Buffer is alive.
-  let buf = env.create_buffer_with_borrowed_data(ptr as *mut u8, len, (), |_, _| {})?;
+  let buf = env.create_buffer_with_borrowed_data(
+    ptr as *mut u8,
+    len,
+    finalizer,
+    move |finalizer, env| {
+      if let Some(finalizer) = finalizer {
+        finalizer.call(env, ptr)
+      }
+    },
+  )?;
   Ok(buf.into_unknown())
 }
 
+/// Wrap `length` bytes of native memory into an `ArrayBuffer` without copying,
+/// like `create_external_buffer`. The optional `finalizer` releases the memory
+/// once the ArrayBuffer is garbage collected, without it the caller keeps
+/// owning the memory.
+///
+/// Node-API can't create a SharedArrayBuffer over external memory, share the
+/// ArrayBuffer between threads by passing its pointer instead. On runtimes that
+/// forbid external buffers the data is copied and the finalizer runs at once.
 #[napi]
-unsafe fn open(params: OpenParams) -> Result<()> {
-  let OpenParams { library, path } = params;
-  if LIBRARY_MAP.is_none() {
-    LIBRARY_MAP = Some(HashMap::new());
+#[allow(deprecated)]
+unsafe fn create_external_array_buffer(
+  env: Env,
+  external: JsExternal,
+  length: i64,
+  finalizer: Option<JsUnknown>,
+) -> napi::Result<JsUnknown> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let ptr = get_js_external_wrap_data(&env, external)?;
+    if ptr.is_null() {
+      return Err(
+        FFIError::InvalidArg("createExternalArrayBuffer: pointer is null".to_string()).into(),
+      );
+    }
+    if length < 0 {
+      return Err(
+        FFIError::InvalidArg(format!(
+          "createExternalArrayBuffer: length {} is negative",
+          length
+        ))
+        .into(),
+      );
+    }
+    let finalizer = finalizer
+      .map(|finalizer| BufferFinalizer::from_js_value(&env, finalizer))
+      .transpose()?;
+    let arraybuffer = env.create_arraybuffer_with_borrowed_data(
+      ptr as *mut u8,
+      length as usize,
+      finalizer,
+      move |finalizer, env| {
+        if let Some(finalizer) = finalizer {
+          finalizer.call(env, ptr)
+        }
+      },
+    )?;
+    Ok(arraybuffer.into_raw().into_unknown())
+  })
+}
+
+/// Size of the value described by a type, computed by the same layout code the marshaller uses
+#[napi]
+unsafe fn size_of(env: Env, type_desc: JsUnknown) -> napi::Result<u32> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let type_desc = parse_type_define(&env, type_desc, "type")?;
+    Ok(get_type_size_align(&type_desc)?.0 as u32)
+  })
+}
+
+#[napi]
+unsafe fn align_of(env: Env, type_desc: JsUnknown) -> napi::Result<u32> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let type_desc = parse_type_define(&env, type_desc, "type")?;
+    Ok(get_type_size_align(&type_desc)?.1 as u32)
+  })
+}
+
+unsafe fn parse_struct_type(
+  env: &Env,
+  struct_type: JsUnknown,
+) -> Result<IndexMap<String, RsArgsValue>> {
+  match parse_type_define(env, struct_type, "structType")? {
+    RsArgsValue::Object(obj)
+      if matches!(
+        get_ffi_tag(&obj),
+        FFITypeTag::Unknown | FFITypeTag::StackStruct
+      ) =>
+    {
+      Ok(obj)
+    }
+    _ => {
+      Err(FFIError::UnsupportedValueType("structType should be a struct type".to_string()).into())
+    }
   }
-  let map = LIBRARY_MAP.as_mut().unwrap();
-  if map.get(&library).is_none() {
-    let lib = if path == "" {
-      Library::open_self().unwrap()
-    } else {
-      match Library::open(&path) {
-        Ok(lib) => lib,
-        Err(e) => match e {
-          dlopen::Error::OpeningLibraryError(e) => {
-            let err_msg = e.to_string();
-            if err_msg.contains("is not a valid Win32 application")
-              || err_msg.contains("not a mach-o file")
-              || err_msg.contains("invalid ELF header")
-            {
-              return Err(
-                                    FFIError::Panic(
-                                        format!(
-                                            "Please check whether the library has the same compilation and runtime environment.\n Error detail info: {:?}",
-                                            e
-                                        )
-                                    ).into()
-                                );
-            } else {
-              return Err(FFIError::Panic(e.to_string()).into());
-            }
+}
+
+/// Offset of a field from the start of the struct, nested fields of StackStruct fields are
+/// separated by dots like `header.flags`
+#[napi]
+unsafe fn offset_of(env: Env, struct_type: JsUnknown, field_path: String) -> napi::Result<u32> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let struct_type = parse_struct_type(&env, struct_type)?;
+    let mut struct_type = &struct_type;
+    let mut offset = 0;
+    let mut field_names = field_path.split('.').peekable();
+    while let Some(field_name) = field_names.next() {
+      let (fields, _, _) = calculate_struct_layout(struct_type)?;
+      let field = fields
+        .into_iter()
+        .find(|field| field.name == field_name)
+        .ok_or_else(|| {
+          FFIError::UnsupportedValueType(format!("{} has no field {}", field_path, field_name))
+        })?;
+      offset += field.offset;
+      if field_names.peek().is_some() {
+        // only the fields of a StackStruct are stored in the parent struct
+        match field.field_type {
+          RsArgsValue::Object(obj) if get_ffi_tag(obj) == FFITypeTag::StackStruct => {
+            struct_type = obj
           }
-          e => {
-            return Err(FFIError::Panic(e.to_string()).into());
+          _ => {
+            return Err(
+              FFIError::UnsupportedValueType(format!(
+                "{}: field {} is not a StackStruct",
+                field_path, field_name
+              ))
+              .into(),
+            )
           }
-        },
+        }
       }
-    };
-    map.insert(library, (lib, HashMap::new()));
-  }
-  Ok(())
+    }
+    Ok(offset as u32)
+  })
+}
+
+#[napi]
+unsafe fn layout(env: Env, struct_type: JsUnknown) -> napi::Result<StructLayout> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let struct_type = parse_struct_type(&env, struct_type)?;
+    let (fields, size, align) = calculate_struct_layout(&struct_type)?;
+    Ok(StructLayout {
+      size: size as u32,
+      align: align as u32,
+      fields: fields
+        .into_iter()
+        .map(|field| FieldLayout {
+          name: field.name.to_string(),
+          offset: field.offset as u32,
+          size: field.size as u32,
+          align: field.align as u32,
+          padding: field.padding as u32,
+        })
+        .collect(),
+    })
+  })
+}
+
+/// Turn a JS function into a C function pointer which can be stored in struct fields and vtables
+/// or passed as `DataType.External`. The callback is released once the returned External is
+/// garbage collected, so keep a reference to it for as long as C may call it. The callback does
+/// not keep the process alive on its own.
+#[napi]
+#[allow(deprecated)]
+unsafe fn create_callback(
+  env: Env,
+  func: JsFunction,
+  signature: JsUnknown,
+) -> napi::Result<JsExternal> {
+  with_error_code(&env, ErrorContext::default(), || {
+    create_callback_external(&env, func, signature)
+  })
+}
+
+#[allow(deprecated)]
+unsafe fn create_callback_external(
+  env: &Env,
+  func: JsFunction,
+  signature: JsUnknown,
+) -> Result<JsExternal> {
+  let func_desc = match parse_type_define(env, signature, "signature")? {
+    RsArgsValue::Object(func_desc) if get_ffi_tag(&func_desc) == FFITypeTag::Function => func_desc,
+    _ => {
+      return Err(
+        FFIError::UnsupportedValueType(
+          "createCallback: signature should be created by funcConstructor".to_string(),
+        )
+        .into(),
+      )
+    }
+  };
+  let code_ptr = with_allocation_site(
+    env,
+    || AllocationSite {
+      library: None,
+      func_name: "createCallback".to_string(),
+    },
+    || create_closure(env, func_desc, func),
+  )?;
+  let tsfn_call_context =
+    with_instance_data(env, |data| data.closure_map[&code_ptr])? as *mut TsFnCallContext;
+  (*tsfn_call_context).tsfn.unref(env)?;
+  let js_external = env.create_external(
+    *(code_ptr as *mut *mut c_void),
+    Some(std::mem::size_of::<*mut c_void>() as i64),
+  )?;
+  check_status!(napi::sys::napi_add_finalizer(
+    env.raw(),
+    js_external.raw(),
+    code_ptr,
+    Some(finalize_callback),
+    std::ptr::null_mut(),
+    std::ptr::null_mut(),
+  ))?;
+  Ok(js_external)
+}
+
+unsafe extern "C" fn finalize_callback(
+  env: napi::sys::napi_env,
+  code_ptr: *mut c_void,
+  _hint: *mut c_void,
+) {
+  free_closure(&Env::from_raw(env), code_ptr);
+}
+
+#[napi]
+fn open(env: Env, params: OpenParams) -> napi::Result<Library> {
+  let OpenParams { library, path } = params;
+  let context = ErrorContext {
+    library: Some(library.clone()),
+    ..Default::default()
+  };
+  with_error_code(&env, context, || {
+    let lib = open_shared_library(&path)?;
+    // the name is an index for load, it can't be taken by another library until it is closed
+    with_instance_data(&env, |data| -> Result<()> {
+      match data.library_map.get(&library) {
+        Some((registered, _)) if !Arc::ptr_eq(registered, &lib) => Err(
+          FFIError::InvalidArg(format!(
+            "library {:?} has been opened from another path, close it before opening {:?}",
+            library, path
+          ))
+          .into(),
+        ),
+        Some(_) => Ok(()),
+        None => {
+          data
+            .library_map
+            .insert(library.clone(), (Arc::clone(&lib), HashMap::new()));
+          Ok(())
+        }
+      }
+    })??;
+    Ok(Library::new(library, path, &lib))
+  })
 }
 
 #[napi]
-fn close(library: String) {
-  unsafe {
-    if LIBRARY_MAP.is_none() {
-      return;
+fn close(env: Env, library: String, params: Option<CloseParams>) -> napi::Result<()> {
+  // only drops this env's reference and closes the handles returned from open, the library is
+  // unloaded once no other env, function created by Library.func, symbol, pending runInNewThread
+  // call or callback passed to it holds it
+  if let Some((lib, _)) = with_instance_data(&env, |data| data.library_map.remove(&library))? {
+    if let Some(CloseParams { force: Some(true) }) = params {
+      unsafe { unpin_library_callbacks(&env, &lib)? };
     }
-    let map = LIBRARY_MAP.as_mut().unwrap();
-    map.remove(&library);
   }
+  Ok(())
 }
 
-unsafe fn get_symbol<'a>(
+fn get_symbol(
+  env: &Env,
   library: &String,
   func_name: &String,
-) -> napi::Result<unsafe extern "C" fn()> {
-  let library_map = LIBRARY_MAP.as_mut().unwrap();
-  let (lib, func_map) = library_map
-    .get_mut(library)
-    .ok_or(FFIError::LibraryNotFound(format!(
-      "Before calling load, you need to open the file {:?} with the open method",
-      library
-    )))?;
-  let func = func_map
-    .entry(func_name.clone())
-    .or_insert_with(|| {
-      lib
-        .symbol::<unsafe extern "C" fn()>(&func_name)
-        .map_err(|_| {
-          FFIError::FunctionNotFound(format!(
-            "Cannot find {:?} function in shared library",
-            &func_name
-          ))
-          .into()
-        })
-    })
-    .as_ref()
-    .map_err(|e| e.clone())?;
-  Ok(**func)
+) -> Result<(Arc<symbor::Library>, unsafe extern "C" fn())> {
+  with_instance_data(env, |data| {
+    let (lib, func_map) = data
+      .library_map
+      .get_mut(library)
+      .ok_or(FFIError::LibraryNotFound(format!(
+        "Before calling load, you need to open the file {:?} with the open method",
+        library
+      )))?;
+    let func = func_map
+      .entry(func_name.clone())
+      .or_insert_with(|| unsafe {
+        lib
+          .symbol::<unsafe extern "C" fn()>(&func_name)
+          .map(|func| *func)
+          .ok()
+      })
+      .ok_or_else(|| {
+        FFIError::FunctionNotFound(format!(
+          "Cannot find {:?} function in shared library",
+          &func_name
+        ))
+      })?;
+    Ok((Arc::clone(lib), func))
+  })?
 }
 #[napi]
 unsafe fn load(env: Env, params: FFIParams) -> napi::Result<JsUnknown> {
@@ -254,27 +664,233 @@ unsafe fn load(env: Env, params: FFIParams) -> napi::Result<JsUnknown> {
     errno,
     run_in_new_thread,
     free_result_memory,
+    abi,
+    guarded,
+    finalizer,
+    deallocator,
   } = params;
-  let func = get_symbol(&library, &func_name)?;
-  let params_type_len = params_type.len();
-  let params_type_rs: Rc<Vec<RsArgsValue>> = Rc::new(
-    params_type
-      .into_iter()
-      .map(|param| type_define_to_rs_args(&env, param).unwrap())
-      .collect(),
+  let context = ErrorContext {
+    library: Some(library.clone()),
+    func_name: Some(func_name.clone()),
+    ..Default::default()
+  };
+  with_error_code(&env, context, || {
+    let (lib, func) = get_symbol(&env, &library, &func_name)?;
+    call_function(
+      env,
+      func,
+      func_name,
+      Some((library, lib)),
+      &parse_call_signature(
+        &env,
+        FuncSignatureParams {
+          ret_type,
+          params_type,
+          errno,
+          run_in_new_thread,
+          free_result_memory,
+          abi,
+          guarded,
+          finalizer,
+          deallocator,
+        },
+      )?,
+      params_value,
+    )
+  })
+}
+
+/// Call a function through its address, e.g. a function pointer returned from C or the External
+/// returned from `Library.symbol`, with the same marshalling as `load`.
+#[napi]
+unsafe fn call_pointer(env: Env, params: CallPointerParams) -> napi::Result<JsUnknown> {
+  let CallPointerParams {
+    func_ptr,
+    ret_type,
+    params_type,
+    params_value,
+    errno,
+    run_in_new_thread,
+    free_result_memory,
+    abi,
+    guarded,
+    finalizer,
+    deallocator,
+  } = params;
+  with_error_code(&env, ErrorContext::default(), || {
+    if func_ptr.get_type()? != ValueType::External {
+      return Err(
+        FFIError::InvalidArg("callPointer: funcPtr should be an External".to_string()).into(),
+      );
+    }
+    let func_ptr = get_js_external_wrap_data(&env, func_ptr.cast())?;
+    if func_ptr.is_null() {
+      return Err(
+        FFIError::FunctionNotFound("callPointer: funcPtr is a null pointer".to_string()).into(),
+      );
+    }
+    call_function(
+      env,
+      std::mem::transmute::<*mut c_void, unsafe extern "C" fn()>(func_ptr),
+      format!("function at {:?}", func_ptr),
+      None,
+      &parse_call_signature(
+        &env,
+        FuncSignatureParams {
+          ret_type,
+          params_type,
+          errno,
+          run_in_new_thread,
+          free_result_memory,
+          abi,
+          guarded,
+          finalizer,
+          deallocator,
+        },
+      )?,
+      params_value,
+    )
+  })
+}
+
+/// Parse the types and options of a call, a finalizer is only allowed for a returned External
+pub(crate) unsafe fn parse_call_signature(
+  env: &Env,
+  params: FuncSignatureParams,
+) -> Result<CallSignature> {
+  let FuncSignatureParams {
+    ret_type,
+    params_type,
+    errno,
+    run_in_new_thread,
+    free_result_memory,
+    abi,
+    guarded,
+    finalizer,
+    deallocator,
+  } = params;
+  let abi = get_ffi_abi(abi.as_deref())?;
+  let guarded = guarded == Some(true);
+  if guarded {
+    check_guard_supported()?;
+  }
+  let params_type_rs = parse_call_params_type(env, params_type)?;
+  let ret_type_rs = parse_type_define(env, ret_type, RET_TYPE)?;
+  let finalizer = finalizer
+    .map(|finalizer| parse_finalizer(env, finalizer, None))
+    .transpose()?;
+  if finalizer.is_some()
+    && !matches!(ret_type_rs, RsArgsValue::I32(number) if number == DataType::External as i32)
+  {
+    return Err(
+      FFIError::InvalidArg(
+        "finalizer can only be used when retType is DataType.External".to_string(),
+      )
+      .into(),
+    );
+  }
+  let deallocator = parse_deallocator(env, deallocator)?;
+  // a view keeps reading the result memory after the call returns
+  if free_result_memory {
+    if let RsArgsValue::Object(obj) = &ret_type_rs {
+      if let FFITypeTag::Array | FFITypeTag::StackArray = get_ffi_tag(obj) {
+        if get_array_desc(obj)?.typed_array == Some(TypedArrayOutput::View) {
+          return Err(
+            FFIError::InvalidArg(
+              "freeResultMemory can't be used when the result is a typedArray view".to_string(),
+            )
+            .into(),
+          );
+        }
+      }
+    }
+  }
+  Ok(CallSignature {
+    params_type: Rc::new(params_type_rs),
+    ret_type: ret_type_rs,
+    errno,
+    run_in_new_thread,
+    free_result_memory,
+    abi,
+    guarded,
+    finalizer,
+    deallocator,
+  })
+}
+
+unsafe fn call_function(
+  env: Env,
+  func: unsafe extern "C" fn(),
+  func_name: String,
+  library: Option<(String, Arc<symbor::Library>)>,
+  signature: &CallSignature,
+  params_value: Vec<JsUnknown>,
+) -> Result<JsUnknown> {
+  let (library_name, library) = library.unzip();
+  let CallSignature {
+    params_type: params_type_rs,
+    ret_type: ret_type_rs,
+    errno,
+    run_in_new_thread,
+    free_result_memory,
+    abi,
+    guarded,
+    ..
+  } = signature;
+  let (errno, run_in_new_thread, free_result_memory, abi, guarded) = (
+    *errno,
+    *run_in_new_thread,
+    *free_result_memory,
+    *abi,
+    *guarded,
   );
+  let ret_type_rs = ret_type_rs.clone();
+  let finalizer = signature
+    .finalizer
+    .as_ref()
+    .map(|finalizer| finalizer.for_type(&ret_type_rs));
+  let deallocator = signature.deallocator.clone();
+  // a call on another thread borrows the memory of views, they are referenced once it is spawned
+  let views: Vec<napi::sys::napi_value> = if run_in_new_thread == Some(true) {
+    params_value
+      .iter()
+      .filter(|value| {
+        value.is_typedarray().unwrap_or(false) || value.is_dataview().unwrap_or(false)
+      })
+      .map(|value| value.raw())
+      .collect()
+  } else {
+    vec![]
+  };
+  // values are checked before anything is allocated for the call
+  let arg_values = get_arg_values(&env, Rc::clone(params_type_rs), params_value)?;
+  let mut r_type = get_ffi_type(&ret_type_rs)?;
   let mut arg_types: Vec<*mut ffi_type> = params_type_rs
     .iter()
-    .map(|arg| Box::into_raw(get_ffi_type(arg)))
+    .map(|arg| get_param_ffi_types(arg))
+    .collect::<Result<Vec<_>>>()?
+    .into_iter()
+    .flatten()
     .collect();
-  let arg_values = get_arg_values(Rc::clone(&params_type_rs), params_value)?;
-  let mut arg_values_c_void = get_value_pointer(&env, Rc::clone(&params_type_rs), arg_values)?;
-  let ret_type_rs = type_define_to_rs_args(&env, ret_type)?;
-  let mut r_type = get_ffi_type(&ret_type_rs);
+  let nargs = arg_types.len();
+  // the memory of the arguments is released at once after the call
+  let arena = Arena::for_call(with_instance_data(&env, |data| data.hardened)?);
+  let arg_values_c_void = with_allocation_site(
+    &env,
+    || AllocationSite {
+      library: library_name,
+      func_name: func_name.clone(),
+    },
+    || get_value_pointer(&env, Rc::clone(params_type_rs), arg_values, Some(&arena)),
+  )?;
+  if let Some(lib) = &library {
+    pin_library_callbacks(&env, lib, params_type_rs, &arg_values_c_void)?;
+  }
+  let mut arg_values_c_void = get_call_arg_values(params_type_rs, arg_values_c_void);
 
   let mut cif = ffi_cif {
-    abi: ffi_abi_FFI_DEFAULT_ABI,
-    nargs: params_type_len as u32,
+    abi,
+    nargs: nargs as u32,
     arg_types: arg_types.as_mut_ptr(),
     rtype: &mut *r_type,
     bytes: 0,
@@ -282,7 +898,7 @@ unsafe fn load(env: Env, params: FFIParams) -> napi::Result<JsUnknown> {
     #[cfg(all(target_arch = "aarch64", target_os = "windows"))]
     is_variadic: 0,
     #[cfg(all(target_arch = "aarch64", target_vendor = "apple"))]
-    aarch64_nfixedargs: params_type_len as u32,
+    aarch64_nfixedargs: nargs as u32,
     #[cfg(all(target_arch = "arm"))]
     vfp_used: 0,
     #[cfg(all(target_arch = "arm"))]
@@ -293,37 +909,51 @@ unsafe fn load(env: Env, params: FFIParams) -> napi::Result<JsUnknown> {
     vfp_args: [0; 16],
   };
 
-  ffi_prep_cif(
+  let status = ffi_prep_cif(
     &mut cif,
-    ffi_abi_FFI_DEFAULT_ABI,
-    params_type_len as u32,
+    abi,
+    nargs as u32,
     &mut *r_type,
     arg_types.as_mut_ptr(),
   );
+  if status != ffi_status_FFI_OK {
+    return Err(FFIError::Panic(format!("ffi_prep_cif failed with status {}", status)).into());
+  }
   if run_in_new_thread == Some(true) {
     use napi::Task;
     impl Task for FFICALL {
       type Output = BarePointerWrap;
       type JsValue = JsUnknown;
-      fn compute(&mut self) -> Result<BarePointerWrap> {
+      fn compute(&mut self) -> napi::Result<BarePointerWrap> {
         let FFICALLPARAMS {
           mut cif,
           fn_pointer,
           errno,
+          guarded,
           ..
         } = self.data;
 
         let FFICALLPARAMS {
-          arg_values_c_void, ..
+          r_type,
+          arg_values_c_void,
+          func_name,
+          ..
         } = &mut self.data;
         unsafe {
-          let result = libc::malloc(std::mem::size_of::<*mut c_void>());
-          ffi_call(
-            &mut cif,
-            Some(fn_pointer),
-            result,
-            arg_values_c_void.as_mut_ptr(),
-          );
+          let result = libc::malloc(get_result_size(&mut **r_type));
+          let mut fault = None;
+          if guarded {
+            fault = guarded_ffi_call(&mut cif, fn_pointer, result, arg_values_c_void.as_mut_ptr())
+              .err()
+              .map(|fault| fault_message(func_name, &fault));
+          } else {
+            ffi_call(
+              &mut cif,
+              Some(fn_pointer),
+              result,
+              arg_values_c_void.as_mut_ptr(),
+            );
+          }
           let (errno_code, errno_message) = if let Some(true) = errno {
             let last_error = std::io::Error::last_os_error();
             (
@@ -337,11 +967,17 @@ unsafe fn load(env: Env, params: FFIParams) -> napi::Result<JsUnknown> {
             data: result,
             errno_code,
             errno_message,
+            fault,
           })
         }
       }
 
-      fn resolve(&mut self, env: Env, output: Self::Output) -> Result<JsUnknown> {
+      fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<JsUnknown> {
+        release_view_refs(&env, &mut self.data.view_refs);
+        Err(err)
+      }
+
+      fn resolve(&mut self, env: Env, output: Self::Output) -> napi::Result<JsUnknown> {
         let FFICALLPARAMS {
           errno,
           free_result_memory,
@@ -350,25 +986,49 @@ unsafe fn load(env: Env, params: FFIParams) -> napi::Result<JsUnknown> {
         let FFICALLPARAMS {
           ret_type_rs,
           arg_types,
-          arg_values_c_void,
-          params_type_rs,
+          library,
+          finalizer,
+          deallocator,
+          arena,
+          func_name,
+          view_refs,
           ..
         } = &mut self.data;
-        unsafe {
-          let call_result = get_js_unknown_from_pointer(&env, &ret_type_rs, output.data);
-          if free_result_memory {
-            free_c_pointer_memory(output.data, &ret_type_rs);
-          }
+        release_view_refs(&env, view_refs);
+        if let Some(fault) = output.fault {
+          // the memory of a faulted call is leaked on purpose, the heap may be corrupted
+          std::mem::forget(arena.take());
+          let err = add_error_context(FFIError::Fault(fault).into(), |context| {
+            context.func_name = Some(func_name.clone())
+          });
+          return Err(to_js_error(&env, report_fault(&env, err)));
+        }
+        let context = ErrorContext {
+          func_name: Some(func_name.clone()),
+          ..Default::default()
+        };
+        with_error_code(&env, context, || unsafe {
+          let call_result = match arena.as_ref().and_then(|arena| arena.check_canaries()) {
+            Some(index) => Err(out_of_bounds_error(func_name, index)),
+            None => get_call_result(&env, ret_type_rs, output.data, finalizer.take()),
+          };
+          let free_result = if free_result_memory {
+            free_c_pointee_memory(&env, output.data, &ret_type_rs, deallocator.as_ref())
+          } else {
+            Ok(())
+          };
+          libc::free(output.data);
           arg_types.into_iter().for_each(|arg| {
             let _ = Box::from_raw(*arg);
           });
-          arg_values_c_void
-            .into_iter()
-            .zip(params_type_rs.iter())
-            .for_each(|(ptr, ptr_desc)| {
-              free_rs_pointer_memory(*ptr, ptr_desc);
-            });
-          libc::free(output.data);
+          if let Some(arena) = arena.take() {
+            arena.release(&env);
+          }
+          // the result has been converted, the library may be unloaded now if it has been closed
+          // while the call was running
+          library.take();
+          // the memory of the call is released even when the result memory can't be
+          free_result?;
           if let Some(true) = errno {
             let mut obj = env.create_object()?;
             obj.set_named_property("errnoCode", env.create_int32(output.errno_code)?)?;
@@ -378,7 +1038,7 @@ unsafe fn load(env: Env, params: FFIParams) -> napi::Result<JsUnknown> {
           } else {
             call_result
           }
-        }
+        })
       }
     }
     let task = FFICALL::new(FFICALLPARAMS {
@@ -386,29 +1046,52 @@ unsafe fn load(env: Env, params: FFIParams) -> napi::Result<JsUnknown> {
       arg_values_c_void,
       ret_type_rs,
       fn_pointer: func,
+      library,
       errno,
       arg_types,
+      r_type,
       free_result_memory,
-      params_type_rs,
+      func_name,
+      guarded,
+      finalizer,
+      deallocator,
+      arena: Some(arena),
+      view_refs: views
+        .into_iter()
+        .map(|view| env.create_reference(JsUnknown::from_raw_unchecked(env.raw(), view)))
+        .collect::<napi::Result<_>>()?,
     });
     let async_work_promise = env.spawn(task)?;
     Ok(async_work_promise.promise_object().into_unknown())
   } else {
-    let result = &mut () as *mut _ as *mut c_void;
-    ffi_call(&mut cif, Some(func), result, arg_values_c_void.as_mut_ptr());
+    let result = libc::malloc(get_result_size(cif.rtype));
+    if guarded {
+      // the memory of a faulted call is leaked on purpose, the heap may be corrupted
+      if let Err(fault) = guarded_ffi_call(&mut cif, func, result, arg_values_c_void.as_mut_ptr()) {
+        std::mem::forget(arena);
+        return Err(report_fault(
+          &env,
+          FFIError::Fault(fault_message(&func_name, &fault)).into(),
+        ));
+      }
+    } else {
+      ffi_call(&mut cif, Some(func), result, arg_values_c_void.as_mut_ptr());
+    }
     arg_types.into_iter().for_each(|arg| {
       let _ = Box::from_raw(arg);
     });
-    let call_result = get_js_unknown_from_pointer(&env, &ret_type_rs, result);
-    if free_result_memory {
-      free_c_pointer_memory(result, &ret_type_rs);
-    }
-    arg_values_c_void
-      .into_iter()
-      .zip(params_type_rs.iter())
-      .for_each(|(ptr, ptr_desc)| {
-        free_rs_pointer_memory(ptr, ptr_desc);
-      });
+    let call_result = match arena.check_canaries() {
+      Some(index) => Err(out_of_bounds_error(&func_name, index)),
+      None => get_call_result(&env, &ret_type_rs, result, finalizer),
+    };
+    let free_result = if free_result_memory {
+      free_c_pointee_memory(&env, result, &ret_type_rs, deallocator.as_ref())
+    } else {
+      Ok(())
+    };
+    libc::free(result);
+    arena.release(&env);
+    free_result?;
     if let Some(true) = errno {
       add_errno(&env, call_result?)
     } else {
@@ -417,6 +1100,57 @@ unsafe fn load(env: Env, params: FFIParams) -> napi::Result<JsUnknown> {
   }
 }
 
+fn release_view_refs(env: &Env, view_refs: &mut Vec<Ref<()>>) {
+  view_refs.drain(..).for_each(|mut view_ref| {
+    let _ = view_ref.unref(*env);
+  });
+}
+
+fn out_of_bounds_error(func_name: &str, index: usize) -> Error {
+  add_error_context(
+    FFIError::OutOfBounds(format!(
+      "{} wrote out of the bounds of the memory of paramsValue[{}]",
+      func_name, index
+    ))
+    .into(),
+    |context| {
+      context.func_name = Some(func_name.to_string());
+      context.arg_index = Some(index);
+    },
+  )
+}
+
+unsafe fn parse_deallocator(
+  env: &Env,
+  deallocator: Option<JsUnknown>,
+) -> Result<Option<Destructor>> {
+  deallocator
+    .map(|deallocator| Destructor::from_js_value(env, deallocator, "deallocator"))
+    .transpose()
+}
+
+unsafe fn get_call_result(
+  env: &Env,
+  ret_type_rs: &RsArgsValue,
+  result: *mut c_void,
+  finalizer: Option<Finalizer>,
+) -> Result<JsUnknown> {
+  match finalizer {
+    // the returned pointer is only wrapped, the result storage is freed as usual
+    Some(finalizer) => {
+      create_external_with_finalizer(env, *(result as *mut *mut c_void), finalizer)
+        .map(|js_external| js_external.into_unknown())
+    }
+    None => get_js_unknown_from_pointer(env, ret_type_rs, result),
+  }
+}
+
+// ffi_call writes at least a full ffi_arg for integral returns and the whole
+// struct for StackStruct returns, so the result storage must fit both.
+unsafe fn get_result_size(rtype: *mut ffi_type) -> usize {
+  std::cmp::max((*rtype).size, std::mem::size_of::<u64>())
+}
+
 fn add_errno(env: &Env, call_result: JsUnknown) -> Result<JsUnknown> {
   use std::io::Error;
   let last_error = Error::last_os_error();
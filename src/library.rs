@@ -0,0 +1,195 @@
+use crate::define::*;
+use crate::utils::error::Result;
+use crate::utils::error::{with_error_code, ErrorContext};
+use crate::utils::instance::{unpin_library_callbacks, with_instance_data};
+use crate::{call_function, parse_call_signature};
+use dlopen::symbor;
+#[allow(deprecated)]
+use napi::JsExternal;
+use napi::{check_status, Env, JsUnknown, NapiRaw};
+use std::ffi::c_void;
+use std::sync::{Arc, Weak};
+
+/// Handle of a dynamic library returned from `open`.
+/// The library is owned by its registration under the name given to `open`, the handle only refers
+/// to it so it is closed as soon as the name is unregistered, either by `close(library)` or by
+/// `Library.close`. The functions created by `func` and the symbols returned from `symbol` hold
+/// their own reference, so the library is only unloaded once all of them are garbage collected.
+#[napi]
+pub struct Library {
+  name: String,
+  path: String,
+  lib: Weak<symbor::Library>,
+}
+
+#[napi]
+impl Library {
+  pub fn new(name: String, path: String, lib: &Arc<symbor::Library>) -> Self {
+    Library {
+      name,
+      path,
+      lib: Arc::downgrade(lib),
+    }
+  }
+
+  // the library while its name is still registered for it
+  fn get_lib(&self, env: &Env) -> Result<Arc<symbor::Library>> {
+    with_instance_data(env, |data| match data.library_map.get(&self.name) {
+      Some((registered, _)) if Weak::ptr_eq(&Arc::downgrade(registered), &self.lib) => {
+        Some(Arc::clone(registered))
+      }
+      _ => None,
+    })?
+    .ok_or_else(|| {
+      FFIError::LibraryNotFound(format!(
+        "Library {:?} has been closed, please open it again",
+        self.name
+      ))
+      .into()
+    })
+  }
+
+  unsafe fn get_func(
+    &self,
+    lib: &symbor::Library,
+    func_name: &str,
+  ) -> Result<unsafe extern "C" fn()> {
+    lib
+      .symbol::<unsafe extern "C" fn()>(func_name)
+      .map(|func| *func)
+      .map_err(|_| {
+        FFIError::FunctionNotFound(format!(
+          "Cannot find {:?} function in shared library",
+          func_name
+        ))
+        .into()
+      })
+  }
+
+  fn error_context(&self, func_name: Option<&str>) -> ErrorContext {
+    ErrorContext {
+      library: Some(self.name.clone()),
+      func_name: func_name.map(|name| name.to_string()),
+      ..Default::default()
+    }
+  }
+
+  #[napi(getter)]
+  pub fn path(&self) -> String {
+    self.path.clone()
+  }
+
+  #[napi]
+  pub unsafe fn has(&self, env: Env, name: String) -> napi::Result<bool> {
+    with_error_code(&env, self.error_context(None), || {
+      Ok(
+        self
+          .get_lib(&env)?
+          .symbol::<unsafe extern "C" fn()>(&name)
+          .is_ok(),
+      )
+    })
+  }
+
+  // a symbol is a DataType.External like the pointers of the rest of the api
+  #[napi]
+  #[allow(deprecated)]
+  pub unsafe fn symbol(&self, env: Env, name: String) -> napi::Result<JsExternal> {
+    with_error_code(&env, self.error_context(Some(&name)), || {
+      let lib = self.get_lib(&env)?;
+      let func = self.get_func(&lib, &name)?;
+      let js_external = env.create_external(
+        func as *mut c_void,
+        Some(std::mem::size_of::<*mut c_void>() as i64),
+      )?;
+      // the address is only valid while the library is loaded
+      let lib = Box::into_raw(Box::new(lib));
+      check_status!(napi::sys::napi_add_finalizer(
+        env.raw(),
+        js_external.raw(),
+        lib as *mut c_void,
+        Some(release_symbol_library),
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+      ))
+      .inspect_err(|_| {
+        let _ = Box::from_raw(lib);
+      })?;
+      Ok(js_external)
+    })
+  }
+
+  /// The signature is parsed once, the returned function only converts the values of every call
+  #[napi]
+  pub unsafe fn func(
+    &self,
+    env: Env,
+    name: String,
+    signature: FuncSignatureParams,
+  ) -> napi::Result<LibraryFunction> {
+    with_error_code(&env, self.error_context(Some(&name)), || {
+      let lib = self.get_lib(&env)?;
+      Ok(LibraryFunction {
+        func: self.get_func(&lib, &name)?,
+        lib,
+        signature: parse_call_signature(&env, signature)?,
+        library: self.name.clone(),
+        name,
+      })
+    })
+  }
+
+  #[napi]
+  pub fn close(&self, env: Env, params: Option<CloseParams>) -> napi::Result<()> {
+    // the name is only unregistered when it still refers to this library
+    let lib = with_instance_data(&env, |data| match data.library_map.get(&self.name) {
+      Some((registered, _)) if Weak::ptr_eq(&Arc::downgrade(registered), &self.lib) => {
+        data.library_map.remove(&self.name).map(|(lib, _)| lib)
+      }
+      _ => None,
+    })?;
+    if let (Some(lib), Some(CloseParams { force: Some(true) })) = (lib, params) {
+      unsafe { unpin_library_callbacks(&env, &lib)? };
+    }
+    Ok(())
+  }
+}
+
+unsafe extern "C" fn release_symbol_library(
+  _env: napi::sys::napi_env,
+  lib: *mut c_void,
+  _hint: *mut c_void,
+) {
+  let _ = Box::from_raw(lib as *mut Arc<symbor::Library>);
+}
+
+#[napi]
+pub struct LibraryFunction {
+  library: String,
+  name: String,
+  func: unsafe extern "C" fn(),
+  lib: Arc<symbor::Library>,
+  signature: CallSignature,
+}
+
+#[napi]
+impl LibraryFunction {
+  #[napi]
+  pub unsafe fn invoke(&self, env: Env, params_value: Vec<JsUnknown>) -> napi::Result<JsUnknown> {
+    let context = ErrorContext {
+      library: Some(self.library.clone()),
+      func_name: Some(self.name.clone()),
+      ..Default::default()
+    };
+    with_error_code(&env, context, || {
+      call_function(
+        env,
+        self.func,
+        self.name.clone(),
+        Some((self.library.clone(), Arc::clone(&self.lib))),
+        &self.signature,
+        params_value,
+      )
+    })
+  }
+}
@@ -0,0 +1,345 @@
+// the pointers are the Externals created by createPointer and load, which napi's typed External
+// can't read, so the deprecated JsExternal is kept like in the rest of the pointer api
+#![allow(deprecated)]
+
+use crate::define::*;
+use crate::utils::error::{with_error_code, ErrorContext};
+use crate::utils::error::{Error, Result};
+use crate::utils::object_utils::get_type_size_align;
+use crate::utils::{get_js_external_wrap_data, parse_type_define};
+use napi::bindgen_prelude::{BigInt, Either};
+use napi::{Env, JsExternal, JsUnknown};
+use std::ffi::{c_char, c_void, CStr};
+
+unsafe fn get_address(env: &Env, ptr: JsExternal, offset: Option<i64>) -> Result<*mut u8> {
+  let ptr = get_js_external_wrap_data(env, ptr)?;
+  if ptr.is_null() {
+    return Err(FFIError::InvalidArg("pointer is null".to_string()).into());
+  }
+  Ok((ptr as *mut u8).offset(get_offset(offset.unwrap_or(0))?))
+}
+
+// an i64 from js doesn't fit in an isize on 32 bit targets
+fn get_offset(offset: i64) -> Result<isize> {
+  isize::try_from(offset).map_err(|_| {
+    FFIError::InvalidArg(format!(
+      "offset {} is out of the range of a pointer",
+      offset
+    ))
+    .into()
+  })
+}
+
+pub fn create_pointer_external(env: &Env, ptr: *mut c_void) -> Result<JsExternal> {
+  Ok(env.create_external(ptr, Some(std::mem::size_of::<*mut c_void>() as i64))?)
+}
+
+fn out_of_range(value: f64, type_name: &str) -> Error {
+  FFIError::InvalidArg(format!(
+    "value {} is out of the range of {}",
+    value, type_name
+  ))
+  .into()
+}
+
+// js numbers are doubles, a value the c type can't hold is an error instead of being truncated
+trait FromJsNumber: Sized {
+  fn from_js_number(value: f64) -> Result<Self>;
+}
+
+macro_rules! impl_integer_from_js_number {
+  ($($type:ty),*) => {
+    $(
+      impl FromJsNumber for $type {
+        fn from_js_number(value: f64) -> Result<Self> {
+          // fract is NaN for NaN and the infinities
+          if value.fract() != 0.0 || value < <$type>::MIN as f64 || value > <$type>::MAX as f64 {
+            return Err(out_of_range(value, stringify!($type)));
+          }
+          Ok(value as $type)
+        }
+      }
+    )*
+  };
+}
+impl_integer_from_js_number!(i8, u8, i16, u16, i32, u32);
+
+impl FromJsNumber for f32 {
+  fn from_js_number(value: f64) -> Result<Self> {
+    if value.is_finite() && value.abs() > f32::MAX as f64 {
+      return Err(out_of_range(value, "f32"));
+    }
+    Ok(value as f32)
+  }
+}
+
+impl FromJsNumber for f64 {
+  fn from_js_number(value: f64) -> Result<Self> {
+    Ok(value)
+  }
+}
+
+// the value is stored in the c type and converted from/to the js type, the address may be unaligned
+macro_rules! define_read_write {
+  ($read:ident, $write:ident, $type:ty, $js_type:ty) => {
+    #[napi]
+    unsafe fn $read(env: Env, ptr: JsExternal, offset: Option<i64>) -> napi::Result<$js_type> {
+      with_error_code(&env, ErrorContext::default(), || {
+        let address = get_address(&env, ptr, offset)?;
+        Ok((address as *const $type).read_unaligned() as $js_type)
+      })
+    }
+
+    #[napi]
+    unsafe fn $write(
+      env: Env,
+      ptr: JsExternal,
+      offset: Option<i64>,
+      value: f64,
+    ) -> napi::Result<()> {
+      with_error_code(&env, ErrorContext::default(), || {
+        let value = <$type>::from_js_number(value)?;
+        let address = get_address(&env, ptr, offset)?;
+        (address as *mut $type).write_unaligned(value);
+        Ok(())
+      })
+    }
+  };
+}
+define_read_write!(read_i8, write_i8, i8, i32);
+define_read_write!(read_u8, write_u8, u8, u32);
+define_read_write!(read_i16, write_i16, i16, i32);
+define_read_write!(read_u16, write_u16, u16, u32);
+define_read_write!(read_i32, write_i32, i32, i32);
+define_read_write!(read_u32, write_u32, u32, u32);
+define_read_write!(read_float, write_float, f32, f64);
+define_read_write!(read_double, write_double, f64, f64);
+
+// 64 bit integers are read and written as bigint so no precision is lost
+#[napi]
+unsafe fn read_i64(env: Env, ptr: JsExternal, offset: Option<i64>) -> napi::Result<BigInt> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let address = get_address(&env, ptr, offset)?;
+    Ok(BigInt::from((address as *const i64).read_unaligned()))
+  })
+}
+
+#[napi]
+unsafe fn write_i64(
+  env: Env,
+  ptr: JsExternal,
+  offset: Option<i64>,
+  value: BigInt,
+) -> napi::Result<()> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let address = get_address(&env, ptr, offset)?;
+    let (value, lossless) = value.get_i64();
+    if !lossless {
+      return Err(FFIError::InvalidArg("value is out of the range of i64".to_string()).into());
+    }
+    (address as *mut i64).write_unaligned(value);
+    Ok(())
+  })
+}
+
+#[napi]
+unsafe fn read_u64(env: Env, ptr: JsExternal, offset: Option<i64>) -> napi::Result<BigInt> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let address = get_address(&env, ptr, offset)?;
+    Ok(BigInt::from((address as *const u64).read_unaligned()))
+  })
+}
+
+#[napi]
+unsafe fn write_u64(
+  env: Env,
+  ptr: JsExternal,
+  offset: Option<i64>,
+  value: BigInt,
+) -> napi::Result<()> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let address = get_address(&env, ptr, offset)?;
+    let (sign_bit, value, lossless) = value.get_u64();
+    if sign_bit || !lossless {
+      return Err(FFIError::InvalidArg("value is out of the range of u64".to_string()).into());
+    }
+    (address as *mut u64).write_unaligned(value);
+    Ok(())
+  })
+}
+
+#[napi]
+unsafe fn read_pointer(env: Env, ptr: JsExternal, offset: Option<i64>) -> napi::Result<JsExternal> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let address = get_address(&env, ptr, offset)?;
+    create_pointer_external(&env, (address as *const *mut c_void).read_unaligned())
+  })
+}
+
+#[napi]
+unsafe fn write_pointer(
+  env: Env,
+  ptr: JsExternal,
+  offset: Option<i64>,
+  value: JsExternal,
+) -> napi::Result<()> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let address = get_address(&env, ptr, offset)?;
+    let value = get_js_external_wrap_data(&env, value)?;
+    (address as *mut *mut c_void).write_unaligned(value);
+    Ok(())
+  })
+}
+
+/// Read a NUL terminated string, at most `max_len` bytes are read when the string may not be
+/// terminated
+#[napi]
+unsafe fn read_c_string(
+  env: Env,
+  ptr: JsExternal,
+  offset: Option<i64>,
+  max_len: Option<u32>,
+) -> napi::Result<String> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let address = get_address(&env, ptr, offset)?;
+    let bytes = match max_len {
+      Some(max_len) => {
+        // nothing after the terminator is read, it may be past the end of the mapped memory
+        let mut len = 0;
+        while len < max_len as usize && *address.add(len) != 0 {
+          len += 1;
+        }
+        std::slice::from_raw_parts(address, len)
+      }
+      None => CStr::from_ptr(address as *const c_char).to_bytes(),
+    };
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+  })
+}
+
+// a byte count is a number or a bigint since a region may be larger than 4 GiB
+fn get_byte_count(n: Either<i64, BigInt>) -> Result<usize> {
+  let count = match n {
+    Either::A(n) => u64::try_from(n).ok(),
+    Either::B(n) => match n.get_u64() {
+      (false, n, true) => Some(n),
+      _ => None,
+    },
+  };
+  count
+    .and_then(|count| usize::try_from(count).ok())
+    .filter(|count| isize::try_from(*count).is_ok())
+    .ok_or_else(|| FFIError::InvalidArg("n is out of the range of a byte count".to_string()).into())
+}
+
+#[napi]
+unsafe fn memcpy(
+  env: Env,
+  dst: JsExternal,
+  src: JsExternal,
+  n: Either<i64, BigInt>,
+) -> napi::Result<()> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let n = get_byte_count(n)?;
+    let dst = get_address(&env, dst, None)?;
+    let src = get_address(&env, src, None)?;
+    // the regions may overlap
+    std::ptr::copy(src, dst, n);
+    Ok(())
+  })
+}
+
+#[napi]
+unsafe fn memset(
+  env: Env,
+  ptr: JsExternal,
+  value: f64,
+  n: Either<i64, BigInt>,
+) -> napi::Result<()> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let value = u8::from_js_number(value)?;
+    let n = get_byte_count(n)?;
+    let address = get_address(&env, ptr, None)?;
+    std::ptr::write_bytes(address, value, n);
+    Ok(())
+  })
+}
+
+/// Create a new External pointing `bytes` bytes after `ptr`
+#[napi]
+unsafe fn ptr_add(env: Env, ptr: JsExternal, bytes: i64) -> napi::Result<JsExternal> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let bytes = get_offset(bytes)?;
+    let ptr = get_js_external_wrap_data(&env, ptr)?;
+    create_pointer_external(&env, (ptr as *mut u8).wrapping_offset(bytes) as *mut c_void)
+  })
+}
+
+#[napi]
+unsafe fn pointer_to_address(env: Env, ptr: JsExternal) -> napi::Result<BigInt> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let ptr = get_js_external_wrap_data(&env, ptr)?;
+    Ok(BigInt::from(ptr as usize as u64))
+  })
+}
+
+#[napi]
+unsafe fn address_to_pointer(env: Env, address: BigInt) -> napi::Result<JsExternal> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let (sign_bit, address, lossless) = address.get_u64();
+    if sign_bit || !lossless || usize::try_from(address).is_err() {
+      return Err(
+        FFIError::InvalidArg("address is out of the range of a pointer".to_string()).into(),
+      );
+    }
+    create_pointer_external(&env, address as usize as *mut c_void)
+  })
+}
+
+/// Format the address like `0x7f12a4c0`
+#[napi]
+unsafe fn pointer_to_string(env: Env, ptr: JsExternal) -> napi::Result<String> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let ptr = get_js_external_wrap_data(&env, ptr)?;
+    Ok(format!("{:#x}", ptr as usize))
+  })
+}
+
+#[napi]
+unsafe fn pointer_equals(env: Env, a: JsExternal, b: JsExternal) -> napi::Result<bool> {
+  with_error_code(&env, ErrorContext::default(), || {
+    Ok(get_js_external_wrap_data(&env, a)? == get_js_external_wrap_data(&env, b)?)
+  })
+}
+
+/// Create a new External pointing `n` elements of `element_type` after `ptr`, `n` is in bytes
+/// when there is no element type
+#[napi]
+unsafe fn pointer_offset(
+  env: Env,
+  ptr: JsExternal,
+  n: i64,
+  element_type: Option<JsUnknown>,
+) -> napi::Result<JsExternal> {
+  let bytes = with_error_code(&env, ErrorContext::default(), || {
+    let element_size = match element_type {
+      Some(element_type) => {
+        get_type_size_align(&parse_type_define(&env, element_type, "elementType")?)?.0 as i64
+      }
+      None => 1,
+    };
+    n.checked_mul(element_size)
+      .ok_or_else(|| FFIError::InvalidArg("n is out of the range of an offset".to_string()).into())
+  })?;
+  ptr_add(env, ptr, bytes)
+}
+
+/// Distance in bytes from `b` to `a`
+#[napi]
+unsafe fn pointer_diff(env: Env, a: JsExternal, b: JsExternal) -> napi::Result<i64> {
+  with_error_code(&env, ErrorContext::default(), || {
+    let a = get_js_external_wrap_data(&env, a)?;
+    let b = get_js_external_wrap_data(&env, b)?;
+    Ok((a as isize).wrapping_sub(b as isize) as i64)
+  })
+}
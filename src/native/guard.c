@@ -0,0 +1,74 @@
+// Runs a native call with SIGSEGV/SIGBUS turned into a return value instead of killing the process.
+// sigsetjmp returns twice, which can't be expressed in Rust, so the jump point lives here.
+#include <pthread.h>
+#include <setjmp.h>
+#include <signal.h>
+#include <stddef.h>
+#include <string.h>
+
+typedef void (*ffi_rs_guarded_fn)(void *data);
+
+static __thread sigjmp_buf *guard_jmp_buf = NULL;
+static __thread void *guard_fault_addr = NULL;
+static __thread int guard_signal = 0;
+
+static struct sigaction previous_segv_action;
+static struct sigaction previous_bus_action;
+static pthread_once_t install_once = PTHREAD_ONCE_INIT;
+
+static void guard_handler(int sig, siginfo_t *info, void *ucontext)
+{
+  if (guard_jmp_buf != NULL)
+  {
+    sigjmp_buf *env = guard_jmp_buf;
+    guard_jmp_buf = NULL;
+    guard_fault_addr = info->si_addr;
+    guard_signal = sig;
+    siglongjmp(*env, 1);
+  }
+  // the fault didn't happen inside a guarded call, hand it to whoever was installed before us,
+  // e.g. the WebAssembly trap handler of V8
+  struct sigaction *previous = sig == SIGSEGV ? &previous_segv_action : &previous_bus_action;
+  if (previous->sa_flags & SA_SIGINFO)
+  {
+    previous->sa_sigaction(sig, info, ucontext);
+  }
+  else if (previous->sa_handler == SIG_DFL || previous->sa_handler == SIG_IGN)
+  {
+    // returning re-executes the faulting instruction, which now terminates the process as usual
+    signal(sig, SIG_DFL);
+  }
+  else
+  {
+    previous->sa_handler(sig);
+  }
+}
+
+static void install_guard_handler(void)
+{
+  struct sigaction action;
+  memset(&action, 0, sizeof(action));
+  action.sa_sigaction = guard_handler;
+  action.sa_flags = SA_SIGINFO | SA_ONSTACK;
+  sigemptyset(&action.sa_mask);
+  sigaction(SIGSEGV, &action, &previous_segv_action);
+  sigaction(SIGBUS, &action, &previous_bus_action);
+}
+
+// Returns 0 when fn returned normally, otherwise the signal number and the faulting address
+int ffi_rs_guarded_call(ffi_rs_guarded_fn fn, void *data, void **fault_addr)
+{
+  sigjmp_buf env;
+  sigjmp_buf *previous = guard_jmp_buf;
+  pthread_once(&install_once, install_guard_handler);
+  if (sigsetjmp(env, 1) != 0)
+  {
+    guard_jmp_buf = previous;
+    *fault_addr = guard_fault_addr;
+    return guard_signal;
+  }
+  guard_jmp_buf = &env;
+  fn(data);
+  guard_jmp_buf = previous;
+  return 0;
+}
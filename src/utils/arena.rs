@@ -0,0 +1,305 @@
+use crate::datatype::pointer::free_closure;
+use crate::datatype::string::{string_to_c_string, string_to_c_w_string};
+use crate::define::EncodedString;
+use crate::utils::error::Result;
+use crate::utils::instance::with_instance_data;
+use crate::utils::tracking::{record_allocation, untrack_arena};
+use napi::Env;
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::ffi::{c_char, c_void};
+use widestring::WideChar;
+
+const CHUNK_SIZE: usize = 4096;
+const CANARY_SIZE: usize = 16;
+const CANARY: u8 = 0xfd;
+const POISON: u8 = 0xdd;
+// the memory of the released hardened arenas which isn't reused yet
+const QUARANTINE_SIZE: usize = 16 << 20;
+
+/// A bump allocator owning the memory of the arguments of a call, or of the pointers created in a
+/// `withScope` callback, everything is released at once instead of walking the types
+pub struct Arena {
+  chunks: RefCell<Vec<(*mut u8, Layout)>>,
+  // the free space of the last chunk
+  cursor: Cell<usize>,
+  end: Cell<usize>,
+  // the closures with needFree which are released with the arena
+  closures: RefCell<Vec<*mut c_void>>,
+  // canaries surround the arrays, structs and strings of a hardened arena and its memory is
+  // poisoned when it is released, see setMemoryHardening
+  hardened: bool,
+  // (start, size, param) of the memory surrounded by canaries
+  guarded: RefCell<Vec<(*mut u8, usize, usize)>>,
+  // the index of the param whose value is being allocated
+  param: Cell<usize>,
+  // the arena of the arguments of a single call, see for_call
+  call: bool,
+}
+
+impl Arena {
+  pub fn new(hardened: bool) -> Self {
+    Arena {
+      chunks: RefCell::new(vec![]),
+      cursor: Cell::new(0),
+      end: Cell::new(0),
+      closures: RefCell::new(vec![]),
+      hardened,
+      guarded: RefCell::new(vec![]),
+      param: Cell::new(0),
+      call: false,
+    }
+  }
+
+  /// The arena of the arguments of a call, which is released once the call returns so the values
+  /// may borrow the memory of js values like TypedArrays instead of copying it
+  pub fn for_call(hardened: bool) -> Self {
+    let mut arena = Arena::new(hardened);
+    arena.call = true;
+    arena
+  }
+
+  pub fn is_call(&self) -> bool {
+    self.call
+  }
+
+  pub unsafe fn alloc_layout(&self, layout: Layout) -> *mut u8 {
+    if !self.hardened {
+      return self.bump(layout);
+    }
+    // the canary before the memory is as large as its alignment requires
+    let front = (CANARY_SIZE + layout.align() - 1) & !(layout.align() - 1);
+    let start = self
+      .bump(Layout::from_size_align(front + layout.size() + CANARY_SIZE, layout.align()).unwrap());
+    std::ptr::write_bytes(start, CANARY, front);
+    let ptr = start.add(front);
+    std::ptr::write_bytes(ptr.add(layout.size()), CANARY, CANARY_SIZE);
+    self
+      .guarded
+      .borrow_mut()
+      .push((ptr, layout.size(), self.param.get()));
+    ptr
+  }
+
+  unsafe fn bump(&self, layout: Layout) -> *mut u8 {
+    let start = (self.cursor.get() + layout.align() - 1) & !(layout.align() - 1);
+    if self.cursor.get() != 0 && start + layout.size() <= self.end.get() {
+      self.cursor.set(start + layout.size());
+      return start as *mut u8;
+    }
+    let chunk_layout = Layout::from_size_align(
+      std::cmp::max(CHUNK_SIZE, layout.size()),
+      std::cmp::max(layout.align(), 16),
+    )
+    .unwrap();
+    let chunk = alloc(chunk_layout);
+    if chunk.is_null() {
+      handle_alloc_error(chunk_layout);
+    }
+    self.chunks.borrow_mut().push((chunk, chunk_layout));
+    self.cursor.set(chunk as usize + layout.size());
+    self.end.set(chunk as usize + chunk_layout.size());
+    chunk
+  }
+
+  pub unsafe fn alloc<T>(&self, value: T) -> *mut T {
+    let ptr = self.bump(Layout::new::<T>()) as *mut T;
+    ptr.write(value);
+    ptr
+  }
+
+  pub unsafe fn alloc_slice<T: Copy>(&self, values: &[T]) -> *mut T {
+    let ptr = self.alloc_layout(Layout::array::<T>(values.len()).unwrap()) as *mut T;
+    std::ptr::copy_nonoverlapping(values.as_ptr(), ptr, values.len());
+    ptr
+  }
+
+  pub fn defer_closure(&self, code_ptr: *mut c_void) {
+    self.closures.borrow_mut().push(code_ptr);
+  }
+
+  /// The values allocated next belong to the next param
+  pub fn next_param(&self) {
+    self.param.set(self.param.get() + 1);
+  }
+
+  /// The param of the first allocation whose canaries have been overwritten
+  pub unsafe fn check_canaries(&self) -> Option<usize> {
+    let intact =
+      |ptr: *mut u8| std::slice::from_raw_parts(ptr, CANARY_SIZE) == [CANARY; CANARY_SIZE];
+    self
+      .guarded
+      .borrow()
+      .iter()
+      .find(|(ptr, size, _)| !intact(ptr.sub(CANARY_SIZE)) || !intact(ptr.add(*size)))
+      .map(|(_, _, param)| *param)
+  }
+
+  pub fn contains(&self, ptr: *mut c_void) -> bool {
+    let ptr = ptr as usize;
+    self
+      .chunks
+      .borrow()
+      .iter()
+      .any(|(chunk, layout)| ptr >= *chunk as usize && ptr < *chunk as usize + layout.size())
+  }
+
+  /// Free the closures and the memory of the arena
+  pub unsafe fn release(self, env: &Env) {
+    let _ = untrack_arena(env, &self);
+    self
+      .closures
+      .borrow()
+      .iter()
+      .for_each(|code_ptr| free_closure(env, *code_ptr));
+    if self.hardened {
+      // the memory is poisoned and kept aside for a while so a use after free reads the poison
+      let chunks = std::mem::take(&mut *self.chunks.borrow_mut());
+      chunks
+        .iter()
+        .for_each(|(chunk, layout)| std::ptr::write_bytes(*chunk, POISON, layout.size()));
+      let _ = with_instance_data(env, |data| data.quarantine.extend(chunks));
+    }
+  }
+}
+
+impl Drop for Arena {
+  fn drop(&mut self) {
+    self
+      .chunks
+      .get_mut()
+      .drain(..)
+      .for_each(|(chunk, layout)| unsafe { dealloc(chunk, layout) });
+  }
+}
+
+/// The chunks of the released hardened arenas, they are deallocated in release order once the
+/// quarantine is full
+#[derive(Default)]
+pub struct Quarantine {
+  chunks: VecDeque<(*mut u8, Layout)>,
+  size: usize,
+}
+
+impl Quarantine {
+  /// Whether `ptr` points into the memory of a released hardened arena
+  pub fn contains(&self, ptr: *mut c_void) -> bool {
+    let ptr = ptr as usize;
+    self
+      .chunks
+      .iter()
+      .any(|(chunk, layout)| ptr >= *chunk as usize && ptr < *chunk as usize + layout.size())
+  }
+
+  fn extend(&mut self, chunks: Vec<(*mut u8, Layout)>) {
+    chunks.into_iter().for_each(|(chunk, layout)| {
+      self.size += layout.size();
+      self.chunks.push_back((chunk, layout));
+    });
+    while self.size > QUARANTINE_SIZE {
+      let (chunk, layout) = self.chunks.pop_front().unwrap();
+      self.size -= layout.size();
+      unsafe { dealloc(chunk, layout) };
+    }
+  }
+}
+
+impl Drop for Quarantine {
+  fn drop(&mut self) {
+    self
+      .chunks
+      .drain(..)
+      .for_each(|(chunk, layout)| unsafe { dealloc(chunk, layout) });
+  }
+}
+
+/// Surround the arrays, structs and strings allocated for call arguments with canaries which are
+/// checked after the call, so a function writing out of their bounds throws instead of corrupting
+/// the heap. The memory of the arguments, of withScope and of createPointer is poisoned when it is
+/// released. This is a debug mode, createPointer and freePointer are much slower.
+#[napi]
+pub fn set_memory_hardening(env: Env, enabled: bool) -> napi::Result<()> {
+  Ok(with_instance_data(&env, |data| {
+    data.hardened = enabled;
+    if !enabled {
+      data.quarantine = Quarantine::default();
+    }
+  })?)
+}
+
+// the helpers below allocate in the arena when there is one, otherwise on the heap where the memory
+// is freed by free_rs_pointer_memory, every allocation is recorded for the allocation tracking
+
+pub unsafe fn alloc_value<T>(arena: Option<&Arena>, value: T) -> *mut c_void {
+  let ptr = match arena {
+    Some(arena) => arena.alloc(value) as *mut c_void,
+    None => Box::into_raw(Box::new(value)) as *mut c_void,
+  };
+  record_allocation(ptr);
+  ptr
+}
+
+pub unsafe fn alloc_layout(arena: Option<&Arena>, layout: Layout) -> *mut c_void {
+  let ptr = match arena {
+    Some(arena) => arena.alloc_layout(layout) as *mut c_void,
+    None => alloc(layout) as *mut c_void,
+  };
+  record_allocation(ptr);
+  ptr
+}
+
+pub unsafe fn alloc_vec<T: Copy>(arena: Option<&Arena>, vec: Vec<T>) -> *const T {
+  // an empty array doesn't allocate
+  let is_empty = vec.is_empty();
+  let ptr = match arena {
+    Some(arena) => arena.alloc_slice(&vec),
+    None => {
+      let ptr = vec.as_ptr();
+      std::mem::forget(vec);
+      ptr
+    }
+  };
+  if !is_empty {
+    record_allocation(ptr);
+  }
+  ptr
+}
+
+pub unsafe fn alloc_c_string(arena: Option<&Arena>, val: String) -> Result<*const c_char> {
+  let c_string = string_to_c_string(val)?;
+  let ptr = match arena {
+    Some(arena) => arena.alloc_slice(c_string.as_bytes_with_nul()) as *const c_char,
+    None => c_string.into_raw(),
+  };
+  record_allocation(ptr);
+  Ok(ptr)
+}
+
+pub unsafe fn alloc_c_w_string(arena: Option<&Arena>, val: String) -> Result<*const WideChar> {
+  let c_w_string = string_to_c_w_string(val)?;
+  let ptr = match arena {
+    Some(arena) => arena.alloc_slice(c_w_string.as_slice_with_nul()),
+    None => c_w_string.into_raw(),
+  };
+  record_allocation(ptr);
+  Ok(ptr)
+}
+
+unsafe fn alloc_code_units<T: Copy>(arena: Option<&Arena>, units: Vec<T>) -> *const c_void {
+  let ptr = match arena {
+    Some(arena) => arena.alloc_slice(&units) as *const c_void,
+    // boxed so free_encoded_string knows the capacity from the null terminator
+    None => Box::into_raw(units.into_boxed_slice()) as *const T as *const c_void,
+  };
+  record_allocation(ptr);
+  ptr
+}
+
+pub unsafe fn alloc_encoded_string(arena: Option<&Arena>, val: EncodedString) -> *const c_void {
+  match val {
+    EncodedString::U8(units) => alloc_code_units(arena, units),
+    EncodedString::U16(units) => alloc_code_units(arena, units),
+    EncodedString::U32(units) => alloc_code_units(arena, units),
+  }
+}
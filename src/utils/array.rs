@@ -1,24 +1,48 @@
+use crate::datatype::string::parse_string_encoding;
 use crate::define::{
-  RsArgsValue, ARRAY_LENGTH_TAG, ARRAY_STRUCT_ITEM_TYPE_TAG, ARRAY_TYPE_TAG, FFIARRARYDESC,
+  FFIError, RsArgsValue, TypedArrayOutput, ARRAY_LENGTH_TAG, ARRAY_STRUCT_ITEM_TYPE_TAG,
+  ARRAY_TYPED_ARRAY_TAG, ARRAY_TYPE_TAG, FFIARRARYDESC, STRING_ENCODING_TAG,
 };
+use crate::utils::error::Result;
 use indexmap::IndexMap;
 
-pub fn get_array_desc(obj: &IndexMap<String, RsArgsValue>) -> FFIARRARYDESC {
+pub fn get_array_desc(obj: &IndexMap<String, RsArgsValue>) -> Result<FFIARRARYDESC> {
   let (mut array_len, mut array_type, mut struct_item_type) = (0, 0, None);
-  if let RsArgsValue::I32(number) = obj.get(ARRAY_LENGTH_TAG).unwrap() {
+  if let Some(RsArgsValue::I32(number)) = obj.get(ARRAY_LENGTH_TAG) {
     array_len = *number as usize
   }
-  if let RsArgsValue::I32(number) = obj.get(ARRAY_TYPE_TAG).unwrap() {
+  if let Some(RsArgsValue::I32(number)) = obj.get(ARRAY_TYPE_TAG) {
     array_type = *number
   }
   if let Some(RsArgsValue::Object(item_type)) = obj.get(ARRAY_STRUCT_ITEM_TYPE_TAG) {
     struct_item_type = Some(item_type.clone());
   }
 
-  let array_type = array_type.try_into().unwrap();
-  FFIARRARYDESC {
+  // checked by check_type_desc
+  let typed_array = match obj.get(ARRAY_TYPED_ARRAY_TAG) {
+    Some(RsArgsValue::String(output)) if output == "copy" => Some(TypedArrayOutput::Copy),
+    Some(RsArgsValue::String(output)) if output == "view" => Some(TypedArrayOutput::View),
+    _ => None,
+  };
+  let encoding = match obj.get(STRING_ENCODING_TAG) {
+    Some(RsArgsValue::String(name)) => Some(parse_string_encoding(name)),
+    _ => None,
+  };
+
+  let array_type = array_type.try_into()?;
+  Ok(FFIARRARYDESC {
     array_len,
     array_type,
     struct_item_type,
-  }
+    typed_array,
+    encoding,
+  })
+}
+
+pub fn get_struct_item_type(
+  struct_item_type: &Option<IndexMap<String, RsArgsValue>>,
+) -> Result<&IndexMap<String, RsArgsValue>> {
+  struct_item_type.as_ref().ok_or_else(|| {
+    FFIError::UnsupportedValueType("Missing struct item type of StructArray".to_string()).into()
+  })
 }
@@ -1,17 +1,27 @@
-use super::get_array_desc;
+use super::arena::{
+  alloc_c_string, alloc_c_w_string, alloc_encoded_string, alloc_layout, alloc_value, alloc_vec,
+  Arena,
+};
+use super::error::{add_error_context, throw_uncaught, to_js_error};
+use super::instance::with_instance_data;
 use super::js_value::create_js_value_unchecked;
 use super::object_utils::calculate_struct_size;
+use super::pointer::get_ffi_abi;
+use super::tracking::{track_allocation, track_value_allocations};
+use super::{get_array_desc, get_struct_item_type};
 use crate::datatype::array::ToRsArray;
 use crate::datatype::buffer::get_safe_buffer;
 use crate::datatype::create_struct::generate_c_struct;
 use crate::datatype::function::get_rs_value_from_pointer;
 use crate::datatype::pointer::*;
 use crate::datatype::restore_struct::{create_rs_struct_from_pointer, rs_value_to_js_unknown};
-use crate::datatype::string::{js_string_to_string, string_to_c_string, string_to_c_w_string};
+use crate::datatype::string::*;
+use crate::datatype::typed_array::*;
 use crate::define::*;
 use indexmap::IndexMap;
 use libc::{c_char, c_double, c_float, c_int, c_uchar, c_void};
 
+use super::error::{Error, Result};
 use napi::threadsafe_function::{
   ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode,
 };
@@ -19,22 +29,30 @@ use napi::{
   bindgen_prelude::*, Env, JsBigInt, JsBoolean, JsBuffer, JsExternal, JsNumber, JsObject, JsString,
   JsUnknown, NapiRaw,
 };
-use std::alloc::{alloc, Layout};
-use std::collections::HashMap;
+use std::alloc::{dealloc, Layout};
 use std::ffi::CStr;
 use std::rc::Rc;
 use widestring::{WideCString, WideChar};
 
-pub unsafe fn get_js_external_wrap_data(env: &Env, js_external: JsExternal) -> Result<*mut c_void> {
-  use std::any::TypeId;
-  #[repr(C)]
-  pub struct TaggedObject<T> {
-    type_id: TypeId,
-    pub(crate) object: Option<T>,
-  }
+// the layout napi-rs wraps the data of an External in
+#[repr(C)]
+pub struct TaggedObject<T> {
+  type_id: std::any::TypeId,
+  pub(crate) object: Option<T>,
+}
+
+#[allow(deprecated)]
+pub unsafe fn get_js_external_tagged_object(
+  env: &Env,
+  js_external: &JsExternal,
+) -> *mut TaggedObject<*mut c_void> {
   let mut unknown_tagged_object = std::ptr::null_mut();
   sys::napi_get_value_external(env.raw(), js_external.raw(), &mut unknown_tagged_object);
-  let tagged_object = unknown_tagged_object as *mut TaggedObject<*mut c_void>;
+  unknown_tagged_object as *mut TaggedObject<*mut c_void>
+}
+
+pub unsafe fn get_js_external_wrap_data(env: &Env, js_external: JsExternal) -> Result<*mut c_void> {
+  let tagged_object = get_js_external_tagged_object(env, &js_external);
   let p = (*tagged_object).object.as_mut().unwrap();
   Ok(*p)
 }
@@ -56,6 +74,9 @@ pub fn get_ffi_tag(obj: &IndexMap<String, RsArgsValue>) -> FFITypeTag {
     if ffitypetag == &FFITypeTag::Function.into() {
       return FFITypeTag::Function;
     }
+    if ffitypetag == &FFITypeTag::String.into() {
+      return FFITypeTag::String;
+    }
     FFITypeTag::Unknown
   } else {
     FFITypeTag::Unknown
@@ -63,7 +84,7 @@ pub fn get_ffi_tag(obj: &IndexMap<String, RsArgsValue>) -> FFITypeTag {
 }
 
 pub fn get_func_desc(obj: &IndexMap<String, RsArgsValue>) -> FFIFUNCDESC {
-  let need_free = if let RsArgsValue::Boolean(val) = obj.get(FUNCTION_FREE_TAG).unwrap() {
+  let need_free = if let Some(RsArgsValue::Boolean(val)) = obj.get(FUNCTION_FREE_TAG) {
     *val
   } else {
     false
@@ -72,179 +93,384 @@ pub fn get_func_desc(obj: &IndexMap<String, RsArgsValue>) -> FFIFUNCDESC {
 }
 
 pub unsafe fn get_arg_values(
+  env: &Env,
   params_type: Rc<Vec<RsArgsValue>>,
   params_value: Vec<JsUnknown>,
 ) -> Result<Vec<RsArgsValue>> {
   if params_type.len() != params_value.len() {
     return Err(
-      FFIError::Panic(format!(
-        "params_type length is not equal with params_value length"
+      FFIError::ArgCountMismatch(format!(
+        "params_type length is not equal with params_value length, expected {} params but received {}",
+        params_type.len(),
+        params_value.len()
       ))
       .into(),
     );
   }
+  // check every value before converting any of them
+  let params_value = params_type
+    .iter()
+    .zip(params_value)
+    .enumerate()
+    .map(|(index, (param, value))| {
+      check_arg_value(env, param, value, index)
+        .map_err(|err| add_error_context(err, |context| context.arg_index = Some(index)))
+    })
+    .collect::<Result<Vec<JsUnknown>>>()?;
   params_type
     .iter()
     .zip(params_value.into_iter())
-    .map(|(param, value)| {
-      let res = match param {
-        RsArgsValue::I32(number) => {
-          let param_data_type = (*number).try_into()?;
-          match param_data_type {
-            BasicDataType::U8 => {
-              let arg_val: u32 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
-              RsArgsValue::U8(arg_val as u8)
-            }
-            BasicDataType::I16 => {
-              let arg_val: i32 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
-              RsArgsValue::I16(arg_val as i16)
-            }
-            BasicDataType::I32 => {
-              let arg_val: i32 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
-              RsArgsValue::I32(arg_val)
-            }
-            BasicDataType::U32 => {
-              let arg_val: u32 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
-              RsArgsValue::U32(arg_val)
-            }
-            BasicDataType::I64 => {
-              let arg_val: i64 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
-              RsArgsValue::I64(arg_val)
-            }
-            BasicDataType::BigInt => {
-              let arg_val: i64 = create_js_value_unchecked::<JsBigInt>(value)?.try_into()?;
-              RsArgsValue::I64(arg_val)
-            }
-            BasicDataType::U64 => {
-              let arg_val: i64 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
-              RsArgsValue::U64(arg_val as u64)
-            }
-            BasicDataType::Float => {
-              let arg_val: f64 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
-              RsArgsValue::Float(arg_val as f32)
-            }
-            BasicDataType::Double => {
-              let arg_val: f64 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
-              RsArgsValue::Double(arg_val)
-            }
-            BasicDataType::String => {
-              let arg_val: String =
-                js_string_to_string(create_js_value_unchecked::<JsString>(value)?)?;
-              RsArgsValue::String(arg_val)
-            }
-            BasicDataType::WString => {
-              let arg_val: String =
-                js_string_to_string(create_js_value_unchecked::<JsString>(value)?)?;
-              RsArgsValue::WString(arg_val)
-            }
-            BasicDataType::Boolean => {
-              let arg_val: bool = create_js_value_unchecked::<JsBoolean>(value)?.get_value()?;
-              RsArgsValue::Boolean(arg_val)
+    .enumerate()
+    .map(|(index, (param, value))| {
+      get_arg_value(env, param, value)
+        .map_err(|err| add_error_context(err, |context| context.arg_index = Some(index)))
+    })
+    .collect()
+}
+
+fn invalid_arg(index: usize, reason: String) -> Error {
+  FFIError::InvalidArg(format!("paramsValue[{}] {}", index, reason)).into()
+}
+
+/// Check that the js value matches the declared type, the value is given back to be converted
+unsafe fn check_arg_value(
+  env: &Env,
+  param: &RsArgsValue,
+  value: JsUnknown,
+  index: usize,
+) -> Result<JsUnknown> {
+  let value_type = value.get_type()?;
+  let expected_type = match param {
+    param if is_string_with_length(param) => ValueType::String,
+    RsArgsValue::I32(number) => match (*number).try_into()? {
+      BasicDataType::U8
+      | BasicDataType::I16
+      | BasicDataType::I32
+      | BasicDataType::U32
+      | BasicDataType::I64
+      | BasicDataType::U64
+      | BasicDataType::Float
+      | BasicDataType::Double => ValueType::Number,
+      BasicDataType::BigInt => ValueType::BigInt,
+      BasicDataType::String
+      | BasicDataType::WString
+      | BasicDataType::U16String
+      | BasicDataType::U32String
+      | BasicDataType::Latin1String => ValueType::String,
+      BasicDataType::Boolean => ValueType::Boolean,
+      BasicDataType::External => {
+        // the memory of a TypedArray or DataView is passed as a pointer to it
+        if get_array_view(env, &value)?.is_some() {
+          return Ok(value);
+        }
+        ValueType::External
+      }
+      BasicDataType::Void => return Ok(value),
+    },
+    RsArgsValue::Object(obj) => match get_ffi_tag(obj) {
+      FFITypeTag::Array | FFITypeTag::StackArray => {
+        let FFIARRARYDESC {
+          array_type,
+          array_len,
+          ..
+        } = get_array_desc(obj)?;
+        // a TypedArray or DataView of the items is passed without copying
+        if let Some(typed_array_type) = get_typed_array_type(&array_type) {
+          if let Some(view) = get_array_view(env, &value)? {
+            if view
+              .typed_array_type
+              .is_some_and(|view_type| view_type != typed_array_type)
+            {
+              return Err(invalid_arg(
+                index,
+                format!(
+                  "expected {:?}Array for {:?} but received {:?}Array",
+                  typed_array_type,
+                  array_type,
+                  view.typed_array_type.unwrap()
+                ),
+              ));
             }
-            BasicDataType::Void => RsArgsValue::Void(()),
-            BasicDataType::External => {
-              let js_external: JsExternal = value.try_into()?;
-              RsArgsValue::External(js_external)
+            let value_len = get_view_len(&view, typed_array_type).ok_or_else(|| {
+              invalid_arg(
+                index,
+                format!(
+                  "DataView of {} bytes can't be read as {:?}",
+                  view.byte_length, array_type
+                ),
+              )
+            })?;
+            if obj.get(ARRAY_LENGTH_TAG).is_some() && value_len != array_len {
+              return Err(invalid_arg(
+                index,
+                format!(
+                  "has {} items but the declared length is {}",
+                  value_len, array_len
+                ),
+              ));
             }
+            return Ok(value);
           }
         }
-        RsArgsValue::Object(params_type_object_rs) => {
-          if let FFITypeTag::Array | FFITypeTag::StackArray = get_ffi_tag(&params_type_object_rs) {
-            let array_desc = get_array_desc(&params_type_object_rs);
-            let FFIARRARYDESC {
+        let is_expected_value = if let RefDataType::U8Array = array_type {
+          value.is_buffer()?
+        } else {
+          value.is_array()?
+        };
+        if !is_expected_value {
+          return Err(invalid_arg(
+            index,
+            format!(
+              "expected {} for {:?} but received {}",
+              match array_type {
+                RefDataType::U8Array => "Buffer",
+                RefDataType::StringArray | RefDataType::StructArray => "array",
+                _ => "array, TypedArray or DataView",
+              },
               array_type,
-              struct_item_type,
-              ..
-            } = array_desc;
-            match array_type {
-              RefDataType::U8Array => {
-                let js_buffer: JsBuffer = value.try_into()?;
-                RsArgsValue::U8Array(Some(js_buffer.into_value()?), None)
-              }
-              RefDataType::I16Array => {
-                let js_object = create_js_value_unchecked::<JsObject>(value)?;
-                let arg_val = vec![0; js_object.get_array_length()? as usize]
-                  .iter()
-                  .enumerate()
-                  .map(|(index, _)| {
-                    let js_element: JsNumber = js_object.get_element(index as u32).unwrap();
-                    js_element.get_int32().unwrap() as i16
-                  })
-                  .collect::<Vec<i16>>();
-                RsArgsValue::I16Array(arg_val)
-              }
-              RefDataType::I32Array => {
-                let js_object = create_js_value_unchecked::<JsObject>(value)?;
-                let arg_val = vec![0; js_object.get_array_length()? as usize]
-                  .iter()
-                  .enumerate()
-                  .map(|(index, _)| {
-                    let js_element: JsNumber = js_object.get_element(index as u32).unwrap();
-                    js_element.get_int32().unwrap()
-                  })
-                  .collect::<Vec<i32>>();
-                RsArgsValue::I32Array(arg_val)
-              }
-              RefDataType::FloatArray => {
-                let js_object = create_js_value_unchecked::<JsObject>(value)?;
-                let arg_val = vec![0; js_object.get_array_length()? as usize]
-                  .iter()
-                  .enumerate()
-                  .map(|(index, _)| {
-                    let js_element: JsNumber = js_object.get_element(index as u32).unwrap();
-                    js_element.get_double().unwrap() as f32
-                  })
-                  .collect::<Vec<f32>>();
-                RsArgsValue::FloatArray(arg_val)
-              }
-              RefDataType::DoubleArray => {
-                let js_object = create_js_value_unchecked::<JsObject>(value)?;
-                let arg_val = vec![0; js_object.get_array_length()? as usize]
-                  .iter()
-                  .enumerate()
-                  .map(|(index, _)| {
-                    let js_element: JsNumber = js_object.get_element(index as u32).unwrap();
-                    js_element.get_double().unwrap()
-                  })
-                  .collect::<Vec<f64>>();
-                RsArgsValue::DoubleArray(arg_val)
-              }
-              RefDataType::StringArray => {
-                let js_object = create_js_value_unchecked::<JsObject>(value)?;
-                let arg_val = js_object.to_rs_array()?;
-                RsArgsValue::StringArray(arg_val)
-              }
-              RefDataType::StructArray => {
-                let js_object = create_js_value_unchecked::<JsObject>(value)?;
-                let arg_values = vec![0; js_object.get_array_length()? as usize]
+              value_type
+            ),
+          ));
+        }
+        let (value_len, value) = if let RefDataType::U8Array = array_type {
+          let js_buffer = create_js_value_unchecked::<JsBuffer>(value)?.into_value()?;
+          (js_buffer.len(), js_buffer.into_raw().into_unknown())
+        } else {
+          let js_object = create_js_value_unchecked::<JsObject>(value)?;
+          (
+            js_object.get_array_length()? as usize,
+            js_object.into_unknown(),
+          )
+        };
+        // the declared length is how many items the c side reads
+        if obj.get(ARRAY_LENGTH_TAG).is_some() && value_len != array_len {
+          return Err(invalid_arg(
+            index,
+            format!(
+              "has {} items but the declared length is {}",
+              value_len, array_len
+            ),
+          ));
+        }
+        return Ok(value);
+      }
+      FFITypeTag::Function => ValueType::Function,
+      FFITypeTag::String => ValueType::String,
+      _ => ValueType::Object,
+    },
+    _ => return Ok(value),
+  };
+  if value_type != expected_type {
+    return Err(invalid_arg(
+      index,
+      format!("expected {} but received {}", expected_type, value_type),
+    ));
+  }
+  Ok(value)
+}
+
+unsafe fn get_arg_value(env: &Env, param: &RsArgsValue, value: JsUnknown) -> Result<RsArgsValue> {
+  let res = match param {
+    param if is_string_with_length(param) => {
+      RsArgsValue::EncodedString(string_to_bytes_with_length(js_string_to_string(
+        create_js_value_unchecked::<JsString>(value)?,
+      )?))
+    }
+    RsArgsValue::I32(number) => {
+      let param_data_type = (*number).try_into()?;
+      match param_data_type {
+        BasicDataType::U8 => {
+          let arg_val: u32 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
+          RsArgsValue::U8(arg_val as u8)
+        }
+        BasicDataType::I16 => {
+          let arg_val: i32 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
+          RsArgsValue::I16(arg_val as i16)
+        }
+        BasicDataType::I32 => {
+          let arg_val: i32 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
+          RsArgsValue::I32(arg_val)
+        }
+        BasicDataType::U32 => {
+          let arg_val: u32 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
+          RsArgsValue::U32(arg_val)
+        }
+        BasicDataType::I64 => {
+          let arg_val: i64 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
+          RsArgsValue::I64(arg_val)
+        }
+        BasicDataType::BigInt => {
+          let arg_val: i64 = create_js_value_unchecked::<JsBigInt>(value)?.try_into()?;
+          RsArgsValue::I64(arg_val)
+        }
+        BasicDataType::U64 => {
+          let arg_val: i64 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
+          RsArgsValue::U64(arg_val as u64)
+        }
+        BasicDataType::Float => {
+          let arg_val: f64 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
+          RsArgsValue::Float(arg_val as f32)
+        }
+        BasicDataType::Double => {
+          let arg_val: f64 = create_js_value_unchecked::<JsNumber>(value)?.try_into()?;
+          RsArgsValue::Double(arg_val)
+        }
+        BasicDataType::String => {
+          let arg_val: String = js_string_to_string(create_js_value_unchecked::<JsString>(value)?)?;
+          RsArgsValue::String(arg_val)
+        }
+        BasicDataType::WString => {
+          let arg_val: String = js_string_to_string(create_js_value_unchecked::<JsString>(value)?)?;
+          RsArgsValue::WString(arg_val)
+        }
+        BasicDataType::U16String | BasicDataType::U32String | BasicDataType::Latin1String => {
+          RsArgsValue::EncodedString(js_string_to_encoded_string(
+            create_js_value_unchecked::<JsString>(value)?,
+            &get_string_encoding(param).unwrap(),
+          )?)
+        }
+        BasicDataType::Boolean => {
+          let arg_val: bool = create_js_value_unchecked::<JsBoolean>(value)?.get_value()?;
+          RsArgsValue::Boolean(arg_val)
+        }
+        BasicDataType::Void => RsArgsValue::Void(()),
+        BasicDataType::External => match get_array_view(env, &value)? {
+          Some(view) => RsArgsValue::ArrayView(view.data, view.byte_length),
+          None => {
+            let js_external: JsExternal = value.try_into()?;
+            RsArgsValue::External(js_external)
+          }
+        },
+      }
+    }
+    RsArgsValue::Object(params_type_object_rs) => {
+      if let FFITypeTag::Array | FFITypeTag::StackArray = get_ffi_tag(&params_type_object_rs) {
+        let array_desc = get_array_desc(&params_type_object_rs)?;
+        let FFIARRARYDESC {
+          array_type,
+          struct_item_type,
+          encoding,
+          ..
+        } = array_desc;
+        if get_typed_array_type(&array_type).is_some() {
+          if let Some(view) = get_array_view(env, &value)? {
+            return Ok(RsArgsValue::ArrayView(view.data, view.byte_length));
+          }
+        }
+        match array_type {
+          RefDataType::U8Array => {
+            let js_buffer: JsBuffer = value.try_into()?;
+            RsArgsValue::U8Array(Some(js_buffer.into_value()?), None)
+          }
+          RefDataType::I16Array => {
+            let js_object = create_js_value_unchecked::<JsObject>(value)?;
+            let arg_val = vec![0; js_object.get_array_length()? as usize]
+              .iter()
+              .enumerate()
+              .map(|(index, _)| {
+                let js_element: JsNumber = js_object.get_element(index as u32)?;
+                Ok(js_element.get_int32()? as i16)
+              })
+              .collect::<Result<Vec<i16>>>()?;
+            RsArgsValue::I16Array(arg_val)
+          }
+          RefDataType::I32Array => {
+            let js_object = create_js_value_unchecked::<JsObject>(value)?;
+            let arg_val = vec![0; js_object.get_array_length()? as usize]
+              .iter()
+              .enumerate()
+              .map(|(index, _)| {
+                let js_element: JsNumber = js_object.get_element(index as u32)?;
+                Ok(js_element.get_int32()?)
+              })
+              .collect::<Result<Vec<i32>>>()?;
+            RsArgsValue::I32Array(arg_val)
+          }
+          RefDataType::I64Array => {
+            let js_object = create_js_value_unchecked::<JsObject>(value)?;
+            let arg_val = vec![0; js_object.get_array_length()? as usize]
+              .iter()
+              .enumerate()
+              .map(|(index, _)| {
+                let js_element: JsBigInt = js_object.get_element(index as u32)?;
+                Ok(js_element.try_into()?)
+              })
+              .collect::<Result<Vec<i64>>>()?;
+            RsArgsValue::I64Array(arg_val)
+          }
+          RefDataType::FloatArray => {
+            let js_object = create_js_value_unchecked::<JsObject>(value)?;
+            let arg_val = vec![0; js_object.get_array_length()? as usize]
+              .iter()
+              .enumerate()
+              .map(|(index, _)| {
+                let js_element: JsNumber = js_object.get_element(index as u32)?;
+                Ok(js_element.get_double()? as f32)
+              })
+              .collect::<Result<Vec<f32>>>()?;
+            RsArgsValue::FloatArray(arg_val)
+          }
+          RefDataType::DoubleArray => {
+            let js_object = create_js_value_unchecked::<JsObject>(value)?;
+            let arg_val = vec![0; js_object.get_array_length()? as usize]
+              .iter()
+              .enumerate()
+              .map(|(index, _)| {
+                let js_element: JsNumber = js_object.get_element(index as u32)?;
+                Ok(js_element.get_double()?)
+              })
+              .collect::<Result<Vec<f64>>>()?;
+            RsArgsValue::DoubleArray(arg_val)
+          }
+          RefDataType::StringArray => {
+            let js_object = create_js_value_unchecked::<JsObject>(value)?;
+            let arg_val: Vec<String> = js_object.to_rs_array()?;
+            match encoding {
+              Some(encoding) => RsArgsValue::EncodedStringArray(
+                arg_val
                   .iter()
-                  .enumerate()
-                  .map(|(index, _)| {
-                    let js_element: JsObject = js_object.get_element(index as u32).unwrap();
-                    let struct_item_type = struct_item_type.as_ref().unwrap();
-                    let index_map = get_params_value_rs_struct(struct_item_type, &js_element);
-                    index_map.unwrap()
-                  })
-                  .collect();
-                RsArgsValue::StructArray(arg_values)
-              }
+                  .map(|str| encode_string(str, &encoding))
+                  .collect::<Result<Vec<_>>>()?,
+              ),
+              None => RsArgsValue::StringArray(arg_val),
             }
-          } else if let FFITypeTag::Function = get_ffi_tag(&params_type_object_rs) {
-            let params_val_function: JsFunction = value.try_into()?;
-            RsArgsValue::Function(params_type_object_rs.clone(), params_val_function)
-          } else {
-            // struct
-            let params_value_object = create_js_value_unchecked::<JsObject>(value)?;
-            let index_map =
-              get_params_value_rs_struct(params_type_object_rs, &params_value_object)?;
-            RsArgsValue::Object(index_map)
+          }
+          RefDataType::StructArray => {
+            let js_object = create_js_value_unchecked::<JsObject>(value)?;
+            let arg_values = vec![0; js_object.get_array_length()? as usize]
+              .iter()
+              .enumerate()
+              .map(|(index, _)| {
+                let js_element: JsObject = js_object.get_element(index as u32)?;
+                let struct_item_type = get_struct_item_type(&struct_item_type)?;
+                get_params_value_rs_struct(struct_item_type, &js_element)
+              })
+              .collect::<Result<Vec<_>>>()?;
+            RsArgsValue::StructArray(arg_values)
           }
         }
-        _ => panic!("unsupported params type {:?}", param),
-      };
-      Ok(res)
-    })
-    .collect()
+      } else if let FFITypeTag::String = get_ffi_tag(params_type_object_rs) {
+        RsArgsValue::EncodedString(js_string_to_encoded_string(
+          create_js_value_unchecked::<JsString>(value)?,
+          &get_string_encoding(param).unwrap(),
+        )?)
+      } else if let FFITypeTag::Function = get_ffi_tag(&params_type_object_rs) {
+        let params_val_function: JsFunction = value.try_into()?;
+        RsArgsValue::Function(params_type_object_rs.clone(), params_val_function)
+      } else {
+        // struct
+        let params_value_object = create_js_value_unchecked::<JsObject>(value)?;
+        let index_map = get_params_value_rs_struct(params_type_object_rs, &params_value_object)?;
+        RsArgsValue::Object(index_map)
+      }
+    }
+    _ => {
+      return Err(
+        FFIError::UnsupportedValueType(format!("unsupported params type {:?}", param)).into(),
+      )
+    }
+  };
+  Ok(res)
 }
 
 #[macro_export]
@@ -282,307 +508,379 @@ macro_rules! match_args_len {
         }
     };
 }
+/// Store the values in C layout, the memory is owned by `arena` when there is one, otherwise it is
+/// freed by free_rs_pointer_memory
 pub unsafe fn get_value_pointer(
   env: &Env,
   params_type: Rc<Vec<RsArgsValue>>,
   arg_values: Vec<RsArgsValue>,
+  arena: Option<&Arena>,
 ) -> Result<Vec<*mut c_void>> {
-  params_type
+  let arg_values_c_void = params_type
     .iter()
     .zip(arg_values.into_iter())
     .map(|(arg_type, val)| {
-      match val {
-        RsArgsValue::External(val) => {
-          Ok(Box::into_raw(Box::new(get_js_external_wrap_data(&env, val)?)) as *mut c_void)
-        }
-        RsArgsValue::U8(val) => Ok(Box::into_raw(Box::new(val)) as *mut c_void),
-        RsArgsValue::I16(val) => Ok(Box::into_raw(Box::new(val)) as *mut c_void),
-        RsArgsValue::I32(val) => Ok(Box::into_raw(Box::new(val)) as *mut c_void),
-        RsArgsValue::I64(val) | RsArgsValue::BigInt(val) => {
-          Ok(Box::into_raw(Box::new(val)) as *mut c_void)
-        }
-        RsArgsValue::U64(val) => Ok(Box::into_raw(Box::new(val)) as *mut c_void),
-        RsArgsValue::U32(val) => Ok(Box::into_raw(Box::new(val)) as *mut c_void),
-        RsArgsValue::String(val) => {
-          let c_string = string_to_c_string(val);
-          let ptr = c_string.as_ptr();
-          std::mem::forget(c_string);
-          Ok(Box::into_raw(Box::new(ptr)) as *mut c_void)
-        }
-        RsArgsValue::WString(val) => {
-          let c_w_string = string_to_c_w_string(val);
-          let ptr = c_w_string.as_ptr();
-          std::mem::forget(c_w_string);
-          Ok(Box::into_raw(Box::new(ptr)) as *mut c_void)
-        }
-        RsArgsValue::Float(val) => {
-          let c_float = Box::new(val);
-          Ok(Box::into_raw(c_float) as *mut c_void)
-        }
-        RsArgsValue::Double(val) => {
-          let c_double = Box::new(val);
-          Ok(Box::into_raw(c_double) as *mut c_void)
+      track_value_allocations(env, arg_type, || match val {
+        RsArgsValue::EncodedString(EncodedString::U8(bytes)) if is_string_with_length(arg_type) => {
+          let len = bytes.len() - 1;
+          let data = alloc_encoded_string(arena, EncodedString::U8(bytes));
+          Ok(alloc_value(arena, CStringWithLength { data, len }))
         }
+        RsArgsValue::External(val) => Ok(alloc_value(arena, get_js_external_wrap_data(&env, val)?)),
+        RsArgsValue::U8(val) => Ok(alloc_value(arena, val)),
+        RsArgsValue::I16(val) => Ok(alloc_value(arena, val)),
+        RsArgsValue::I32(val) => Ok(alloc_value(arena, val)),
+        RsArgsValue::I64(val) | RsArgsValue::BigInt(val) => Ok(alloc_value(arena, val)),
+        RsArgsValue::U64(val) => Ok(alloc_value(arena, val)),
+        RsArgsValue::U32(val) => Ok(alloc_value(arena, val)),
+        RsArgsValue::String(val) => Ok(alloc_value(arena, alloc_c_string(arena, val)?)),
+        RsArgsValue::WString(val) => Ok(alloc_value(arena, alloc_c_w_string(arena, val)?)),
+        RsArgsValue::EncodedString(val) => Ok(alloc_value(arena, alloc_encoded_string(arena, val))),
+        RsArgsValue::Float(val) => Ok(alloc_value(arena, val)),
+        RsArgsValue::Double(val) => Ok(alloc_value(arena, val)),
         RsArgsValue::U8Array(buffer, _) => {
           let buffer = buffer.unwrap();
           let ptr = buffer.as_ptr();
           std::mem::forget(buffer);
-          Ok(Box::into_raw(Box::new(ptr)) as *mut c_void)
-        }
-        RsArgsValue::I16Array(val) => {
-          let ptr = val.as_ptr();
-          std::mem::forget(val);
-          Ok(Box::into_raw(Box::new(ptr)) as *mut c_void)
-        }
-        RsArgsValue::I32Array(val) => {
-          let ptr = val.as_ptr();
-          std::mem::forget(val);
-          Ok(Box::into_raw(Box::new(ptr)) as *mut c_void)
-        }
-        RsArgsValue::DoubleArray(val) => {
-          let ptr = val.as_ptr();
-          std::mem::forget(val);
-          Ok(Box::into_raw(Box::new(ptr)) as *mut c_void)
-        }
-        RsArgsValue::FloatArray(val) => {
-          let ptr = val.as_ptr();
-          std::mem::forget(val);
-          Ok(Box::into_raw(Box::new(ptr)) as *mut c_void)
+          Ok(alloc_value(arena, ptr))
         }
+        RsArgsValue::I16Array(val) => Ok(alloc_value(arena, alloc_vec(arena, val))),
+        RsArgsValue::I32Array(val) => Ok(alloc_value(arena, alloc_vec(arena, val))),
+        RsArgsValue::I64Array(val) => Ok(alloc_value(arena, alloc_vec(arena, val))),
+        RsArgsValue::ArrayView(data, byte_length) => match arg_type {
+          // the js memory is only borrowed for the duration of a call, a pointer which may outlive
+          // it owns a copy of the array. An External only points to the memory
+          RsArgsValue::Object(arg_type) if !arena.is_some_and(|arena| arena.is_call()) => {
+            let array_type = get_array_desc(arg_type)?.array_type;
+            Ok(alloc_value(
+              arena,
+              copy_array_view(arena, &array_type, data, byte_length)?,
+            ))
+          }
+          _ => Ok(alloc_value(arena, data)),
+        },
+        RsArgsValue::DoubleArray(val) => Ok(alloc_value(arena, alloc_vec(arena, val))),
+        RsArgsValue::FloatArray(val) => Ok(alloc_value(arena, alloc_vec(arena, val))),
         RsArgsValue::StringArray(val) => {
           let c_char_vec: Vec<*const c_char> = val
             .into_iter()
-            .map(|str| {
-              let c_string = string_to_c_string(str);
-              let ptr = c_string.as_ptr();
-              std::mem::forget(c_string);
-              ptr
-            })
+            .map(|str| alloc_c_string(arena, str))
+            .collect::<Result<_>>()?;
+          Ok(alloc_value(arena, alloc_vec(arena, c_char_vec)))
+        }
+        RsArgsValue::EncodedStringArray(val) => {
+          let str_vec: Vec<*const c_void> = val
+            .into_iter()
+            .map(|str| alloc_encoded_string(arena, str))
             .collect();
-          let ptr = c_char_vec.as_ptr();
-          std::mem::forget(c_char_vec);
-          Ok(Box::into_raw(Box::new(ptr)) as *mut c_void)
+          Ok(alloc_value(arena, alloc_vec(arena, str_vec)))
         }
         RsArgsValue::StructArray(val) => {
           if let RsArgsValue::Object(arg_type) = arg_type {
-            let array_desc = get_array_desc(arg_type);
+            let array_desc = get_array_desc(arg_type)?;
             let FFIARRARYDESC {
               struct_item_type,
               array_len,
               ..
             } = array_desc;
-            let struct_item_type = struct_item_type
-              .as_ref()
-              .ok_or_else(|| FFIError::Panic("Missing struct item type".to_string()))?;
+            let struct_item_type = struct_item_type.as_ref().ok_or_else(|| {
+              FFIError::UnsupportedValueType("Missing struct item type".to_string())
+            })?;
 
             let is_stack_struct = get_ffi_tag(struct_item_type) == FFITypeTag::StackStruct;
 
             if is_stack_struct {
-              let (struct_size, align) = calculate_struct_size(struct_item_type);
+              let (struct_size, align) = calculate_struct_size(struct_item_type)?;
               let mut head_ptr = None;
-              let mut current_ptr =
-                alloc(Layout::from_size_align(struct_size * array_len, align).unwrap())
-                  as *mut c_void;
+              let mut current_ptr = alloc_layout(
+                arena,
+                Layout::from_size_align(struct_size * array_len, align).unwrap(),
+              );
 
               for item in val {
                 let struct_ptr =
-                  generate_c_struct(&env, struct_item_type, item, Some(current_ptr))?;
+                  generate_c_struct(&env, struct_item_type, item, Some(current_ptr), arena)?;
                 if head_ptr.is_none() {
                   head_ptr = Some(struct_ptr);
                 }
-                current_ptr = struct_ptr.offset(struct_size as isize);
+                current_ptr = struct_ptr.add(struct_size);
               }
-              Ok(Box::into_raw(Box::new(head_ptr.unwrap())) as *mut c_void)
+              Ok(alloc_value(arena, head_ptr.unwrap()))
             } else {
               let struct_ptrs: Vec<_> = val
                 .into_iter()
-                .map(|item| generate_c_struct(&env, struct_item_type, item, None))
+                .map(|item| generate_c_struct(&env, struct_item_type, item, None, arena))
                 .collect::<Result<Vec<_>>>()?;
-              let ptr = struct_ptrs.as_ptr();
-              std::mem::forget(struct_ptrs);
-
-              Ok(Box::into_raw(Box::new(ptr as *mut c_void)) as *mut c_void)
+              Ok(alloc_value(arena, alloc_vec(arena, struct_ptrs)))
             }
           } else {
-            Err(FFIError::Panic(format!("uncorrect params type {:?}", arg_type)).into())
+            Err(
+              FFIError::UnsupportedValueType(format!("uncorrect params type {:?}", arg_type))
+                .into(),
+            )
           }
         }
-        RsArgsValue::Boolean(val) => {
-          let c_bool = Box::new(val);
-          Ok(Box::into_raw(c_bool) as *mut c_void)
-        }
-        RsArgsValue::Void(_) => {
-          Ok(Box::into_raw(Box::new(std::ptr::null_mut() as *mut c_void)) as *mut c_void)
-        }
+        RsArgsValue::Boolean(val) => Ok(alloc_value(arena, val)),
+        RsArgsValue::Void(_) => Ok(alloc_value(arena, std::ptr::null_mut() as *mut c_void)),
         RsArgsValue::Object(val) => {
           if let RsArgsValue::Object(arg_type_rs) = arg_type {
             let is_stack_struct = get_ffi_tag(arg_type_rs) == FFITypeTag::StackStruct;
             Ok(if is_stack_struct {
-              generate_c_struct(&env, &arg_type_rs, val, None)?
+              generate_c_struct(&env, &arg_type_rs, val, None, arena)?
             } else {
-              Box::into_raw(Box::new(generate_c_struct(&env, &arg_type_rs, val, None)?))
-                as *mut c_void
+              alloc_value(
+                arena,
+                generate_c_struct(&env, &arg_type_rs, val, None, arena)?,
+              )
             })
           } else {
-            Err(FFIError::Panic(format!("uncorrect params type {:?}", arg_type)).into())
+            Err(
+              FFIError::UnsupportedValueType(format!("uncorrect params type {:?}", arg_type))
+                .into(),
+            )
           }
         }
         RsArgsValue::Function(func_desc, js_function) => {
-          use libffi::low;
-          use libffi::middle::*;
-          let func_args_type = func_desc.get(PARAMS_TYPE).unwrap().clone();
+          let need_free = get_func_desc(&func_desc).need_free;
+          let code_ptr = create_closure(env, func_desc, js_function)?;
+          if let (Some(arena), true) = (arena, need_free) {
+            arena.defer_closure(code_ptr);
+          }
+          Ok(code_ptr)
+        }
+      })
+    })
+    .enumerate()
+    .map(|(index, res)| {
+      res.map_err(|err| add_error_context(err, |context| context.arg_index = Some(index)))
+    })
+    .inspect(|_| {
+      if let Some(arena) = arena {
+        arena.next_param()
+      }
+    })
+    .collect::<Result<Vec<*mut c_void>>>()?;
+  Ok(arg_values_c_void)
+}
 
-          let func_ret_type = if func_desc.get(RET_TYPE).is_some() {
-            func_desc.get(RET_TYPE).unwrap().clone()
-          } else {
-            RsArgsValue::I32(DataType::Void as i32)
-          };
-          let free_c_params_memory = func_desc.get(FREE_FUNCTION_TAG).unwrap().clone();
-          let tsfn: ThreadsafeFunction<Vec<RsArgsValue>, ErrorStrategy::Fatal> = (&js_function)
-            .create_threadsafe_function(
-              0,
-              move |ctx: ThreadSafeCallContext<Vec<RsArgsValue>>| {
-                let js_call_params: Vec<JsUnknown> = ctx
-                  .value
-                  .into_iter()
-                  .map(|rs_args| rs_value_to_js_unknown(&ctx.env, rs_args))
-                  .collect::<Result<Vec<JsUnknown>, _>>()?;
-                Ok(js_call_params)
-              },
-            )?;
+pub fn is_string_with_length(type_desc: &RsArgsValue) -> bool {
+  matches!(type_desc, RsArgsValue::I32(number) if *number == DataType::StringWithLength as i32)
+}
 
-          unsafe extern "C" fn lambda_callback<F: Fn((Vec<*mut c_void>, *mut c_void))>(
-            _cif: &low::ffi_cif,
-            result: &mut c_void,
-            args: *const *const c_void,
-            userdata: &F,
-          ) {
-            let params: Vec<*mut c_void> = (0.._cif.nargs)
-              .map(|index| *args.offset(index as isize) as *mut c_void)
-              .collect();
+/// The argument values of a call from the values of its params, the data pointer and the length of
+/// a StringWithLength are two arguments
+pub unsafe fn get_call_arg_values(
+  params_type: &[RsArgsValue],
+  arg_values_c_void: Vec<*mut c_void>,
+) -> Vec<*mut c_void> {
+  params_type
+    .iter()
+    .zip(arg_values_c_void)
+    .flat_map(|(param_type, ptr)| {
+      if is_string_with_length(param_type) {
+        let string_with_length = ptr as *mut CStringWithLength;
+        vec![
+          std::ptr::addr_of_mut!((*string_with_length).data) as *mut c_void,
+          std::ptr::addr_of_mut!((*string_with_length).len) as *mut c_void,
+        ]
+      } else {
+        vec![ptr]
+      }
+    })
+    .collect()
+}
 
-            userdata((params, result));
-          }
+// turn a JS function into a C function pointer, returns a pointer to the storage holding the code
+// pointer which is also the key of the closure in closure_map
+pub unsafe fn create_closure(
+  env: &Env,
+  func_desc: IndexMap<String, RsArgsValue>,
+  js_function: JsFunction,
+) -> Result<*mut c_void> {
+  use libffi::low;
+  use libffi::middle::*;
+  let abi = get_ffi_abi(match func_desc.get(ABI_TAG) {
+    Some(RsArgsValue::String(abi)) => Some(abi),
+    _ => None,
+  })?;
+  let func_args_type = func_desc
+    .get(PARAMS_TYPE)
+    .ok_or_else(|| FFIError::UnsupportedValueType(format!("missing {}", PARAMS_TYPE)))?
+    .clone();
 
-          let tsfn_call_context = TsFnCallContext {
-            tsfn,
-            lambda: None,
-            closure: None,
-          };
+  let func_ret_type = if func_desc.get(RET_TYPE).is_some() {
+    func_desc.get(RET_TYPE).unwrap().clone()
+  } else {
+    RsArgsValue::I32(DataType::Void as i32)
+  };
+  let free_c_params_memory = func_desc
+    .get(FREE_FUNCTION_TAG)
+    .cloned()
+    .unwrap_or(RsArgsValue::Boolean(false));
+  let RsArgsValue::Object(func_args_type_rs) = func_args_type else {
+    return Err(FFIError::UnsupportedValueType(format!("{} must be an array", PARAMS_TYPE)).into());
+  };
+  // build the ffi types before creating the threadsafe function so an unsupported type is reported
+  // without leaking it
+  let args_ffi_type = func_args_type_rs
+    .values()
+    .map(|val| val.to_ffi_type())
+    .collect::<Result<Vec<_>>>()?;
+  let ret_ffi_type = func_ret_type.to_ffi_type()?;
+  let tsfn: ThreadsafeFunction<Result<Vec<RsArgsValue>>, ErrorStrategy::Fatal> = (&js_function)
+    .create_threadsafe_function(
+      0,
+      move |ctx: ThreadSafeCallContext<Result<Vec<RsArgsValue>>>| {
+        let js_call_params: Vec<JsUnknown> = ctx
+          .value
+          .map_err(|err| to_js_error(&ctx.env, err))?
+          .into_iter()
+          .map(|rs_args| rs_value_to_js_unknown(&ctx.env, rs_args))
+          .collect::<Result<Vec<JsUnknown>>>()?;
+        Ok(js_call_params)
+      },
+    )?;
 
-          let tsfn_call_context_ptr = Box::into_raw(Box::new(tsfn_call_context));
+  unsafe extern "C" fn lambda_callback<F: Fn((Vec<*mut c_void>, *mut c_void))>(
+    _cif: &low::ffi_cif,
+    result: &mut c_void,
+    args: *const *const c_void,
+    userdata: &F,
+  ) {
+    // the result is left as zero when the callback can't be called
+    std::ptr::write_bytes(result as *mut c_void as *mut u8, 0, (*_cif.rtype).size);
+    let params: Vec<*mut c_void> = (0.._cif.nargs)
+      .map(|index| *args.offset(index as isize) as *mut c_void)
+      .collect();
 
-          let (cif, lambda) = if let RsArgsValue::Object(func_args_type_rs) = func_args_type {
-            let cif = Cif::new(
-              func_args_type_rs
-                .values()
-                .into_iter()
-                .map(|val| val.to_ffi_type()),
-              func_ret_type.to_ffi_type(),
-            );
-            let main_thread_id = std::thread::current().id();
-            let env_clone = env.clone();
+    userdata((params, result));
+  }
 
-            let lambda = move |args: (Vec<*mut c_void>, *mut c_void)| {
-              let (params, result) = args;
-              let value: Vec<RsArgsValue> = params
-                .into_iter()
-                .enumerate()
-                .map(|(index, c_param)| {
-                  let arg_type = func_args_type_rs.get(&index.to_string()).unwrap();
-                  let param = get_rs_value_from_pointer(env, arg_type, c_param, true);
-                  if free_c_params_memory == RsArgsValue::Boolean(true) {
-                    free_c_pointer_memory(c_param, arg_type);
-                  }
+  let tsfn_call_context = TsFnCallContext {
+    tsfn,
+    lambda: None,
+    closure: None,
+    library: None,
+  };
 
-                  param
-                })
-                .collect();
-              let func_ret_type_rc = Rc::new(vec![func_ret_type.clone()]);
-              if std::thread::current().id() != main_thread_id
-                && func_ret_type != RsArgsValue::I32(7)
-              {
-                let (se, re) = std::sync::mpsc::channel();
-                (*tsfn_call_context_ptr).tsfn.call_with_return_value(
-                  value,
-                  ThreadsafeFunctionCallMode::Blocking,
-                  move |js_return_value: JsUnknown| {
-                    let js_return_value_rs =
-                      get_arg_values(Rc::clone(&func_ret_type_rc), vec![js_return_value]).unwrap();
-                    let js_return_value_rs_ptr = get_value_pointer(
-                      &env_clone,
-                      Rc::clone(&func_ret_type_rc),
-                      js_return_value_rs,
-                    )
-                    .unwrap()[0];
-                    write_rs_ptr_to_c(
-                      &Rc::clone(&func_ret_type_rc)[0],
-                      js_return_value_rs_ptr,
-                      result,
-                    );
-                    se.send(()).unwrap();
-                    Ok(())
-                  },
-                );
-                re.recv().unwrap();
-              } else {
-                if func_ret_type != RsArgsValue::I32(DataType::Void as i32) {
-                  println!(
-                    "\x1b[33m{}\x1b[0m",
-                    "warning: set runInNewThread to true to get the return value in c environment"
-                  );
-                }
-                (*tsfn_call_context_ptr)
-                  .tsfn
-                  .call(value, ThreadsafeFunctionCallMode::Blocking);
-              }
-            };
-            (cif, lambda)
-          } else {
-            return Err(FFIError::Panic(format!("generate cif error")).into());
-          };
-          (*tsfn_call_context_ptr).lambda = Some(Box::new(lambda));
-          let closure = Closure::new(
-            cif,
-            lambda_callback,
-            (*tsfn_call_context_ptr).lambda.as_ref().unwrap(),
-          );
-          (*tsfn_call_context_ptr).closure = Some(closure);
-          if CLOSURE_MAP.is_none() {
-            CLOSURE_MAP = Some(HashMap::new());
-          }
-          let code_ptr = std::mem::transmute(
-            (*tsfn_call_context_ptr)
-              .closure
-              .as_ref()
-              .unwrap()
-              .code_ptr(),
-          );
-          CLOSURE_MAP
-            .as_mut()
-            .unwrap()
-            .insert(code_ptr, tsfn_call_context_ptr as *mut c_void);
-          Ok(code_ptr)
+  let tsfn_call_context_ptr = Box::into_raw(Box::new(tsfn_call_context));
 
-          // has been deprecated
-          // Ok(
-          //   match_args_len!(env, args_len, tsfn_ptr, func_args_type_rs_ptr,
-          //       1 => Closure1, a
-          //       ,2 => Closure2, a,b
-          //       ,3 => Closure3, a,b,c
-          //       ,4 => Closure4, a,b,c,d
-          //       ,5 => Closure5, a,b,c,d,e
-          //       ,6 => Closure6, a,b,c,d,e,f
-          //       ,7 => Closure7, a,b,c,d,e,f,g
-          //       ,8 => Closure8, a,b,c,d,e,f,g,h
-          //       ,9 => Closure9, a,b,c,d,e,f,g,h,i
-          //       ,10 => Closure10, a,b,c,d,e,f,g,h,i,j
-          //   ),
-          // )
+  let (cif, lambda) = {
+    let cif = Cif::new_with_abi(args_ffi_type, ret_ffi_type, abi);
+    let main_thread_id = std::thread::current().id();
+    let env_clone = env.clone();
+
+    let lambda = move |args: (Vec<*mut c_void>, *mut c_void)| {
+      let (params, result) = args;
+      let value: Result<Vec<RsArgsValue>> = params
+        .into_iter()
+        .zip(func_args_type_rs.values())
+        .map(|(c_param, arg_type)| {
+          let param = get_rs_value_from_pointer(env, arg_type, c_param, true)?;
+          // the storage of the param is owned by libffi
+          if free_c_params_memory == RsArgsValue::Boolean(true) {
+            free_c_pointee_memory(env, c_param, arg_type, None)?;
+          }
+
+          Ok(param)
+        })
+        .collect();
+      // there is no js caller to throw to when c calls the callback, the error is thrown on the js
+      // thread as an uncaught exception
+      let value = match value {
+        Ok(value) => value,
+        Err(err) => {
+          (*tsfn_call_context_ptr)
+            .tsfn
+            .call(Err(err), ThreadsafeFunctionCallMode::NonBlocking);
+          return;
+        }
+      };
+      let func_ret_type_rc = Rc::new(vec![func_ret_type.clone()]);
+      if std::thread::current().id() != main_thread_id && func_ret_type != RsArgsValue::I32(7) {
+        let (se, re) = std::sync::mpsc::channel();
+        (*tsfn_call_context_ptr).tsfn.call_with_return_value(
+          Ok(value),
+          ThreadsafeFunctionCallMode::Blocking,
+          move |js_return_value: JsUnknown| {
+            let write_result = (|| {
+              let js_return_value_rs = get_arg_values(
+                &env_clone,
+                Rc::clone(&func_ret_type_rc),
+                vec![js_return_value],
+              )?;
+              let js_return_value_rs_ptr = get_value_pointer(
+                &env_clone,
+                Rc::clone(&func_ret_type_rc),
+                js_return_value_rs,
+                None,
+              )?[0];
+              write_rs_ptr_to_c(
+                &Rc::clone(&func_ret_type_rc)[0],
+                js_return_value_rs_ptr,
+                result,
+              )
+            })();
+            // always release the c thread waiting for the return value
+            se.send(()).unwrap();
+            if let Err(err) = write_result {
+              throw_uncaught(&env_clone, err);
+            }
+            Ok(())
+          },
+        );
+        re.recv().unwrap();
+      } else {
+        if func_ret_type != RsArgsValue::I32(DataType::Void as i32) {
+          println!(
+            "\x1b[33m{}\x1b[0m",
+            "warning: set runInNewThread to true to get the return value in c environment"
+          );
         }
+        (*tsfn_call_context_ptr)
+          .tsfn
+          .call(Ok(value), ThreadsafeFunctionCallMode::Blocking);
       }
-    })
-    .collect::<Result<Vec<*mut c_void>>>()
+    };
+    (cif, lambda)
+  };
+  (*tsfn_call_context_ptr).lambda = Some(Box::new(lambda));
+  let closure = Closure::new(
+    cif,
+    lambda_callback,
+    (*tsfn_call_context_ptr).lambda.as_ref().unwrap(),
+  );
+  (*tsfn_call_context_ptr).closure = Some(closure);
+  let code_ptr = std::mem::transmute(
+    (*tsfn_call_context_ptr)
+      .closure
+      .as_ref()
+      .unwrap()
+      .code_ptr(),
+  );
+  with_instance_data(env, |data| {
+    data
+      .closure_map
+      .insert(code_ptr, tsfn_call_context_ptr as *mut c_void)
+  })?;
+  track_allocation(env, code_ptr, &RsArgsValue::Object(func_desc), true)?;
+  Ok(code_ptr)
+
+  // has been deprecated
+  // Ok(
+  //   match_args_len!(env, args_len, tsfn_ptr, func_args_type_rs_ptr,
+  //       1 => Closure1, a
+  //       ,2 => Closure2, a,b
+  //       ,3 => Closure3, a,b,c
+  //       ,4 => Closure4, a,b,c,d
+  //       ,5 => Closure5, a,b,c,d,e
+  //       ,6 => Closure6, a,b,c,d,e,f
+  //       ,7 => Closure7, a,b,c,d,e,f,g
+  //       ,8 => Closure8, a,b,c,d,e,f,g,h
+  //       ,9 => Closure9, a,b,c,d,e,f,g,h,i
+  //       ,10 => Closure10, a,b,c,d,e,f,g,h,i,j
+  //   ),
+  // )
 }
 
 pub unsafe fn get_params_value_rs_struct(
@@ -612,6 +910,13 @@ pub unsafe fn get_params_value_rs_struct(
                 let val: String = js_string_to_string(val)?;
                 RsArgsValue::WString(val)
               }
+              DataType::U16String | DataType::U32String | DataType::Latin1String => {
+                let val: JsString = params_value_object.get_named_property(&field)?;
+                RsArgsValue::EncodedString(js_string_to_encoded_string(
+                  val,
+                  &get_string_encoding(field_type).unwrap(),
+                )?)
+              }
               DataType::U8 => {
                 let val: JsNumber = params_value_object.get_named_property(&field)?;
                 let val: u32 = val.try_into()?;
@@ -667,18 +972,45 @@ pub unsafe fn get_params_value_rs_struct(
                 RsArgsValue::External(val)
               }
               DataType::Void => RsArgsValue::Void(()),
-              _ => panic!("unsupport data type: {:?}", data_type),
+              _ => {
+                return Err(
+                  FFIError::UnsupportedValueType(format!("unsupport data type: {:?}", data_type))
+                    .into(),
+                )
+              }
             };
             index_map.insert(field, val);
           }
 
+          RsArgsValue::Object(params_type_rs_value)
+            if get_ffi_tag(&params_type_rs_value) == FFITypeTag::String =>
+          {
+            let val: JsString = params_value_object.get_named_property(&field)?;
+            index_map.insert(
+              field,
+              RsArgsValue::EncodedString(js_string_to_encoded_string(
+                val,
+                &get_string_encoding(field_type).unwrap(),
+              )?),
+            );
+          }
+          RsArgsValue::Object(params_type_rs_value)
+            if get_ffi_tag(&params_type_rs_value) == FFITypeTag::Function =>
+          {
+            let js_function: JsFunction = params_value_object.get_named_property(&field)?;
+            index_map.insert(
+              field,
+              RsArgsValue::Function(params_type_rs_value, js_function),
+            );
+          }
           RsArgsValue::Object(mut params_type_rs_value) => {
             let params_value: JsObject = params_value_object.get_named_property(&field)?;
             if let FFITypeTag::Array | FFITypeTag::StackArray = get_ffi_tag(&params_type_rs_value) {
-              let array_desc = get_array_desc(&params_type_rs_value);
+              let array_desc = get_array_desc(&params_type_rs_value)?;
               let FFIARRARYDESC {
                 array_type,
                 struct_item_type,
+                encoding,
                 ..
               } = array_desc;
               let array_value = match array_type {
@@ -696,6 +1028,16 @@ pub unsafe fn get_params_value_rs_struct(
                   let arg_val = js_array.to_rs_array()?;
                   RsArgsValue::I32Array(arg_val)
                 }
+                RefDataType::I64Array => {
+                  let js_array: JsObject = params_value_object.get_named_property(&field)?;
+                  let arg_val = (0..js_array.get_array_length()?)
+                    .map(|index| {
+                      let js_element: JsBigInt = js_array.get_element(index)?;
+                      Ok(js_element.try_into()?)
+                    })
+                    .collect::<Result<Vec<i64>>>()?;
+                  RsArgsValue::I64Array(arg_val)
+                }
                 RefDataType::DoubleArray => {
                   let js_array: JsObject = params_value_object.get_named_property(&field)?;
                   let arg_val = js_array.to_rs_array()?;
@@ -712,8 +1054,16 @@ pub unsafe fn get_params_value_rs_struct(
                 }
                 RefDataType::StringArray => {
                   let js_array: JsObject = params_value_object.get_named_property(&field)?;
-                  let arg_val = js_array.to_rs_array()?;
-                  RsArgsValue::StringArray(arg_val)
+                  let arg_val: Vec<String> = js_array.to_rs_array()?;
+                  match encoding {
+                    Some(encoding) => RsArgsValue::EncodedStringArray(
+                      arg_val
+                        .iter()
+                        .map(|str| encode_string(str, &encoding))
+                        .collect::<Result<Vec<_>>>()?,
+                    ),
+                    None => RsArgsValue::StringArray(arg_val),
+                  }
                 }
                 RefDataType::StructArray => {
                   let js_array: JsObject = params_value_object.get_named_property(&field)?;
@@ -721,12 +1071,11 @@ pub unsafe fn get_params_value_rs_struct(
                     .iter()
                     .enumerate()
                     .map(|(index, _)| {
-                      let js_element: JsObject = js_array.get_element(index as u32).unwrap();
-                      let struct_item_type = struct_item_type.as_ref().unwrap();
-                      let index_map = get_params_value_rs_struct(struct_item_type, &js_element);
-                      index_map.unwrap()
+                      let js_element: JsObject = js_array.get_element(index as u32)?;
+                      let struct_item_type = get_struct_item_type(&struct_item_type)?;
+                      get_params_value_rs_struct(struct_item_type, &js_element)
                     })
-                    .collect();
+                    .collect::<Result<Vec<_>>>()?;
                   RsArgsValue::StructArray(arg_val)
                 }
               };
@@ -827,6 +1176,140 @@ pub unsafe fn type_define_to_rs_args(env: &Env, type_define: JsUnknown) -> Resul
   Ok(ret_value)
 }
 
+fn type_error(path: &str, reason: &str) -> Error {
+  FFIError::UnsupportedValueType(format!("Invalid type at {}: {}", path, reason)).into()
+}
+
+/// Parse a js type define and check every field of it, the error names the path of the offending
+/// field like `paramsType[2].header.flags`
+pub unsafe fn parse_type_define(
+  env: &Env,
+  type_define: JsUnknown,
+  path: &str,
+) -> Result<RsArgsValue> {
+  let type_desc =
+    type_define_to_rs_args(env, type_define).map_err(|err| type_error(path, &err.reason))?;
+  check_type_desc(&type_desc, path)?;
+  Ok(type_desc)
+}
+
+/// Parse the type define of a param of a call, which can also be StringWithLength
+pub unsafe fn parse_call_param_type(
+  env: &Env,
+  type_define: JsUnknown,
+  path: &str,
+) -> Result<RsArgsValue> {
+  let type_desc =
+    type_define_to_rs_args(env, type_define).map_err(|err| type_error(path, &err.reason))?;
+  if !is_string_with_length(&type_desc) {
+    check_type_desc(&type_desc, path)?;
+  }
+  Ok(type_desc)
+}
+
+pub fn check_type_desc(type_desc: &RsArgsValue, path: &str) -> Result<()> {
+  match type_desc {
+    RsArgsValue::I32(number) => {
+      BasicDataType::try_from(*number).map_err(|err| type_error(path, err.as_ref()))?;
+    }
+    RsArgsValue::Object(obj) => {
+      match get_ffi_tag(obj) {
+        FFITypeTag::Array | FFITypeTag::StackArray => {
+          let type_path = format!("{}.{}", path, ARRAY_TYPE_TAG);
+          let array_type = match obj.get(ARRAY_TYPE_TAG) {
+            Some(RsArgsValue::I32(number)) => {
+              RefDataType::try_from(*number).map_err(|err| type_error(&type_path, err.as_ref()))?
+            }
+            _ => return Err(type_error(&type_path, "array type is required")),
+          };
+          if !matches!(obj.get(ARRAY_LENGTH_TAG), None | Some(RsArgsValue::I32(_))) {
+            return Err(type_error(
+              &format!("{}.{}", path, ARRAY_LENGTH_TAG),
+              "array length must be a number",
+            ));
+          }
+          match obj.get(ARRAY_TYPED_ARRAY_TAG) {
+            None | Some(RsArgsValue::Boolean(false)) => {}
+            Some(RsArgsValue::String(output)) if output == "copy" || output == "view" => {
+              if get_typed_array_type(&array_type).is_none() {
+                return Err(type_error(
+                  &format!("{}.{}", path, ARRAY_TYPED_ARRAY_TAG),
+                  &format!("{:?} can't be returned as a TypedArray", array_type),
+                ));
+              }
+            }
+            _ => {
+              return Err(type_error(
+                &format!("{}.{}", path, ARRAY_TYPED_ARRAY_TAG),
+                "typedArray must be \"copy\" or \"view\"",
+              ))
+            }
+          }
+          if obj.get(STRING_ENCODING_TAG).is_some() {
+            if !matches!(array_type, RefDataType::StringArray) {
+              return Err(type_error(
+                &format!("{}.{}", path, STRING_ENCODING_TAG),
+                &format!("{:?} has no encoding", array_type),
+              ));
+            }
+            check_string_encoding_desc(obj, path)?;
+          }
+          if let RefDataType::StructArray = array_type {
+            let item_path = format!("{}.{}", path, ARRAY_STRUCT_ITEM_TYPE_TAG);
+            match obj.get(ARRAY_STRUCT_ITEM_TYPE_TAG) {
+              Some(item_type @ RsArgsValue::Object(_)) => check_type_desc(item_type, &item_path)?,
+              _ => {
+                return Err(type_error(
+                  &item_path,
+                  "StructArray requires a struct item type",
+                ))
+              }
+            }
+          }
+        }
+        FFITypeTag::String => check_string_encoding_desc(obj, path)?,
+        FFITypeTag::Function => {
+          match obj.get(PARAMS_TYPE) {
+            Some(RsArgsValue::Object(params_type)) => params_type
+              .values()
+              .enumerate()
+              .try_for_each(|(index, param_type)| {
+                check_type_desc(param_type, &format!("{}.{}[{}]", path, PARAMS_TYPE, index))
+              })?,
+            _ => {
+              return Err(type_error(
+                &format!("{}.{}", path, PARAMS_TYPE),
+                "function params type is required",
+              ))
+            }
+          }
+          if let Some(ret_type) = obj.get(RET_TYPE) {
+            check_type_desc(ret_type, &format!("{}.{}", path, RET_TYPE))?;
+          }
+        }
+        _ => obj
+          .iter()
+          .filter(|(field, _)| field.as_str() != FFI_TAG_FIELD)
+          .try_for_each(|(field, field_type)| {
+            check_type_desc(field_type, &format!("{}.{}", path, field))
+          })?,
+      }
+    }
+    _ => return Err(type_error(path, "type can only be number or object")),
+  }
+  Ok(())
+}
+
+fn check_string_encoding_desc(obj: &IndexMap<String, RsArgsValue>, path: &str) -> Result<()> {
+  let encoding_path = format!("{}.{}", path, STRING_ENCODING_TAG);
+  match obj.get(STRING_ENCODING_TAG) {
+    None => Ok(()),
+    Some(RsArgsValue::String(name)) => check_string_encoding(&parse_string_encoding(name))
+      .map_err(|err| type_error(&encoding_path, &err.reason)),
+    _ => Err(type_error(&encoding_path, "encoding must be a string")),
+  }
+}
+
 pub unsafe fn get_js_unknown_from_pointer(
   env: &Env,
   ret_type_rs: &RsArgsValue,
@@ -846,6 +1329,13 @@ pub unsafe fn get_js_unknown_from_pointer(
           let ptr_str = WideCString::from_ptr_str(*(ptr as *mut *const WideChar)).to_string_lossy();
           rs_value_to_js_unknown(&env, RsArgsValue::WString(ptr_str))
         }
+        BasicDataType::U16String | BasicDataType::U32String | BasicDataType::Latin1String => {
+          let ptr_str = decode_string(
+            *(ptr as *mut *const c_void),
+            &get_string_encoding(ret_type_rs).unwrap(),
+          )?;
+          rs_value_to_js_unknown(env, RsArgsValue::String(ptr_str))
+        }
         BasicDataType::U8 => rs_value_to_js_unknown(env, RsArgsValue::U8(*(ptr as *mut u8))),
         BasicDataType::I16 => rs_value_to_js_unknown(env, RsArgsValue::I16(*(ptr as *mut i16))),
         BasicDataType::I32 => rs_value_to_js_unknown(env, RsArgsValue::I32(*(ptr as *mut i32))),
@@ -873,20 +1363,40 @@ pub unsafe fn get_js_unknown_from_pointer(
       }
     }
     RsArgsValue::Object(sub_obj_type) => {
-      if let FFITypeTag::Array | FFITypeTag::StackArray = get_ffi_tag(&sub_obj_type) {
-        let array_desc = get_array_desc(&sub_obj_type);
+      if let FFITypeTag::String = get_ffi_tag(sub_obj_type) {
+        let ptr_str = decode_string(
+          *(ptr as *mut *const c_void),
+          &get_string_encoding(ret_type_rs).unwrap(),
+        )?;
+        rs_value_to_js_unknown(env, RsArgsValue::String(ptr_str))
+      } else if let FFITypeTag::Array | FFITypeTag::StackArray = get_ffi_tag(&sub_obj_type) {
+        let array_desc = get_array_desc(&sub_obj_type)?;
         // array
         let FFIARRARYDESC {
           array_type,
           array_len,
           struct_item_type,
-          ..
+          typed_array,
+          encoding,
         } = array_desc;
+        if let Some(output) = typed_array {
+          return create_typed_array(
+            env,
+            &array_type,
+            *(ptr as *mut *mut c_void),
+            array_len,
+            output,
+          );
+        }
         match array_type {
           RefDataType::U8Array => {
             let arr = create_array_from_pointer(*(ptr as *mut *mut c_uchar), array_len);
             rs_value_to_js_unknown(env, get_safe_buffer(env, arr, false))
           }
+          RefDataType::I64Array => {
+            let arr = create_array_from_pointer(*(ptr as *mut *mut i64), array_len);
+            rs_value_to_js_unknown(env, RsArgsValue::I64Array(arr))
+          }
           RefDataType::I16Array => {
             let arr = create_array_from_pointer(*(ptr as *mut *mut i16), array_len);
             rs_value_to_js_unknown(env, RsArgsValue::I16Array(arr))
@@ -904,30 +1414,31 @@ pub unsafe fn get_js_unknown_from_pointer(
             rs_value_to_js_unknown(env, RsArgsValue::FloatArray(arr))
           }
           RefDataType::StringArray => {
-            let arr = create_array_from_pointer(*(ptr as *mut *mut *mut c_char), array_len);
+            let arr = match encoding {
+              Some(encoding) => {
+                decode_string_array(*(ptr as *mut *const *const c_void), array_len, &encoding)?
+              }
+              None => create_array_from_pointer(*(ptr as *mut *mut *mut c_char), array_len),
+            };
             rs_value_to_js_unknown(env, RsArgsValue::StringArray(arr))
           }
           RefDataType::StructArray => {
             let mut safe_ptr = std::ptr::read(ptr as *const *mut c_void);
-            let is_stack_struct =
-              get_ffi_tag(struct_item_type.as_ref().unwrap()) == FFITypeTag::StackStruct;
+            let struct_item_type = get_struct_item_type(&struct_item_type)?;
+            let is_stack_struct = get_ffi_tag(struct_item_type) == FFITypeTag::StackStruct;
+            let (struct_size, _) = calculate_struct_size(struct_item_type)?;
             let v = (0..array_len)
               .map(|_| {
-                let rs_struct = create_rs_struct_from_pointer(
-                  env,
-                  safe_ptr,
-                  struct_item_type.as_ref().unwrap(),
-                  false,
-                );
-                let (struct_size, _) = calculate_struct_size(&struct_item_type.as_ref().unwrap());
+                let rs_struct =
+                  create_rs_struct_from_pointer(env, safe_ptr, struct_item_type, false)?;
                 if is_stack_struct {
                   safe_ptr = safe_ptr.offset(struct_size as isize);
                 } else {
                   safe_ptr = safe_ptr.offset(1);
                 }
-                rs_struct
+                Ok(rs_struct)
               })
-              .collect();
+              .collect::<Result<Vec<_>>>()?;
             rs_value_to_js_unknown(env, RsArgsValue::StructArray(v))
           }
         }
@@ -943,18 +1454,22 @@ pub unsafe fn get_js_unknown_from_pointer(
           },
           &sub_obj_type,
           false,
-        );
+        )?;
         rs_value_to_js_unknown(env, RsArgsValue::Object(rs_struct))
       }
     }
-    _ => Err(FFIError::Panic(format!("ret_type err {:?}", ret_type_rs)).into()),
+    _ => Err(FFIError::UnsupportedValueType(format!("ret_type err {:?}", ret_type_rs)).into()),
   }
 }
 
-unsafe fn write_rs_ptr_to_c(ret_type: &RsArgsValue, src: *mut c_void, dst: *mut c_void) {
+unsafe fn write_rs_ptr_to_c(
+  ret_type: &RsArgsValue,
+  src: *mut c_void,
+  dst: *mut c_void,
+) -> Result<()> {
   match &ret_type {
     RsArgsValue::I32(number) => {
-      let ret_data_type = (*number).try_into().unwrap();
+      let ret_data_type = (*number).try_into()?;
       match ret_data_type {
         BasicDataType::U8 => std::ptr::copy(src, dst, std::mem::size_of::<u8>()),
         BasicDataType::I16 => std::ptr::copy(src, dst, std::mem::size_of::<i16>()),
@@ -969,6 +1484,9 @@ unsafe fn write_rs_ptr_to_c(ret_type: &RsArgsValue, src: *mut c_void, dst: *mut
         BasicDataType::Boolean => std::ptr::copy(src, dst, std::mem::size_of::<bool>()),
         BasicDataType::String => std::ptr::copy(src, dst, std::mem::size_of::<*const c_char>()),
         BasicDataType::WString => std::ptr::copy(src, dst, std::mem::size_of::<*const WideChar>()),
+        BasicDataType::U16String | BasicDataType::U32String | BasicDataType::Latin1String => {
+          std::ptr::copy(src, dst, std::mem::size_of::<*const c_void>())
+        }
         BasicDataType::External => std::ptr::copy(src, dst, std::mem::size_of::<*mut c_void>()),
         BasicDataType::Void => {}
       }
@@ -987,7 +1505,23 @@ unsafe fn write_rs_ptr_to_c(ret_type: &RsArgsValue, src: *mut c_void, dst: *mut
         _ => {}
       }
     }
-    RsArgsValue::Object(_) => std::ptr::copy(src, dst, std::mem::size_of::<*const *const c_void>()),
+    RsArgsValue::Object(obj) => {
+      if get_ffi_tag(obj) == FFITypeTag::StackStruct {
+        // get_value_pointer lays a StackStruct out in place, so copy the whole
+        // struct into the libffi result slot and release the temporary copy
+        let (size, align) = calculate_struct_size(obj)?;
+        if size > 0 {
+          std::ptr::copy(src as *const u8, dst as *mut u8, size);
+          dealloc(
+            src as *mut u8,
+            Layout::from_size_align(size, align).unwrap(),
+          );
+        }
+      } else {
+        std::ptr::copy(src, dst, std::mem::size_of::<*const *const c_void>())
+      }
+    }
     _ => {}
   }
+  Ok(())
 }
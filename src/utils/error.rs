@@ -0,0 +1,157 @@
+use napi::{check_status, sys, Env, JsError, JsObject, JsUnknown, NapiRaw, NapiValue, Status};
+use std::fmt;
+
+/// Extra properties set on the js error
+#[derive(Default, Clone, Debug)]
+pub struct ErrorContext {
+  pub library: Option<String>,
+  pub func_name: Option<String>,
+  pub arg_index: Option<usize>,
+  pub dlerror: Option<String>,
+}
+
+/// Error of ffi-rs, napi::Error only keeps a status and a message so the code and the context are
+/// carried here until the error is thrown to js in `with_error_code`
+#[derive(Clone, Debug)]
+pub struct Error {
+  pub status: Status,
+  pub reason: String,
+  // errors which don't come from FFIError use their napi status as code
+  pub code: Option<&'static str>,
+  // boxed to keep the Err variant of every Result small
+  pub context: Box<ErrorContext>,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+  pub fn new(status: Status, reason: String, code: Option<&'static str>) -> Self {
+    Error {
+      status,
+      reason,
+      code,
+      context: Box::default(),
+    }
+  }
+
+  fn code(&self) -> &str {
+    self.code.unwrap_or_else(|| self.status.as_ref())
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.code(), self.reason)
+  }
+}
+
+impl From<napi::Error> for Error {
+  fn from(err: napi::Error) -> Self {
+    Error::new(err.status, err.reason, None)
+  }
+}
+
+// only for the napi callbacks which have no js caller to throw to, the code and context are dropped
+impl From<Error> for napi::Error {
+  fn from(err: Error) -> Self {
+    napi::Error::new(err.status, err.reason)
+  }
+}
+
+/// Attach context to an error on its way to js, the context closest to the error wins
+pub fn add_error_context(mut err: Error, add: impl FnOnce(&mut ErrorContext)) -> Error {
+  add(&mut err.context);
+  err
+}
+
+fn fill_context(target: &mut ErrorContext, context: ErrorContext) {
+  if target.library.is_none() {
+    target.library = context.library;
+  }
+  if target.func_name.is_none() {
+    target.func_name = context.func_name;
+  }
+  if target.arg_index.is_none() {
+    target.arg_index = context.arg_index;
+  }
+  if target.dlerror.is_none() {
+    target.dlerror = context.dlerror;
+  }
+}
+
+/// Create the js error with `code` and the context as properties
+pub fn to_js_error(env: &Env, err: Error) -> napi::Error {
+  // a js exception is already pending, it's thrown as it is
+  if err.status == Status::PendingException {
+    return napi::Error::from(err);
+  }
+  match unsafe { create_js_error(env, &err) } {
+    Ok(js_error) => napi::Error::from(js_error),
+    Err(_) => napi::Error::from(err),
+  }
+}
+
+/// Throw `err` on the js thread as an uncaught exception, for errors which have no js caller such as
+/// the ones of a callback called by c or a finalizer
+pub fn throw_uncaught(env: &Env, err: Error) {
+  unsafe {
+    let js_error = if err.status == Status::PendingException {
+      // rethrow what js threw
+      let mut exception = std::ptr::null_mut();
+      sys::napi_get_and_clear_last_exception(env.raw(), &mut exception);
+      exception
+    } else {
+      return throw_js_uncaught(env, to_js_error(env, err));
+    };
+    sys::napi_fatal_exception(env.raw(), js_error);
+  }
+}
+
+/// Same as `throw_uncaught` for an error returned by napi, the value js threw is kept as it is
+pub fn throw_js_uncaught(env: &Env, err: napi::Error) {
+  unsafe {
+    sys::napi_fatal_exception(env.raw(), JsError::from(err).into_value(env.raw()));
+  }
+}
+
+unsafe fn create_js_error(env: &Env, err: &Error) -> napi::Result<JsUnknown> {
+  let mut js_error = std::ptr::null_mut();
+  check_status!(sys::napi_create_error(
+    env.raw(),
+    env.create_string(err.code())?.raw(),
+    env.create_string(&err.reason)?.raw(),
+    &mut js_error,
+  ))?;
+  let mut js_error_object = JsObject::from_raw_unchecked(env.raw(), js_error);
+  let ErrorContext {
+    library,
+    func_name,
+    arg_index,
+    dlerror,
+  } = &*err.context;
+  if let Some(library) = library {
+    js_error_object.set_named_property("library", env.create_string(library)?)?;
+  }
+  if let Some(func_name) = func_name {
+    js_error_object.set_named_property("funcName", env.create_string(func_name)?)?;
+  }
+  if let Some(arg_index) = arg_index {
+    js_error_object.set_named_property("argIndex", env.create_uint32(*arg_index as u32)?)?;
+  }
+  if let Some(dlerror) = dlerror {
+    js_error_object.set_named_property("dlerror", env.create_string(dlerror)?)?;
+  }
+  Ok(js_error_object.into_unknown())
+}
+
+/// Run the body of an exported function, errors are thrown with their code and context
+pub fn with_error_code<T>(
+  env: &Env,
+  context: ErrorContext,
+  body: impl FnOnce() -> Result<T>,
+) -> napi::Result<T> {
+  body().map_err(|mut err| {
+    fill_context(&mut err.context, context);
+    to_js_error(env, err)
+  })
+}
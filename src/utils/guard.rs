@@ -0,0 +1,124 @@
+use crate::define::*;
+use crate::utils::error::{Error, Result};
+use libffi_sys::{ffi_call, ffi_cif};
+use napi::{Env, JsFunction, JsObject};
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// set once a guarded call has faulted, the native side may have left memory or locks in a broken
+// state so the process can't be trusted anymore
+static TAINTED: AtomicBool = AtomicBool::new(false);
+
+pub struct Fault {
+  pub signal: i32,
+  pub address: *mut c_void,
+}
+
+struct GuardedCall {
+  cif: *mut ffi_cif,
+  func: unsafe extern "C" fn(),
+  result: *mut c_void,
+  args: *mut *mut c_void,
+}
+
+#[cfg(unix)]
+extern "C" {
+  fn ffi_rs_guarded_call(
+    func: unsafe extern "C" fn(*mut c_void),
+    data: *mut c_void,
+    fault_addr: *mut *mut c_void,
+  ) -> i32;
+}
+
+unsafe extern "C" fn run_guarded_call(data: *mut c_void) {
+  let call = &mut *(data as *mut GuardedCall);
+  ffi_call(call.cif, Some(call.func), call.result, call.args);
+}
+
+pub fn check_guard_supported() -> Result<()> {
+  if cfg!(unix) {
+    Ok(())
+  } else {
+    Err(
+      FFIError::UnsupportedValueType("guarded calls are only supported on unix".to_string()).into(),
+    )
+  }
+}
+
+/// ffi_call which reports SIGSEGV/SIGBUS raised by the callee instead of crashing the process
+pub unsafe fn guarded_ffi_call(
+  cif: *mut ffi_cif,
+  func: unsafe extern "C" fn(),
+  result: *mut c_void,
+  args: *mut *mut c_void,
+) -> std::result::Result<(), Fault> {
+  let mut call = GuardedCall {
+    cif,
+    func,
+    result,
+    args,
+  };
+  #[cfg(unix)]
+  {
+    let mut address = std::ptr::null_mut();
+    let signal = ffi_rs_guarded_call(
+      run_guarded_call,
+      &mut call as *mut GuardedCall as *mut c_void,
+      &mut address,
+    );
+    if signal != 0 {
+      TAINTED.store(true, Ordering::SeqCst);
+      return Err(Fault { signal, address });
+    }
+  }
+  #[cfg(not(unix))]
+  run_guarded_call(&mut call as *mut GuardedCall as *mut c_void);
+  Ok(())
+}
+
+/// Whether a guarded call has faulted in this process
+#[napi]
+pub fn is_tainted() -> bool {
+  TAINTED.load(Ordering::SeqCst)
+}
+
+fn signal_name(signal: i32) -> String {
+  #[cfg(unix)]
+  {
+    if signal == libc::SIGSEGV {
+      return "SIGSEGV".to_string();
+    }
+    if signal == libc::SIGBUS {
+      return "SIGBUS".to_string();
+    }
+  }
+  format!("signal {}", signal)
+}
+
+pub fn fault_message(func_name: &str, fault: &Fault) -> String {
+  format!(
+    "{} received {} while accessing address {:?}, the process is tainted and should be restarted",
+    func_name,
+    signal_name(fault.signal),
+    fault.address
+  )
+}
+
+/// Warn that the process is tainted, the error of the faulted call is returned as it is
+pub fn report_fault(env: &Env, err: Error) -> Error {
+  let _ = emit_warning(env, &err.reason);
+  err
+}
+
+fn emit_warning(env: &Env, message: &str) -> Result<()> {
+  let process: JsObject = env.get_global()?.get_named_property("process")?;
+  let emit_warning: JsFunction = process.get_named_property("emitWarning")?;
+  emit_warning.call(
+    Some(&process),
+    &[
+      env.create_string(message)?,
+      env.create_string("FFITaintWarning")?,
+    ],
+  )?;
+  Ok(())
+}
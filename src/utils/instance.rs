@@ -0,0 +1,189 @@
+use super::arena::Quarantine;
+use super::error::add_error_context;
+use super::{calculate_struct_layout, get_ffi_tag};
+use crate::define::*;
+use crate::utils::error::{Error, Result};
+use dlopen::symbor::Library;
+use napi::Env;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, Weak};
+
+// dlopen handles are shared by every env (main thread and worker_threads) which opens the same
+// path, the handle is only closed when the last env holding it drops its Arc
+static SHARED_LIBRARY_MAP: Mutex<Option<HashMap<String, Weak<Library>>>> = Mutex::new(None);
+
+fn open_error(message: String, dlerror: String) -> Error {
+  add_error_context(FFIError::LibraryNotFound(message).into(), |context| {
+    context.dlerror = Some(dlerror)
+  })
+}
+
+pub fn open_shared_library(path: &str) -> Result<Arc<Library>> {
+  let mut shared_map = SHARED_LIBRARY_MAP.lock().unwrap();
+  let shared_map = shared_map.get_or_insert_with(HashMap::new);
+  if let Some(lib) = shared_map.get(path).and_then(|lib| lib.upgrade()) {
+    return Ok(lib);
+  }
+  let lib = if path.is_empty() {
+    Library::open_self().map_err(|e| open_error(e.to_string(), e.to_string()))?
+  } else {
+    match Library::open(path) {
+      Ok(lib) => lib,
+      Err(e) => match e {
+        dlopen::Error::OpeningLibraryError(e) => {
+          let err_msg = e.to_string();
+          if err_msg.contains("is not a valid Win32 application")
+            || err_msg.contains("not a mach-o file")
+            || err_msg.contains("invalid ELF header")
+          {
+            return Err(open_error(
+              format!(
+                "Please check whether the library has the same compilation and runtime environment.\n Error detail info: {:?}",
+                e
+              ),
+              err_msg,
+            ));
+          } else {
+            return Err(open_error(err_msg.clone(), err_msg));
+          }
+        }
+        e => {
+          return Err(open_error(e.to_string(), e.to_string()));
+        }
+      },
+    }
+  };
+  let lib = Arc::new(lib);
+  shared_map.retain(|_, lib| lib.strong_count() > 0);
+  shared_map.insert(path.to_string(), Arc::downgrade(&lib));
+  Ok(lib)
+}
+
+// libraries and callbacks belong to the env which created them, so every worker_thread gets its
+// own registry through napi_set_instance_data
+fn get_shared_instance_data(env: &Env) -> Result<Rc<RefCell<InstanceData>>> {
+  if let Some(data) = env.get_instance_data::<Rc<RefCell<InstanceData>>>()? {
+    return Ok(Rc::clone(data));
+  }
+  let data = Rc::new(RefCell::new(InstanceData {
+    library_map: HashMap::new(),
+    closure_map: HashMap::new(),
+    finalizer_map: HashMap::new(),
+    scopes: vec![],
+    allocation_tracker: None,
+    hardened: false,
+    quarantine: Quarantine::default(),
+    hardened_pointers: HashMap::new(),
+  }));
+  env.set_instance_data(Rc::clone(&data), 0, |_| {})?;
+  let mut env = *env;
+  env.add_env_cleanup_hook(Rc::clone(&data), |data| unsafe {
+    release_instance_data(&data)
+  })?;
+  Ok(data)
+}
+
+/// Run `f` with the instance data of the env. The data is borrowed until `f` returns, so `f` must
+/// not call js or anything else which uses the instance data, a nested borrow is an error.
+pub fn with_instance_data<R>(env: &Env, f: impl FnOnce(&mut InstanceData) -> R) -> Result<R> {
+  let data = get_shared_instance_data(env)?;
+  let mut data = data.try_borrow_mut().map_err(|_| -> Error {
+    FFIError::Panic("the instance data of the env is already in use".to_string()).into()
+  })?;
+  Ok(f(&mut data))
+}
+
+// called when the env is torn down (e.g. a worker_thread terminates), release the callbacks which
+// were never freed by freePointer and drop this env's references to the shared libraries
+unsafe fn release_instance_data(data: &RefCell<InstanceData>) {
+  if let Ok(mut data) = data.try_borrow_mut() {
+    data.closure_map.drain().for_each(|(_, tsfn_call_context)| {
+      let _ = Box::from_raw(tsfn_call_context as *mut TsFnCallContext);
+    });
+    data.library_map.clear();
+  }
+}
+
+// callbacks which are passed to a library, either as a function param, as the External returned
+// from unwrapPointer or in a field of a struct, keep the library loaded since the library may call
+// them at any time
+pub unsafe fn pin_library_callbacks(
+  env: &Env,
+  library: &Arc<Library>,
+  params_type_rs: &[RsArgsValue],
+  arg_values_c_void: &[*mut c_void],
+) -> Result<()> {
+  let mut funcs = vec![];
+  params_type_rs
+    .iter()
+    .zip(arg_values_c_void.iter())
+    .for_each(|(param_type, arg)| collect_func_addresses(param_type, *arg, &mut funcs));
+  if funcs.is_empty() {
+    return Ok(());
+  }
+  with_instance_data(env, |data| {
+    data
+      .closure_map
+      .iter()
+      .filter(|(code_ptr, _)| funcs.contains(&*(**code_ptr as *mut *mut c_void)))
+      .for_each(|(_, tsfn_call_context)| {
+        (*(*tsfn_call_context as *mut TsFnCallContext)).library = Some(Arc::clone(library));
+      });
+  })
+}
+
+// drop the references held by the callbacks of this env, the library is unloaded as soon as
+// nothing else holds it
+pub unsafe fn unpin_library_callbacks(env: &Env, library: &Arc<Library>) -> Result<()> {
+  with_instance_data(env, |data| {
+    data.closure_map.values().for_each(|tsfn_call_context| {
+      let tsfn_call_context = &mut *(*tsfn_call_context as *mut TsFnCallContext);
+      if let Some(lib) = &tsfn_call_context.library {
+        if Arc::ptr_eq(lib, library) {
+          tsfn_call_context.library = None;
+        }
+      }
+    })
+  })
+}
+
+// `value` points to the storage of a value of `value_type`, the same layout as the params of
+// ffi_call and the fields of a struct
+unsafe fn collect_func_addresses(
+  value_type: &RsArgsValue,
+  value: *mut c_void,
+  funcs: &mut Vec<*mut c_void>,
+) {
+  if value.is_null() {
+    return;
+  }
+  match value_type {
+    RsArgsValue::I32(number) if *number == DataType::External as i32 => {
+      funcs.push(*(value as *mut *mut c_void))
+    }
+    RsArgsValue::Object(obj) => {
+      let struct_ptr = match get_ffi_tag(obj) {
+        FFITypeTag::Function => return funcs.push(*(value as *mut *mut c_void)),
+        FFITypeTag::StackStruct => value,
+        FFITypeTag::Unknown => *(value as *mut *mut c_void),
+        _ => return,
+      };
+      if struct_ptr.is_null() {
+        return;
+      }
+      if let Ok((fields, _, _)) = calculate_struct_layout(obj) {
+        fields.iter().for_each(|field| {
+          collect_func_addresses(
+            field.field_type,
+            (struct_ptr as *mut u8).add(field.offset) as *mut c_void,
+            funcs,
+          )
+        });
+      }
+    }
+    _ => {}
+  }
+}
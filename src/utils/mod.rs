@@ -1,8 +1,13 @@
+pub mod arena;
 pub mod array;
 pub mod dataprocess;
+pub mod error;
+pub mod guard;
+pub mod instance;
 pub mod js_value;
 pub mod object_utils;
 pub mod pointer;
+pub mod tracking;
 pub use array::*;
 pub use dataprocess::*;
 pub use object_utils::*;
@@ -1,7 +1,8 @@
 use super::dataprocess::get_ffi_tag;
-use super::get_array_desc;
+use super::{get_array_desc, get_struct_item_type};
 
 use crate::define::*;
+use crate::utils::error::Result;
 use crate::{RefDataType, RsArgsValue, FFIARRARYDESC};
 use indexmap::IndexMap;
 use std::ffi::{c_char, c_double, c_float, c_int, c_longlong, c_short, c_uchar, c_void};
@@ -10,116 +11,130 @@ pub fn get_size_align<T: Sized>() -> (usize, usize) {
   (std::mem::size_of::<T>(), std::mem::align_of::<T>())
 }
 
-#[macro_export]
-macro_rules! calculate_layout_for {
-  ($variant:ident, $type:ty) => {
-    pub fn $variant(size: usize, align: usize, offset: usize) -> (usize, usize, usize) {
-      let (type_size, type_align) = get_size_align::<$type>();
-      let align = align.max(type_align);
-      let padding = (type_align - (offset % type_align)) % type_align;
-      let size = size + padding + type_size;
-      let offset = offset + padding + type_size;
-      (size, align, offset)
-    }
-  };
+/// A field of a struct type laid out the way the marshaller reads and writes it
+pub struct StructField<'a> {
+  pub name: &'a str,
+  pub field_type: &'a RsArgsValue,
+  pub offset: usize,
+  pub size: usize,
+  pub align: usize,
+  // bytes inserted before the field to align it
+  pub padding: usize,
 }
-calculate_layout_for!(calculate_u8, c_uchar);
-calculate_layout_for!(calculate_i16, c_short);
-calculate_layout_for!(calculate_i32, c_int);
-calculate_layout_for!(calculate_u32, u32);
-calculate_layout_for!(calculate_i64, c_longlong);
-calculate_layout_for!(calculate_float, c_float);
-calculate_layout_for!(calculate_double, c_double);
-calculate_layout_for!(calculate_boolean, bool);
-calculate_layout_for!(calculate_void, ());
-calculate_layout_for!(calculate_string, *const c_char);
-calculate_layout_for!(calculate_w_string, *const WideChar);
-calculate_layout_for!(calculate_pointer, *const c_void);
 
-pub fn calculate_struct_size(struct_type: &IndexMap<String, RsArgsValue>) -> (usize, usize) {
-  let (mut size, align, _) = struct_type.iter().fold(
-    (0, 0, 0),
-    |(size, align, offset), (field_name, field_type)| {
-      if field_name == FFI_TAG_FIELD {
-        return (size, align, offset);
+/// Size and alignment of a value stored in a struct field, struct and array fields are pointers
+/// unless they are StackStruct or StackArray
+pub fn get_field_size_align(field_type: &RsArgsValue) -> Result<(usize, usize)> {
+  match field_type {
+    RsArgsValue::I32(field_type_number) => Ok(match (*field_type_number).try_into()? {
+      BasicDataType::U8 => get_size_align::<c_uchar>(),
+      BasicDataType::I16 => get_size_align::<c_short>(),
+      BasicDataType::I32 => get_size_align::<c_int>(),
+      BasicDataType::U32 => get_size_align::<u32>(),
+      BasicDataType::I64 | BasicDataType::U64 | BasicDataType::BigInt => {
+        get_size_align::<c_longlong>()
+      }
+      BasicDataType::Float => get_size_align::<c_float>(),
+      BasicDataType::Double => get_size_align::<c_double>(),
+      BasicDataType::String => get_size_align::<*const c_char>(),
+      BasicDataType::WString => get_size_align::<*const WideChar>(),
+      BasicDataType::U16String | BasicDataType::U32String | BasicDataType::Latin1String => {
+        get_size_align::<*const c_void>()
       }
-      if let RsArgsValue::I32(field_type_number) = field_type {
-        return match (*field_type_number).try_into().unwrap() {
-          BasicDataType::U8 => calculate_u8(size, align, offset),
-          BasicDataType::I16 => calculate_i16(size, align, offset),
-          BasicDataType::I32 => calculate_i32(size, align, offset),
-          BasicDataType::U32 => calculate_u32(size, align, offset),
-          BasicDataType::I64 | BasicDataType::U64 | BasicDataType::BigInt => {
-            calculate_i64(size, align, offset)
+      BasicDataType::Boolean => get_size_align::<bool>(),
+      BasicDataType::Void => get_size_align::<()>(),
+      BasicDataType::External => get_size_align::<*const c_void>(),
+    }),
+    RsArgsValue::Object(obj) => match get_ffi_tag(obj) {
+      FFITypeTag::StackArray => {
+        let FFIARRARYDESC {
+          array_type,
+          array_len,
+          struct_item_type,
+          ..
+        } = get_array_desc(obj)?;
+        let (type_size, type_align) = match array_type {
+          RefDataType::U8Array => get_size_align::<u8>(),
+          RefDataType::I16Array => get_size_align::<i16>(),
+          RefDataType::I32Array => get_size_align::<i32>(),
+          RefDataType::I64Array => get_size_align::<i64>(),
+          RefDataType::FloatArray => get_size_align::<f32>(),
+          RefDataType::StringArray => get_size_align::<*const c_char>(),
+          RefDataType::DoubleArray => get_size_align::<f64>(),
+          RefDataType::StructArray => {
+            calculate_struct_size(get_struct_item_type(&struct_item_type)?)?
           }
-          BasicDataType::Float => calculate_float(size, align, offset),
-          BasicDataType::Double => calculate_double(size, align, offset),
-          BasicDataType::String => calculate_string(size, align, offset),
-          BasicDataType::WString => calculate_w_string(size, align, offset),
-          BasicDataType::Boolean => calculate_boolean(size, align, offset),
-          BasicDataType::Void => calculate_void(size, align, offset),
-          BasicDataType::External => calculate_pointer(size, align, offset),
         };
-      } else if let RsArgsValue::Object(obj) = field_type {
-        if let FFITypeTag::StackArray = get_ffi_tag(obj) {
-          let array_desc = get_array_desc(obj);
-          let FFIARRARYDESC {
-            array_type,
-            array_len,
-            struct_item_type,
-            ..
-          } = array_desc;
-          let (mut type_size, type_align) = match array_type {
-            RefDataType::U8Array => get_size_align::<u8>(),
-            RefDataType::I16Array => get_size_align::<i16>(),
-            RefDataType::I32Array => get_size_align::<i32>(),
-            RefDataType::FloatArray => get_size_align::<f32>(),
-            RefDataType::StringArray => get_size_align::<*const c_char>(),
-            RefDataType::DoubleArray => get_size_align::<f64>(),
-            RefDataType::StructArray => calculate_struct_size(struct_item_type.as_ref().unwrap()),
-          };
-          type_size = type_size * array_len;
-          let align = align.max(type_align);
-          let padding = (type_align - (offset % type_align)) % type_align;
-          let size = size + padding + type_size;
-          let offset = offset + padding + type_size;
-          return (size, align, offset);
-        } else if let FFITypeTag::Array = get_ffi_tag(obj) {
-          return calculate_pointer(size, align, offset);
-        } else if get_ffi_tag(obj) == FFITypeTag::StackStruct {
-          let (type_size, type_align) = calculate_struct_size(obj);
-          let align = align.max(type_align);
-          let padding = (type_align - (offset % type_align)) % type_align;
-          let size = size + padding + type_size;
-          let offset = offset + padding + type_size;
-          return (size, align, offset);
-        } else {
-          return calculate_pointer(size, align, offset);
-        }
-      } else {
-        panic!("unknown struct type {:?}", field_type)
+        Ok((type_size * array_len, type_align))
       }
+      FFITypeTag::StackStruct => calculate_struct_size(obj),
+      _ => Ok(get_size_align::<*const c_void>()),
     },
-  );
-  let padding = if align > 0 && size % align != 0 {
-    align - (size % align)
-  } else {
-    0
-  };
-  size += padding;
-  (size, align)
+    _ => Err(
+      FFIError::UnsupportedValueType(format!("unknown struct field type {:?}", field_type)).into(),
+    ),
+  }
+}
+
+/// Size and alignment of the value described by a type, a struct is measured by its fields
+/// whether or not it is passed by pointer
+pub fn get_type_size_align(type_desc: &RsArgsValue) -> Result<(usize, usize)> {
+  match type_desc {
+    RsArgsValue::Object(obj) if get_ffi_tag(obj) == FFITypeTag::Unknown => {
+      calculate_struct_size(obj)
+    }
+    _ => get_field_size_align(type_desc),
+  }
+}
+
+/// Lay out the fields of a struct, returns the fields, the size and the alignment of the struct
+pub fn calculate_struct_layout(
+  struct_type: &IndexMap<String, RsArgsValue>,
+) -> Result<(Vec<StructField<'_>>, usize, usize)> {
+  let mut fields = vec![];
+  let (mut size, mut align) = (0, 0);
+  for (field_name, field_type) in struct_type {
+    if field_name == FFI_TAG_FIELD {
+      continue;
+    }
+    let (type_size, type_align) = get_field_size_align(field_type).map_err(|err| {
+      FFIError::UnsupportedValueType(format!("field {}: {}", field_name, err.reason))
+    })?;
+    align = align.max(type_align);
+    let padding = (type_align - (size % type_align)) % type_align;
+    fields.push(StructField {
+      name: field_name,
+      field_type,
+      offset: size + padding,
+      size: type_size,
+      align: type_align,
+      padding,
+    });
+    size += padding + type_size;
+  }
+  if align > 0 && size % align != 0 {
+    size += align - (size % align);
+  }
+  Ok((fields, size, align))
+}
+
+pub fn calculate_struct_size(
+  struct_type: &IndexMap<String, RsArgsValue>,
+) -> Result<(usize, usize)> {
+  let (_, size, align) = calculate_struct_layout(struct_type)?;
+  Ok((size, align))
 }
 
 pub unsafe fn create_static_array_from_pointer(
   ptr: *mut c_void,
   array_desc: &FFIARRARYDESC,
-) -> RsArgsValue {
+) -> Result<RsArgsValue> {
   let FFIARRARYDESC {
     array_type,
     array_len,
     ..
   } = array_desc;
-  match array_type {
+  Ok(match array_type {
     RefDataType::U8Array => {
       let ptr = ptr as *mut u8;
       let arr = (0..*array_len).map(|n| *(ptr.offset(n as isize))).collect();
@@ -135,6 +150,11 @@ pub unsafe fn create_static_array_from_pointer(
       let arr = (0..*array_len).map(|n| *(ptr.offset(n as isize))).collect();
       RsArgsValue::I32Array(arr)
     }
+    RefDataType::I64Array => {
+      let ptr = ptr as *mut i64;
+      let arr = (0..*array_len).map(|n| *(ptr.add(n))).collect();
+      RsArgsValue::I64Array(arr)
+    }
     RefDataType::DoubleArray => {
       let ptr = ptr as *mut f64;
       let arr = (0..*array_len).map(|n| *(ptr.offset(n as isize))).collect();
@@ -145,20 +165,14 @@ pub unsafe fn create_static_array_from_pointer(
       let arr = (0..*array_len).map(|n| *(ptr.offset(n as isize))).collect();
       RsArgsValue::FloatArray(arr)
     }
-    RefDataType::StructArray => {
-      panic!("struct array is not supported for static array");
+    RefDataType::StructArray | RefDataType::StringArray => {
+      return Err(
+        FFIError::UnsupportedValueType(format!(
+          "{:?} is not supported for static array",
+          array_type
+        ))
+        .into(),
+      );
     }
-    RefDataType::StringArray => {
-      panic!("string array is not supported for static array");
-      // let ptr = ptr as *mut *const c_char;
-      // let arr = (0..*array_len)
-      //   .map(|n| {
-      //     std::ffi::CStr::from_ptr(*(ptr.offset(n as isize)))
-      //       .to_string_lossy()
-      //       .to_string()
-      //   })
-      //   .collect();
-      // RsArgsValue::StringArray(arr)
-    }
-  }
+  })
 }
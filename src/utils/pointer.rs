@@ -1,39 +1,44 @@
-use super::dataprocess::get_ffi_tag;
+use super::dataprocess::{get_ffi_tag, is_string_with_length};
 use super::object_utils::calculate_struct_size;
 use crate::define::*;
+use crate::utils::error::Result;
 use libffi_sys::{
-  ffi_type, ffi_type_double, ffi_type_enum_STRUCT, ffi_type_float, ffi_type_pointer,
-  ffi_type_sint16, ffi_type_sint32, ffi_type_sint64, ffi_type_uint32, ffi_type_uint64,
-  ffi_type_uint8, ffi_type_void,
+  ffi_abi, ffi_abi_FFI_DEFAULT_ABI, ffi_type, ffi_type_double, ffi_type_enum_STRUCT,
+  ffi_type_float, ffi_type_pointer, ffi_type_sint16, ffi_type_sint32, ffi_type_sint64,
+  ffi_type_uint32, ffi_type_uint64, ffi_type_uint8, ffi_type_void,
 };
-pub unsafe fn get_ffi_type(ret_type_rs: &RsArgsValue) -> Box<ffi_type> {
+pub unsafe fn get_ffi_type(ret_type_rs: &RsArgsValue) -> Result<Box<ffi_type>> {
   match ret_type_rs {
     RsArgsValue::I32(number) => {
-      let ret_data_type = (*number).try_into().unwrap();
-      match ret_data_type {
+      let ret_data_type = (*number).try_into()?;
+      Ok(match ret_data_type {
         BasicDataType::U8 => Box::new(ffi_type_uint8),
         BasicDataType::I32 => Box::new(ffi_type_sint32),
         BasicDataType::I16 => Box::new(ffi_type_sint16),
         BasicDataType::U32 => Box::new(ffi_type_uint32),
         BasicDataType::I64 | BasicDataType::BigInt => Box::new(ffi_type_sint64),
         BasicDataType::U64 => Box::new(ffi_type_uint64),
-        BasicDataType::String | BasicDataType::WString => Box::new(ffi_type_pointer),
+        BasicDataType::String
+        | BasicDataType::WString
+        | BasicDataType::U16String
+        | BasicDataType::U32String
+        | BasicDataType::Latin1String => Box::new(ffi_type_pointer),
         BasicDataType::Void => Box::new(ffi_type_void),
         BasicDataType::Float => Box::new(ffi_type_float),
         BasicDataType::Double => Box::new(ffi_type_double),
         BasicDataType::Boolean => Box::new(ffi_type_uint8),
         BasicDataType::External => Box::new(ffi_type_pointer),
-      }
+      })
     }
     RsArgsValue::Object(struct_type) => {
       if get_ffi_tag(struct_type) == FFITypeTag::StackStruct {
+        let (size, align) = calculate_struct_size(struct_type)?;
         let mut elements: Vec<*mut ffi_type> = struct_type
           .iter()
           .filter(|(field_name, _)| field_name != &FFI_TAG_FIELD)
-          .map(|(_, field_type)| Box::into_raw(get_ffi_type(field_type)))
-          .collect();
+          .map(|(_, field_type)| get_ffi_type(field_type).map(Box::into_raw))
+          .collect::<Result<Vec<*mut ffi_type>>>()?;
         elements.push(std::ptr::null_mut());
-        let (size, align) = calculate_struct_size(struct_type);
         let struct_type_box = Box::new(ffi_type {
           size,
           alignment: align as u16,
@@ -41,11 +46,89 @@ pub unsafe fn get_ffi_type(ret_type_rs: &RsArgsValue) -> Box<ffi_type> {
           elements: elements.as_mut_ptr(),
         });
         let _ = Box::into_raw(Box::new(elements));
-        struct_type_box
+        Ok(struct_type_box)
       } else {
-        Box::new(ffi_type_pointer)
+        Ok(Box::new(ffi_type_pointer))
       }
     }
-    _ => Box::new(ffi_type_void),
+    _ => Ok(Box::new(ffi_type_void)),
+  }
+}
+
+/// The ffi types of the arguments of a param of a call, StringWithLength is passed as its data
+/// pointer and its length
+pub unsafe fn get_param_ffi_types(param_type: &RsArgsValue) -> Result<Vec<*mut ffi_type>> {
+  if is_string_with_length(param_type) {
+    #[cfg(target_pointer_width = "64")]
+    let size_type = ffi_type_uint64;
+    #[cfg(not(target_pointer_width = "64"))]
+    let size_type = ffi_type_uint32;
+    return Ok(vec![
+      Box::into_raw(Box::new(ffi_type_pointer)),
+      Box::into_raw(Box::new(size_type)),
+    ]);
+  }
+  Ok(vec![Box::into_raw(get_ffi_type(param_type)?)])
+}
+
+// the calling conventions libffi supports on the current target besides "default"
+#[cfg(all(target_arch = "x86_64", unix))]
+const SUPPORTED_ABI: &[(&str, ffi_abi)] = &[
+  ("sysv", libffi_sys::ffi_abi_FFI_UNIX64),
+  ("unix64", libffi_sys::ffi_abi_FFI_UNIX64),
+  ("win64", libffi_sys::ffi_abi_FFI_WIN64),
+  ("efi64", libffi_sys::ffi_abi_FFI_EFI64),
+  ("gnuw64", libffi_sys::ffi_abi_FFI_GNUW64),
+];
+#[cfg(all(target_arch = "x86_64", windows))]
+const SUPPORTED_ABI: &[(&str, ffi_abi)] = &[
+  ("win64", libffi_sys::ffi_abi_FFI_WIN64),
+  ("gnuw64", libffi_sys::ffi_abi_FFI_GNUW64),
+];
+#[cfg(target_arch = "x86")]
+const SUPPORTED_ABI: &[(&str, ffi_abi)] = &[
+  ("sysv", libffi_sys::ffi_abi_FFI_SYSV),
+  ("stdcall", libffi_sys::ffi_abi_FFI_STDCALL),
+  ("thiscall", libffi_sys::ffi_abi_FFI_THISCALL),
+  ("fastcall", libffi_sys::ffi_abi_FFI_FASTCALL),
+  ("ms_cdecl", libffi_sys::ffi_abi_FFI_MS_CDECL),
+  ("pascal", libffi_sys::ffi_abi_FFI_PASCAL),
+  ("register", libffi_sys::ffi_abi_FFI_REGISTER),
+];
+#[cfg(target_arch = "arm")]
+const SUPPORTED_ABI: &[(&str, ffi_abi)] = &[
+  ("sysv", libffi_sys::ffi_abi_FFI_SYSV),
+  ("vfp", libffi_sys::ffi_abi_FFI_VFP),
+];
+#[cfg(target_arch = "aarch64")]
+const SUPPORTED_ABI: &[(&str, ffi_abi)] = &[
+  ("sysv", libffi_sys::ffi_abi_FFI_SYSV),
+  ("win64", libffi_sys::ffi_abi_FFI_WIN64),
+];
+#[cfg(not(any(
+  target_arch = "x86_64",
+  target_arch = "x86",
+  target_arch = "arm",
+  target_arch = "aarch64"
+)))]
+const SUPPORTED_ABI: &[(&str, ffi_abi)] = &[];
+
+pub fn get_ffi_abi(abi: Option<&str>) -> Result<ffi_abi> {
+  match abi {
+    None | Some("default") => Ok(ffi_abi_FFI_DEFAULT_ABI),
+    Some(abi) => SUPPORTED_ABI
+      .iter()
+      .find(|(name, _)| *name == abi)
+      .map(|(_, ffi_abi)| *ffi_abi)
+      .ok_or(
+        FFIError::UnsupportedValueType(format!(
+          "abi {:?} is not supported on the current target, supported abi are {:?}",
+          abi,
+          std::iter::once("default")
+            .chain(SUPPORTED_ABI.iter().map(|(name, _)| *name))
+            .collect::<Vec<_>>()
+        ))
+        .into(),
+      ),
   }
 }
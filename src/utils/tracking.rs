@@ -0,0 +1,197 @@
+use super::arena::Arena;
+use super::error::{with_error_code, ErrorContext};
+use super::instance::with_instance_data;
+use crate::datatype::restore_struct::rs_value_to_js_unknown;
+use crate::define::*;
+use crate::utils::error::Result;
+use indexmap::IndexMap;
+use napi::bindgen_prelude::BigInt;
+use napi::{Env, JsObject};
+use std::cell::RefCell;
+use std::ffi::c_void;
+
+/// The call which made an allocation, a native function or one of createPointer/createCallback
+#[derive(Clone)]
+pub struct AllocationSite {
+  pub library: Option<String>,
+  pub func_name: String,
+}
+
+#[derive(Clone)]
+struct Allocation {
+  type_desc: RsArgsValue,
+  site: AllocationSite,
+  callback: bool,
+}
+
+#[derive(Default)]
+pub struct AllocationTracker {
+  // in allocation order
+  live: IndexMap<*mut c_void, Allocation>,
+  // allocations are only recorded while a site is set, e.g. the values returned to a callback are
+  // owned by the caller of the callback
+  site: Option<AllocationSite>,
+}
+
+/// Record the allocations made by `f` as made by `site` when the tracking is enabled
+pub fn with_allocation_site<T>(
+  env: &Env,
+  site: impl FnOnce() -> AllocationSite,
+  f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+  let prev_site = with_instance_data(env, |data| {
+    data
+      .allocation_tracker
+      .as_mut()
+      .map(|tracker| tracker.site.replace(site()))
+  })?;
+  let result = f();
+  if let Some(prev_site) = prev_site {
+    with_instance_data(env, |data| {
+      if let Some(tracker) = &mut data.allocation_tracker {
+        tracker.site = prev_site;
+      }
+    })?;
+  }
+  result
+}
+
+thread_local! {
+  // the allocations made by the helpers of arena.rs while a value is being tracked
+  static RECORDED: RefCell<Option<Vec<*mut c_void>>> = const { RefCell::new(None) };
+}
+
+/// Called by the allocation helpers, the allocation is kept only while a value is being tracked
+pub fn record_allocation<T>(ptr: *const T) {
+  RECORDED.with(|recorded| {
+    if let Some(recorded) = recorded.borrow_mut().as_mut() {
+      recorded.push(ptr as *mut c_void);
+    }
+  });
+}
+
+/// Track every allocation made by `f` for a value of `type_desc`, the storage of the value and its
+/// nested strings, arrays and structs
+pub fn track_value_allocations<T>(
+  env: &Env,
+  type_desc: &RsArgsValue,
+  f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+  let tracking = with_instance_data(
+    env,
+    |data| matches!(&data.allocation_tracker, Some(tracker) if tracker.site.is_some()),
+  )?;
+  if !tracking {
+    return f();
+  }
+  let prev_recorded = RECORDED.with(|recorded| recorded.replace(Some(vec![])));
+  let result = f();
+  let recorded = RECORDED.with(|recorded| recorded.replace(prev_recorded));
+  // what has been allocated before an error is tracked too, it's leaked unless it's in an arena
+  recorded
+    .unwrap_or_default()
+    .into_iter()
+    .try_for_each(|ptr| track_allocation(env, ptr, type_desc, false))?;
+  result
+}
+
+pub fn track_allocation(
+  env: &Env,
+  ptr: *mut c_void,
+  type_desc: &RsArgsValue,
+  callback: bool,
+) -> Result<()> {
+  with_instance_data(env, |data| {
+    if let Some(tracker) = &mut data.allocation_tracker {
+      if let Some(site) = &tracker.site {
+        let allocation = Allocation {
+          type_desc: type_desc.clone(),
+          site: site.clone(),
+          callback,
+        };
+        tracker.live.insert(ptr, allocation);
+      }
+    }
+  })
+}
+
+pub fn untrack_allocation(env: &Env, ptr: *mut c_void) -> Result<()> {
+  with_instance_data(env, |data| {
+    if let Some(tracker) = &mut data.allocation_tracker {
+      tracker.live.shift_remove(&ptr);
+    }
+  })
+}
+
+/// Forget the allocations owned by `arena` which is being released
+pub fn untrack_arena(env: &Env, arena: &Arena) -> Result<()> {
+  with_instance_data(env, |data| {
+    if let Some(tracker) = &mut data.allocation_tracker {
+      tracker.live.retain(|ptr, _| !arena.contains(*ptr));
+    }
+  })
+}
+
+/// Record the memory ffi-rs allocates for call arguments, createPointer and callbacks until it is
+/// released, see getLiveAllocations. Disabling the tracking drops the records.
+#[napi]
+pub fn set_allocation_tracking(env: Env, enabled: bool) -> napi::Result<()> {
+  Ok(with_instance_data(&env, |data| {
+    if !enabled {
+      data.allocation_tracker = None;
+    } else if data.allocation_tracker.is_none() {
+      data.allocation_tracker = Some(AllocationTracker::default());
+    }
+  })?)
+}
+
+/// The allocations recorded since the tracking has been enabled which haven't been released yet
+#[napi]
+pub fn get_live_allocations(env: Env) -> napi::Result<Vec<JsObject>> {
+  with_error_code(&env, ErrorContext::default(), || {
+    // the records are copied out since creating the js objects must not borrow the instance data
+    let live: Vec<(*mut c_void, Allocation)> = match with_instance_data(&env, |data| {
+      data.allocation_tracker.as_ref().map(|tracker| {
+        tracker
+          .live
+          .iter()
+          .map(|(ptr, allocation)| (*ptr, allocation.clone()))
+          .collect()
+      })
+    })? {
+      Some(live) => live,
+      None => {
+        return Err(
+          FFIError::InvalidArg(
+            "allocation tracking is disabled, call setAllocationTracking(true) first".to_string(),
+          )
+          .into(),
+        )
+      }
+    };
+    live
+      .into_iter()
+      .map(|(ptr, allocation)| {
+        let mut obj = env.create_object()?;
+        obj.set_named_property("address", BigInt::from(ptr as usize as u64))?;
+        obj.set_named_property(
+          "kind",
+          env.create_string(if allocation.callback {
+            "callback"
+          } else {
+            "value"
+          })?,
+        )?;
+        obj.set_named_property(
+          "type",
+          rs_value_to_js_unknown(&env, allocation.type_desc.clone())?,
+        )?;
+        if let Some(library) = &allocation.site.library {
+          obj.set_named_property("library", env.create_string(library)?)?;
+        }
+        obj.set_named_property("funcName", env.create_string(&allocation.site.func_name)?)?;
+        Ok(obj)
+      })
+      .collect()
+  })
+}
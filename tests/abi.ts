@@ -0,0 +1,70 @@
+import { equal, throws } from "assert";
+import {
+  open,
+  close,
+  load,
+  DataType,
+  funcConstructor,
+  createPointer,
+  unwrapPointer,
+  freePointer,
+  PointerType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+export const testAbi = async () => {
+  open({ library: "libsum", path: dynamicLib });
+  // msAbiSum is declared with the win64 calling convention
+  equal(
+    load({
+      library: "libsum",
+      funcName: "msAbiSum",
+      retType: DataType.I32,
+      paramsType: [DataType.I32, DataType.I32, DataType.I32, DataType.I32, DataType.I32],
+      paramsValue: [1, 2, 3, 4, 5],
+      abi: "win64",
+    }),
+    15,
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "msAbiSum",
+        retType: DataType.I32,
+        paramsType: [DataType.I32],
+        paramsValue: [1],
+        abi: "pascal",
+      }),
+    /is not supported on the current target/,
+  );
+  const opDesc = funcConstructor({
+    paramsType: [DataType.I32, DataType.I32],
+    retType: DataType.I32,
+    abi: "win64",
+  });
+  const op = createPointer({
+    paramsType: [opDesc],
+    paramsValue: [(a: number, b: number) => a * b],
+  });
+  equal(
+    await load({
+      library: "libsum",
+      funcName: "callMsAbiOp",
+      retType: DataType.I32,
+      paramsType: [DataType.External, DataType.I32, DataType.I32],
+      paramsValue: [unwrapPointer(op)[0], 6, 7],
+      // callbacks can only return a value to C when they are called from another thread
+      runInNewThread: true,
+    }),
+    42,
+  );
+  freePointer({
+    paramsType: [opDesc],
+    paramsValue: op,
+    pointerType: PointerType.RsPointer,
+  });
+  close("libsum");
+};
@@ -0,0 +1,63 @@
+import { equal, ok, throws, deepStrictEqual } from "assert";
+import { open, close, load, arrayConstructor, DataType } from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const isInvalidArg = (funcName: string, argIndex: number) => (err) => {
+  equal(err.code, "ERR_FFI_INVALID_ARG");
+  equal(err.funcName, funcName);
+  equal(err.argIndex, argIndex);
+  ok(err.message.includes(`paramsValue[${argIndex}]`), err.message);
+  return true;
+};
+
+const createArrayi32 = (paramsValue: unknown[], length = 3) =>
+  load({
+    library: "libsum",
+    funcName: "createArrayi32",
+    retType: arrayConstructor({ type: DataType.I32Array, length }),
+    paramsType: [arrayConstructor({ type: DataType.I32Array, length }), DataType.I32],
+    paramsValue,
+  });
+
+export const testArgCheck = () => {
+  open({ library: "libsum", path: dynamicLib });
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "sum",
+        retType: DataType.I32,
+        paramsType: [DataType.I32, DataType.I32],
+        paramsValue: [1, "2"],
+      }),
+    isInvalidArg("sum", 1),
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "concatenateStrings",
+        retType: DataType.String,
+        paramsType: [DataType.String, DataType.String],
+        paramsValue: ["foo", 1],
+      }),
+    isInvalidArg("concatenateStrings", 1),
+  );
+  throws(() => createArrayi32([[1, 2], 3]), isInvalidArg("createArrayi32", 0));
+  throws(() => createArrayi32([{}, 3]), isInvalidArg("createArrayi32", 0));
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "sum",
+        retType: DataType.I32,
+        paramsType: [DataType.I32, DataType.I32],
+        paramsValue: [1],
+      }),
+    (err) => err.code === "ERR_FFI_ARG_COUNT" && err.funcName === "sum",
+  );
+  deepStrictEqual(createArrayi32([[1, 2, 3], 3]), [1, 2, 3]);
+  close("libsum");
+};
@@ -0,0 +1,115 @@
+import { equal, deepStrictEqual, throws } from "assert";
+import { setFlagsFromString } from "v8";
+import { runInNewContext } from "vm";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  restorePointer,
+  createExternalArrayBuffer,
+  DataType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+setFlagsFromString("--expose-gc");
+const gc = runInNewContext("gc");
+
+const collect = async () => {
+  for (let i = 0; i < 3; i++) {
+    gc();
+    await new Promise((resolve) => setImmediate(resolve));
+  }
+};
+
+const fillI32Array = (ptr: unknown, len: number, runInNewThread = false) =>
+  load({
+    library: "libsum",
+    funcName: "fillI32Array",
+    retType: DataType.Void,
+    paramsType: [DataType.External, DataType.I32],
+    paramsValue: [ptr, len],
+    runInNewThread,
+  });
+
+const destroyedHandleTotal = () =>
+  load({
+    library: "libsum",
+    funcName: "destroyedHandleTotal",
+    retType: DataType.I32,
+    paramsType: [],
+    paramsValue: [],
+  });
+
+export const testArrayBuffer = async () => {
+  open({ library: "libsum", path: dynamicLib });
+
+  const sab = new SharedArrayBuffer(16);
+  fillI32Array(sab, 3);
+  deepStrictEqual(Array.from(new Int32Array(sab)), [0, 2, 4, 0]);
+  const ab = new ArrayBuffer(8);
+  await fillI32Array(ab, 2, true);
+  deepStrictEqual(Array.from(new Int32Array(ab)), [0, 2]);
+  // a view passes the memory from its byteOffset
+  const shared = new Int32Array(new SharedArrayBuffer(16));
+  fillI32Array(new Uint8Array(shared.buffer, 8, 8), 2);
+  deepStrictEqual(Array.from(shared), [0, 0, 0, 2]);
+  throws(
+    () => fillI32Array([0, 0], 2),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+
+  // the memory of createPointer is shared with the ArrayBuffer
+  const [ptr] = createPointer({ paramsType: [DataType.I32], paramsValue: [7] });
+  const i32 = new Int32Array(createExternalArrayBuffer(ptr, 4));
+  equal(i32[0], 7);
+  i32[0] = 8;
+  equal(restorePointer({ retType: [DataType.I32], paramsValue: [ptr] })[0], 8);
+
+  // the finalizer releases the memory with the ArrayBuffer
+  const total = destroyedHandleTotal();
+  (() => {
+    const handle = load({
+      library: "libsum",
+      funcName: "createHandle",
+      retType: DataType.External,
+      paramsType: [DataType.I32],
+      paramsValue: [3],
+    });
+    const view = new Int32Array(
+      createExternalArrayBuffer(handle, 4, { library: "libsum", funcName: "destroyHandle" }),
+    );
+    equal(view[0], 3);
+    view[0] = 9;
+  })();
+  await collect();
+  equal(destroyedHandleTotal(), total + 9);
+
+  // the views of a call on another thread are kept alive until it returns
+  const { pending, view } = (() => {
+    const i32 = new Int32Array(1024);
+    return {
+      pending: load({
+        library: "libsum",
+        funcName: "delayFillI32Array",
+        retType: DataType.Void,
+        paramsType: [DataType.External, DataType.I32, DataType.I32],
+        paramsValue: [i32, 1024, 100],
+        runInNewThread: true,
+      }),
+      view: new WeakRef(i32),
+    };
+  })();
+  await collect();
+  equal(view.deref()?.[1], 0);
+  await pending;
+  equal(view.deref()?.[1023], 2046);
+
+  throws(
+    () => createExternalArrayBuffer(ptr, -1),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+  close("libsum");
+};
@@ -0,0 +1,68 @@
+import { equal, throws } from "assert";
+import { open, close, load, callPointer, DataType } from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const getBinaryOp = (name: string) =>
+  load({
+    library: "libsum",
+    funcName: "getBinaryOp",
+    retType: DataType.External,
+    paramsType: [DataType.String],
+    paramsValue: [name],
+  });
+
+export const testCallPointer = async () => {
+  const lib = open({ library: "libsum", path: dynamicLib });
+  const multiply = getBinaryOp("multiply");
+  equal(
+    callPointer({
+      funcPtr: multiply,
+      retType: DataType.I32,
+      paramsType: [DataType.I32, DataType.I32],
+      paramsValue: [6, 7],
+    }),
+    42,
+  );
+  equal(
+    await callPointer({
+      funcPtr: getBinaryOp("sum"),
+      retType: DataType.I32,
+      paramsType: [DataType.I32, DataType.I32],
+      paramsValue: [6, 7],
+      runInNewThread: true,
+    }),
+    13,
+  );
+  const { value } = callPointer({
+    funcPtr: lib.symbol("doubleSum"),
+    retType: DataType.Double,
+    paramsType: [DataType.Double, DataType.Double],
+    paramsValue: [1.5, 2.25],
+    errno: true,
+  });
+  equal(value, 3.75);
+  throws(
+    () =>
+      callPointer({
+        funcPtr: getBinaryOp("unknown"),
+        retType: DataType.I32,
+        paramsType: [DataType.I32, DataType.I32],
+        paramsValue: [1, 2],
+      }),
+    /funcPtr is a null pointer/,
+  );
+  const notAPointer: any = 42;
+  throws(
+    () =>
+      callPointer({
+        funcPtr: notAPointer,
+        retType: DataType.I32,
+        paramsType: [DataType.I32, DataType.I32],
+        paramsValue: [1, 2],
+      }),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+  close("libsum");
+};
@@ -0,0 +1,113 @@
+import { equal, ok } from "assert";
+import {
+  open,
+  close,
+  load,
+  callPointer,
+  DataType,
+  funcConstructor,
+  createCallback,
+  createPointer,
+  unwrapPointer,
+  freePointer,
+  PointerType,
+} from "../index";
+import { logGreen } from "./utils";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const runIntOps = (ops: unknown, value: number) =>
+  load({
+    library: "libsum",
+    funcName: "runIntOps",
+    retType: DataType.I32,
+    paramsType: [DataType.External, DataType.I32],
+    paramsValue: [ops, value],
+    // callbacks can only return a value to C when they are called from another thread
+    runInNewThread: true,
+  });
+
+async function testFunctionStructField() {
+  const intOpType = funcConstructor({
+    paramsType: [DataType.I32],
+    retType: DataType.I32,
+    needFree: true,
+  });
+  const intOpsType = {
+    read: intOpType,
+    transform: intOpType,
+  };
+  const opsPointer = createPointer({
+    paramsType: [intOpsType],
+    paramsValue: [
+      {
+        read: (value: number) => value + 1,
+        transform: (value: number) => value * 10,
+      },
+    ],
+  });
+  equal(await runIntOps(unwrapPointer(opsPointer)[0], 4), 50);
+  freePointer({
+    paramsType: [intOpsType],
+    paramsValue: opsPointer,
+    pointerType: PointerType.RsPointer,
+  });
+  logGreen("test function struct field succeed");
+}
+
+async function testCreateCallback() {
+  const signature = {
+    paramsType: [DataType.I32],
+    retType: DataType.I32,
+  };
+  const read = createCallback((value: number) => value - 1, signature);
+  const transform = createCallback((value: number) => value * value, signature);
+  const opsPointer = createPointer({
+    paramsType: [{ read: DataType.External, transform: DataType.External }],
+    paramsValue: [{ read, transform }],
+  });
+  equal(await runIntOps(unwrapPointer(opsPointer)[0], 5), 16);
+  equal(
+    await callPointer({
+      funcPtr: transform,
+      retType: DataType.I32,
+      paramsType: [DataType.I32],
+      paramsValue: [9],
+      runInNewThread: true,
+    }),
+    81,
+  );
+  logGreen("test create callback succeed");
+}
+
+async function testCallbackParamsError() {
+  const callback = createCallback(() => 5, {
+    paramsType: [DataType.U16String],
+    retType: DataType.I32,
+  });
+  const uncaught = new Promise((resolve) => process.once("uncaughtException", resolve));
+  // the params can't be read so the callback isn't called, c gets a zero result
+  equal(
+    await load({
+      library: "libsum",
+      funcName: "callWithNullU16String",
+      retType: DataType.I32,
+      paramsType: [DataType.External],
+      paramsValue: [callback],
+      runInNewThread: true,
+    }),
+    1,
+  );
+  const isEncodingError = (err) => err.code === "ERR_FFI_ENCODING";
+  ok(isEncodingError(await uncaught));
+  logGreen("test callback params error succeed");
+}
+
+export const testCallback = async () => {
+  open({ library: "libsum", path: dynamicLib });
+  await testFunctionStructField();
+  await testCreateCallback();
+  await testCallbackParamsError();
+  close("libsum");
+};
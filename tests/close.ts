@@ -0,0 +1,211 @@
+import { equal, throws } from "assert";
+import { copyFileSync, unlinkSync } from "fs";
+import { tmpdir } from "os";
+import { basename, join } from "path";
+import {
+  open,
+  close,
+  load,
+  DataType,
+  funcConstructor,
+  createCallback,
+  createPointer,
+  unwrapPointer,
+  freePointer,
+  PointerType,
+} from "../index";
+import { logGreen } from "./utils";
+
+const platform = process.platform;
+const sourceLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+// a copy of the library which nothing else in the process keeps loaded, so closing it really
+// unloads it and resets its globals
+const dynamicLib = join(tmpdir(), `close-test-${process.pid}-${basename(sourceLib)}`);
+
+const triggerCallback = (value: number) =>
+  load({
+    library: "libsumClose",
+    funcName: "triggerCallback",
+    retType: DataType.I32,
+    paramsType: [DataType.I32],
+    paramsValue: [value],
+  });
+
+const destroyedHandleTotal = () =>
+  load({
+    library: "libsumClose",
+    funcName: "destroyedHandleTotal",
+    retType: DataType.I32,
+    paramsType: [],
+    paramsValue: [],
+  });
+
+async function testCloseByName() {
+  const lib = open({ library: "libsumClose", path: dynamicLib });
+  const handle = load({
+    library: "libsumClose",
+    funcName: "createHandle",
+    retType: DataType.External,
+    paramsType: [DataType.I32],
+    paramsValue: [3],
+  });
+  load({
+    library: "libsumClose",
+    funcName: "destroyHandle",
+    retType: DataType.Void,
+    paramsType: [DataType.External],
+    paramsValue: [handle],
+  });
+  equal(destroyedHandleTotal(), 3);
+  // close(name) releases the library although its handle is still referenced
+  close("libsumClose");
+  throws(() => lib.has("createHandle"), /has been closed/);
+  open({ library: "libsumClose", path: dynamicLib });
+  equal(destroyedHandleTotal(), 0);
+  close("libsumClose");
+  logGreen("test close by name succeed");
+}
+
+async function testCloseWithPendingCall() {
+  const lib = open({ library: "libsumClose", path: dynamicLib });
+  const pending = load({
+    library: "libsumClose",
+    funcName: "delaySum",
+    retType: DataType.I32,
+    paramsType: [DataType.I32, DataType.I32, DataType.I32],
+    paramsValue: [1, 2, 100],
+    runInNewThread: true,
+  });
+  // the library is unloaded once the call returns
+  lib.close();
+  equal(await pending, 3);
+  logGreen("test close with pending call succeed");
+}
+
+async function testCloseWithCallback() {
+  const funcDesc = funcConstructor({
+    paramsType: [DataType.I32],
+    retType: DataType.Void,
+  });
+  const received: number[] = [];
+  const funcExternal = createPointer({
+    paramsType: [funcDesc],
+    paramsValue: [(value: number) => received.push(value)],
+  });
+  let lib = open({ library: "libsumClose", path: dynamicLib });
+  load({
+    library: "libsumClose",
+    funcName: "registerCallback",
+    retType: DataType.Void,
+    paramsType: [DataType.External],
+    paramsValue: unwrapPointer(funcExternal),
+  });
+  lib.close();
+  // the registered callback keeps the library loaded, so reopening it finds the callback again
+  lib = open({ library: "libsumClose", path: dynamicLib });
+  equal(triggerCallback(7), 0);
+  await new Promise((res) => setTimeout(res, 10));
+  equal(received.join(), "7");
+
+  // freeing the callback releases its reference, the library is really unloaded
+  lib.close();
+  freePointer({
+    paramsType: [funcDesc],
+    paramsValue: funcExternal,
+    pointerType: PointerType.RsPointer,
+  });
+  lib = open({ library: "libsumClose", path: dynamicLib });
+  equal(triggerCallback(7), -1);
+  lib.close();
+  logGreen("test close with callback succeed");
+}
+
+async function testCloseWithStructCallback() {
+  const received: number[] = [];
+  const callback = createCallback((value: number) => received.push(value), {
+    paramsType: [DataType.I32],
+    retType: DataType.Void,
+  });
+  let lib = open({ library: "libsumClose", path: dynamicLib });
+  load({
+    library: "libsumClose",
+    funcName: "registerCallbackHolder",
+    retType: DataType.Void,
+    paramsType: [{ callback: DataType.External }],
+    paramsValue: [{ callback }],
+  });
+  lib.close();
+  // a callback in a field of a struct keeps the library loaded as well
+  lib = open({ library: "libsumClose", path: dynamicLib });
+  equal(triggerCallback(8), 0);
+  await new Promise((res) => setTimeout(res, 10));
+  equal(received.join(), "8");
+  lib.close();
+  logGreen("test close with struct callback succeed");
+}
+
+async function testForceClose() {
+  const funcDesc = funcConstructor({
+    paramsType: [DataType.I32],
+    retType: DataType.Void,
+  });
+  const funcExternal = createPointer({
+    paramsType: [funcDesc],
+    paramsValue: [() => {}],
+  });
+  let lib = open({ library: "libsumClose", path: dynamicLib });
+  load({
+    library: "libsumClose",
+    funcName: "registerCallback",
+    retType: DataType.Void,
+    paramsType: [DataType.External],
+    paramsValue: unwrapPointer(funcExternal),
+  });
+  const pending = load({
+    library: "libsumClose",
+    funcName: "delaySum",
+    retType: DataType.I32,
+    paramsType: [DataType.I32, DataType.I32, DataType.I32],
+    paramsValue: [1, 2, 100],
+    runInNewThread: true,
+  });
+  // the callback doesn't keep the library loaded anymore, the pending call still does
+  lib.close({ force: true });
+  equal(await pending, 3);
+  lib = open({ library: "libsumClose", path: dynamicLib });
+  equal(triggerCallback(7), -1);
+
+  load({
+    library: "libsumClose",
+    funcName: "registerCallback",
+    retType: DataType.Void,
+    paramsType: [DataType.External],
+    paramsValue: unwrapPointer(funcExternal),
+  });
+  close("libsumClose", { force: true });
+  open({ library: "libsumClose", path: dynamicLib });
+  equal(triggerCallback(7), -1);
+  close("libsumClose");
+  freePointer({
+    paramsType: [funcDesc],
+    paramsValue: funcExternal,
+    pointerType: PointerType.RsPointer,
+  });
+  logGreen("test force close succeed");
+}
+
+export const testClose = async () => {
+  copyFileSync(sourceLib, dynamicLib);
+  try {
+    await testCloseByName();
+    await testCloseWithPendingCall();
+    await testCloseWithCallback();
+    await testCloseWithStructCallback();
+    await testForceClose();
+  } finally {
+    // a library which is still loaded can't be removed on windows
+    try {
+      unlinkSync(dynamicLib);
+    } catch {}
+  }
+};
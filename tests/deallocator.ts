@@ -0,0 +1,136 @@
+import { equal, deepStrictEqual, throws } from "assert";
+import {
+  open,
+  close,
+  load,
+  freePointer,
+  wrapPointer,
+  arrayConstructor,
+  DataType,
+  PointerType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+// the memory is allocated with a header by customAlloc, libc free would abort
+const customFree = { library: "libsum", funcName: "customFree" };
+const customFreedCount = () =>
+  load({
+    library: "libsum",
+    funcName: "customFreedCount",
+    retType: DataType.I32,
+    paramsType: [],
+    paramsValue: [],
+  });
+
+export const testDeallocator = () => {
+  const lib = open({ library: "libsum", path: dynamicLib });
+  equal(
+    load({
+      library: "libsum",
+      funcName: "customString",
+      retType: DataType.String,
+      paramsType: [DataType.String],
+      paramsValue: ["hello"],
+      freeResultMemory: true,
+      deallocator: customFree,
+    }),
+    "hello",
+  );
+  equal(customFreedCount(), 1);
+  deepStrictEqual(
+    load({
+      library: "libsum",
+      funcName: "customI32Array",
+      retType: arrayConstructor({ type: DataType.I32Array, length: 3 }),
+      paramsType: [DataType.I32],
+      paramsValue: [3],
+      freeResultMemory: true,
+      deallocator: lib.symbol("customFree"),
+    }),
+    [0, 1, 2],
+  );
+  equal(customFreedCount(), 2);
+  deepStrictEqual(
+    load({
+      library: "libsum",
+      funcName: "customStringArray",
+      retType: arrayConstructor({ type: DataType.StringArray, length: 2 }),
+      paramsType: [],
+      paramsValue: [],
+      freeResultMemory: true,
+      deallocator: customFree,
+    }),
+    ["foo", "bar"],
+  );
+  equal(customFreedCount(), 5);
+  deepStrictEqual(
+    load({
+      library: "libsum",
+      funcName: "customPerson",
+      retType: { name: DataType.String, age: DataType.I32 },
+      paramsType: [DataType.String, DataType.I32],
+      paramsValue: ["tom", 23],
+      freeResultMemory: true,
+      deallocator: customFree,
+    }),
+    { name: "tom", age: 23 },
+  );
+  equal(customFreedCount(), 7);
+
+  const str = load({
+    library: "libsum",
+    funcName: "customString",
+    retType: DataType.External,
+    paramsType: [DataType.String],
+    paramsValue: ["world"],
+  });
+  freePointer({
+    paramsType: [DataType.External],
+    paramsValue: [str],
+    pointerType: PointerType.CPointer,
+    deallocator: customFree,
+  });
+  equal(customFreedCount(), 8);
+  // only the string is released by the deallocator, wrapPointer's storage belongs to ffi-rs
+  freePointer({
+    paramsType: [DataType.String],
+    paramsValue: wrapPointer([
+      load({
+        library: "libsum",
+        funcName: "customString",
+        retType: DataType.External,
+        paramsType: [DataType.String],
+        paramsValue: ["wrapped"],
+      }),
+    ]),
+    pointerType: PointerType.CPointer,
+    deallocator: customFree,
+  });
+  equal(customFreedCount(), 9);
+  throws(
+    () =>
+      freePointer({
+        paramsType: [DataType.External],
+        paramsValue: [str],
+        pointerType: PointerType.RsPointer,
+        deallocator: customFree,
+      }),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "customString",
+        retType: DataType.String,
+        paramsType: [DataType.String],
+        paramsValue: ["hello"],
+        freeResultMemory: true,
+        deallocator: "free",
+      }),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+  close("libsum");
+};
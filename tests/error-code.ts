@@ -0,0 +1,89 @@
+import { equal, ok, throws } from "assert";
+import { open, close, load, DataType } from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const sum = (paramsValue: unknown[], funcName = "sum") =>
+  load({
+    library: "libsum",
+    funcName,
+    retType: DataType.I32,
+    paramsType: [DataType.I32, DataType.I32],
+    paramsValue,
+  });
+
+export const testErrorCode = () => {
+  throws(
+    () => open({ library: "missing", path: "./libmissing.so" }),
+    (err) => {
+      equal(err.code, "ERR_FFI_LIBRARY_NOT_FOUND");
+      equal(err.library, "missing");
+      ok(err.dlerror.includes("libmissing.so"));
+      return true;
+    },
+  );
+  throws(
+    () => sum([1, 2]),
+    (err) => {
+      equal(err.code, "ERR_FFI_LIBRARY_NOT_FOUND");
+      equal(err.library, "libsum");
+      equal(err.funcName, "sum");
+      return true;
+    },
+  );
+  const lib = open({ library: "libsum", path: dynamicLib });
+  // the result of the symbol lookup is cached, the second lookup throws the same error
+  for (let i = 0; i < 2; i++) {
+    throws(
+      () => sum([1, 2], "unknownFunc"),
+      (err) => {
+        equal(err.code, "ERR_FFI_SYMBOL_NOT_FOUND");
+        equal(err.funcName, "unknownFunc");
+        return true;
+      },
+    );
+  }
+  throws(
+    () => sum([1]),
+    (err) => {
+      equal(err.code, "ERR_FFI_ARG_COUNT");
+      equal(err.funcName, "sum");
+      return true;
+    },
+  );
+  throws(
+    () => sum([1, "2"]),
+    (err) => {
+      equal(err.argIndex, 1);
+      equal(err.funcName, "sum");
+      return true;
+    },
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "sum",
+        retType: DataType.I32,
+        paramsType: [DataType.I32, DataType.I32],
+        paramsValue: [1, 2],
+        abi: "unknown",
+      }),
+    (err) => err.code === "ERR_FFI_TYPE",
+  );
+  throws(
+    () => lib.func("unknownFunc", { retType: DataType.Void, paramsType: [] }),
+    (err) => {
+      equal(err.code, "ERR_FFI_SYMBOL_NOT_FOUND");
+      equal(err.library, "libsum");
+      return true;
+    },
+  );
+  equal(sum([1, 2]), 3);
+  lib.close();
+  throws(
+    () => lib.symbol("sum"),
+    (err) => err.code === "ERR_FFI_LIBRARY_NOT_FOUND" && err.library === "libsum",
+  );
+};
@@ -0,0 +1,111 @@
+import { equal, deepStrictEqual, throws } from "assert";
+import { setFlagsFromString } from "v8";
+import { runInNewContext } from "vm";
+import {
+  open,
+  close,
+  load,
+  createExternalBuffer,
+  pointerToAddress,
+  DataType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+setFlagsFromString("--expose-gc");
+const gc = runInNewContext("gc");
+
+// the finalizers of collected Buffers may be deferred until the next tick
+const collect = async () => {
+  for (let i = 0; i < 3; i++) {
+    gc();
+    await new Promise((resolve) => setImmediate(resolve));
+  }
+};
+
+const destroyHandle = { library: "libsum", funcName: "destroyHandle" };
+const createHandle = (value: number) =>
+  load({
+    library: "libsum",
+    funcName: "createHandle",
+    retType: DataType.External,
+    paramsType: [DataType.I32],
+    paramsValue: [value],
+  });
+const destroyedHandleTotal = () =>
+  load({
+    library: "libsum",
+    funcName: "destroyedHandleTotal",
+    retType: DataType.I32,
+    paramsType: [],
+    paramsValue: [],
+  });
+
+const invalidFinalizer: any = 42;
+
+export const testExternalBuffer = async () => {
+  open({ library: "libsum", path: dynamicLib });
+  const total = destroyedHandleTotal();
+
+  // the Buffer owns the memory and releases it when it is collected
+  (() => {
+    const buf = createExternalBuffer(createHandle(5), 4, destroyHandle);
+    equal(buf.readInt32LE(0), 5);
+    buf.writeInt32LE(6, 0);
+  })();
+  await collect();
+  equal(destroyedHandleTotal(), total + 6);
+
+  (() => {
+    const arr = load({
+      library: "libsum",
+      funcName: "createArrayi32",
+      retType: DataType.External,
+      paramsType: [DataType.I32Array, DataType.I32],
+      paramsValue: [[1, 2], 2],
+    });
+    const buf = createExternalBuffer(arr, 8, "free");
+    deepStrictEqual([buf.readInt32LE(0), buf.readInt32LE(4)], [1, 2]);
+  })();
+
+  const released = [];
+  const address = (() => {
+    const handle = createHandle(7);
+    createExternalBuffer(handle, 4, (ptr) => {
+      released.push(pointerToAddress(ptr));
+      load({
+        library: "libsum",
+        funcName: "destroyHandle",
+        retType: DataType.Void,
+        paramsType: [DataType.External],
+        paramsValue: [ptr],
+      });
+    });
+    return pointerToAddress(handle);
+  })();
+  await collect();
+  deepStrictEqual(released, [address]);
+  equal(destroyedHandleTotal(), total + 13);
+
+  // an empty Buffer doesn't wrap the memory, it is released at once
+  createExternalBuffer(createHandle(2), 0, destroyHandle);
+  equal(destroyedHandleTotal(), total + 15);
+
+  // an error thrown by the finalizer has no caller, it's reported as an uncaught exception
+  const uncaught = new Promise((resolve) => process.once("uncaughtException", resolve));
+  (() => {
+    createExternalBuffer(createHandle(3), 4, () => {
+      throw new Error("finalizer failed");
+    });
+  })();
+  await collect();
+  const err: any = await uncaught;
+  equal(err.message, "finalizer failed");
+
+  throws(
+    () => createExternalBuffer(createHandle(1), 4, invalidFinalizer),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+  close("libsum");
+};
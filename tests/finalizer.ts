@@ -0,0 +1,147 @@
+import { equal, ok, throws } from "assert";
+import { setFlagsFromString } from "v8";
+import { runInNewContext } from "vm";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  restorePointer,
+  isNullPointer,
+  dispose,
+  freePointer,
+  DataType,
+  PointerType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+setFlagsFromString("--expose-gc");
+const gc = runInNewContext("gc");
+
+const destroyHandle = { library: "libsum", funcName: "destroyHandle" };
+const createHandle = (value: number, finalizer: unknown = destroyHandle) =>
+  load({
+    library: "libsum",
+    funcName: "createHandle",
+    retType: DataType.External,
+    paramsType: [DataType.I32],
+    paramsValue: [value],
+    finalizer,
+  });
+const destroyedHandleTotal = () =>
+  load({
+    library: "libsum",
+    funcName: "destroyedHandleTotal",
+    retType: DataType.I32,
+    paramsType: [],
+    paramsValue: [],
+  });
+
+// the finalizers of collected Externals may be deferred until the next tick
+const collect = async () => {
+  for (let i = 0; i < 3; i++) {
+    gc();
+    await new Promise((resolve) => setImmediate(resolve));
+  }
+};
+
+export const testFinalizer = async () => {
+  const lib = open({ library: "libsum", path: dynamicLib });
+  const handle = createHandle(5);
+  equal(dispose(handle), true);
+  equal(destroyedHandleTotal(), 5);
+  ok(isNullPointer(handle));
+  equal(dispose(handle), false);
+
+  createHandle(7);
+  createHandle(11, lib.symbol("destroyHandle"));
+  await collect();
+  equal(destroyedHandleTotal(), 23);
+
+  // the library stays loaded while a destructor may still run
+  const pinned = createHandle(13);
+  const kept = createHandle(17);
+  close("libsum");
+  equal(dispose(pinned), true);
+  open({ library: "libsum", path: dynamicLib });
+  equal(destroyedHandleTotal(), 36);
+  equal(dispose(kept), true);
+  equal(destroyedHandleTotal(), 53);
+  const asyncHandle = await load({
+    library: "libsum",
+    funcName: "createHandle",
+    retType: DataType.External,
+    paramsType: [DataType.I32],
+    paramsValue: [19],
+    runInNewThread: true,
+    finalizer: destroyHandle,
+  });
+  equal(dispose(asyncHandle), true);
+  equal(destroyedHandleTotal(), 72);
+
+  const [str, num] = createPointer({
+    paramsType: [DataType.String, DataType.I32],
+    paramsValue: ["foo", 1],
+    finalizer: PointerType.RsPointer,
+  });
+  equal(restorePointer({ retType: [DataType.String], paramsValue: [str] })[0], "foo");
+  equal(dispose(str), true);
+  equal(dispose(num), true);
+  createPointer({
+    paramsType: [DataType.String],
+    paramsValue: ["bar"],
+    finalizer: PointerType.RsPointer,
+  });
+  await collect();
+
+  // freePointer cancels the finalizer, the memory isn't released again when the External is collected
+  (() => {
+    const [freed] = createPointer({
+      paramsType: [DataType.String],
+      paramsValue: ["baz"],
+      finalizer: PointerType.RsPointer,
+    });
+    freePointer({
+      paramsType: [DataType.String],
+      paramsValue: [freed],
+      pointerType: PointerType.RsPointer,
+    });
+    ok(isNullPointer(freed));
+    equal(dispose(freed), false);
+    const freedHandle = createHandle(23);
+    freePointer({
+      paramsType: [DataType.External],
+      paramsValue: [freedHandle],
+      pointerType: PointerType.CPointer,
+      deallocator: destroyHandle,
+    });
+  })();
+  await collect();
+  equal(destroyedHandleTotal(), 95);
+
+  const [plain] = createPointer({ paramsType: [DataType.I32], paramsValue: [1] });
+  equal(dispose(plain), false);
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "sum",
+        retType: DataType.I32,
+        paramsType: [DataType.I32, DataType.I32],
+        paramsValue: [1, 2],
+        finalizer: destroyHandle,
+      }),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+  throws(
+    () => createHandle(1, PointerType.CPointer),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+  throws(
+    () => createHandle(1, { library: "libsum", funcName: "unknownDestructor" }),
+    (err) => err.code === "ERR_FFI_SYMBOL_NOT_FOUND",
+  );
+  close("libsum");
+};
@@ -0,0 +1,46 @@
+import { equal, ok, rejects, throws } from "assert";
+import { open, close, load, isTainted, DataType } from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const readIntAt = (address: number, runInNewThread: boolean) =>
+  load({
+    library: "libsum",
+    funcName: "readIntAt",
+    retType: DataType.I32,
+    paramsType: [DataType.I64],
+    paramsValue: [address],
+    guarded: true,
+    runInNewThread,
+  });
+
+export const testGuarded = async () => {
+  open({ library: "libsum", path: dynamicLib });
+  const warnings: string[] = [];
+  process.on("warning", (warning) => warnings.push(warning.name));
+  equal(
+    load({
+      library: "libsum",
+      funcName: "sum",
+      retType: DataType.I32,
+      paramsType: [DataType.I32, DataType.I32],
+      paramsValue: [1, 2],
+      guarded: true,
+    }),
+    3,
+  );
+  equal(isTainted(), false);
+  throws(() => readIntAt(16, false), (err) => {
+    ok(/readIntAt received SIGSEGV while accessing address 0x10/.test(err.message));
+    equal(err.code, "ERR_FFI_FAULT");
+    return true;
+  });
+  ok(isTainted());
+  await rejects(readIntAt(32, true), /readIntAt received SIGSEGV while accessing address 0x20/);
+  // the process keeps working after a fault
+  throws(() => readIntAt(16, false), /SIGSEGV/);
+  await new Promise((res) => setImmediate(res));
+  equal(warnings.filter((name) => name === "FFITaintWarning").length, 3);
+  close("libsum");
+};
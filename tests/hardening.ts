@@ -0,0 +1,129 @@
+import { equal, deepStrictEqual, rejects, throws } from "assert";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  restorePointer,
+  freePointer,
+  dispose,
+  withScope,
+  setMemoryHardening,
+  DataType,
+  PointerType,
+} from "../index";
+import { person, personType } from "./types";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+// the bytes of a released hardened allocation
+const POISON_I32 = 0xdddddddd | 0;
+
+const fillI32Array = (arr: number[], len: number, runInNewThread = false) =>
+  load({
+    library: "libsum",
+    funcName: "fillI32Array",
+    retType: DataType.Void,
+    paramsType: [DataType.I32Array, DataType.I32],
+    paramsValue: [arr, len],
+    runInNewThread,
+  });
+
+const isOutOfBounds = (err) =>
+  err.code === "ERR_FFI_OUT_OF_BOUNDS" && err.argIndex === 0 && err.funcName === "fillI32Array";
+
+export const testHardening = async () => {
+  open({ library: "libsum", path: dynamicLib });
+  setMemoryHardening(true);
+
+  fillI32Array([0, 0, 0], 3);
+  throws(() => fillI32Array([0, 0, 0], 4), isOutOfBounds);
+  await fillI32Array([0, 0, 0], 3, true);
+  await rejects(fillI32Array([0, 0, 0], 4, true), isOutOfBounds);
+  load({
+    library: "libsum",
+    funcName: "fillString",
+    retType: DataType.Void,
+    paramsType: [DataType.String, DataType.I32],
+    paramsValue: ["foo", 4],
+  });
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "fillString",
+        retType: DataType.Void,
+        paramsType: [DataType.String, DataType.I32],
+        paramsValue: ["foo", 5],
+      }),
+    (err) => err.code === "ERR_FFI_OUT_OF_BOUNDS" && err.argIndex === 0,
+  );
+
+  // freed memory reads as poison
+  const [num, personPtr] = createPointer({
+    paramsType: [DataType.I32, personType],
+    paramsValue: [42, person],
+  });
+  deepStrictEqual(restorePointer({ retType: [personType], paramsValue: [personPtr] })[0], person);
+  freePointer({
+    paramsType: [DataType.I32, personType],
+    paramsValue: [num, personPtr],
+    pointerType: PointerType.RsPointer,
+  });
+  equal(restorePointer({ retType: [DataType.I32], paramsValue: [num] })[0], POISON_I32);
+  // freeing it again is reported instead of releasing memory the allocator doesn't own
+  const isFreed = (err) => err.code === "ERR_FFI_INVALID_ARG" && /has already been freed/.test(err.message);
+  throws(
+    () =>
+      freePointer({
+        paramsType: [personType],
+        paramsValue: [personPtr],
+        pointerType: PointerType.RsPointer,
+      }),
+    isFreed,
+  );
+  throws(
+    () =>
+      freePointer({
+        paramsType: [personType],
+        paramsValue: [personPtr],
+        pointerType: PointerType.CPointer,
+      }),
+    isFreed,
+  );
+
+  const [disposed] = createPointer({
+    paramsType: [DataType.I32],
+    paramsValue: [7],
+    finalizer: PointerType.RsPointer,
+  });
+  equal(dispose(disposed), true);
+
+  const scoped = withScope(() => {
+    const [ptr] = createPointer({ paramsType: [DataType.I32], paramsValue: [1] });
+    equal(restorePointer({ retType: [DataType.I32], paramsValue: [ptr] })[0], 1);
+    return ptr;
+  });
+  equal(restorePointer({ retType: [DataType.I32], paramsValue: [scoped] })[0], POISON_I32);
+  throws(
+    () =>
+      freePointer({
+        paramsType: [DataType.I32],
+        paramsValue: [scoped],
+        pointerType: PointerType.RsPointer,
+      }),
+    isFreed,
+  );
+
+  // the pointers created in hardened mode are still released after it is disabled
+  const [late] = createPointer({ paramsType: [DataType.String], paramsValue: ["foo"] });
+  setMemoryHardening(false);
+  freePointer({
+    paramsType: [DataType.String],
+    paramsValue: [late],
+    pointerType: PointerType.RsPointer,
+  });
+  fillI32Array([0, 0, 0], 3);
+  close("libsum");
+};
@@ -18,6 +18,31 @@ import {
 } from "../index";
 import { logGreen } from "./utils";
 import { testObject } from "./struct";
+import { testLibraryHandle } from "./library";
+import { testCallPointer } from "./call-pointer";
+import { testCallback } from "./callback";
+import { testStackStructCallback } from "./stack-struct-callback";
+import { testStructView } from "./struct-view";
+import { testLayout } from "./layout";
+import { testAbi } from "./abi";
+import { testArgCheck } from "./arg-check";
+import { testTypeError } from "./type-error";
+import { testErrorCode } from "./error-code";
+import { testNullCharacter } from "./null-character";
+import { testStringEncoding } from "./string-encoding";
+import { testTypedArray } from "./typed-array";
+import { testArrayBuffer } from "./array-buffer";
+import { testExternalBuffer } from "./external-buffer";
+import { testMemory } from "./memory";
+import { testPointerAddress } from "./pointer-address";
+import { testFinalizer } from "./finalizer";
+import { testDeallocator } from "./deallocator";
+import { testScope } from "./scope";
+import { testTracking } from "./tracking";
+import { testHardening } from "./hardening";
+import { testGuarded } from "./guarded";
+import { testClose } from "./close";
+import { testWorkerThreads } from "./worker-threads";
 import { person, personType } from "./types";
 
 const platform = process.platform;
@@ -307,7 +332,7 @@ const testPointer = () => {
 };
 
 const testRunInNewThread = () => {
-  load({
+  return load({
     library: "libsum",
     funcName: "sum",
     retType: DataType.I32,
@@ -354,7 +379,7 @@ const testFunction = () => {
     paramsType: [funcDesc],
     paramsValue: [func],
   });
-  load({
+  return load({
     library: "libsum",
     funcName: "callFunction",
     // set runInNewThread to true, if you want to get the function return value in c
@@ -429,16 +454,83 @@ const unitTest = () => {
   logGreen("test bool succeed");
   testMainProgram();
   logGreen("test main program succeed");
-  testFunction();
+  const functionCall = testFunction();
   testCpp();
   logGreen("test cpp succeed");
   testPointer();
   logGreen("test createPointer succeed");
-  testRunInNewThread();
+  const runInNewThread = testRunInNewThread();
   testObject();
   logGreen("test object succeed");
+  // resolves once the calls running in new threads have finished
+  return Promise.all([functionCall, runInNewThread]);
+};
+
+// run after unitTest, each of them opens and closes libsum itself
+const featureTest = async () => {
+  await testLibraryHandle();
+  logGreen("test library handle succeed");
+  await testCallPointer();
+  logGreen("test call pointer succeed");
+  await testCallback();
+  logGreen("test callback succeed");
+  await testStackStructCallback();
+  logGreen("test stack struct callback succeed");
+  await testStructView();
+  logGreen("test struct view succeed");
+  await testLayout();
+  logGreen("test layout succeed");
+  // the abi fixtures are only built on x86_64 unix
+  if (process.arch === "x64" && platform !== "win32") {
+    await testAbi();
+    logGreen("test abi succeed");
+  }
+  await testArgCheck();
+  logGreen("test arg check succeed");
+  await testTypeError();
+  logGreen("test type error succeed");
+  await testErrorCode();
+  logGreen("test error code succeed");
+  await testNullCharacter();
+  logGreen("test null character succeed");
+  await testStringEncoding();
+  logGreen("test string encoding succeed");
+  await testTypedArray();
+  logGreen("test typed array succeed");
+  await testArrayBuffer();
+  logGreen("test array buffer succeed");
+  await testExternalBuffer();
+  logGreen("test external buffer succeed");
+  await testMemory();
+  logGreen("test memory succeed");
+  await testPointerAddress();
+  logGreen("test pointer address succeed");
+  await testFinalizer();
+  logGreen("test finalizer succeed");
+  await testDeallocator();
+  logGreen("test deallocator succeed");
+  await testScope();
+  logGreen("test scope succeed");
+  await testTracking();
+  logGreen("test tracking succeed");
+  await testHardening();
+  logGreen("test hardening succeed");
+  // guarded calls are only supported on unix
+  if (platform !== "win32") {
+    await testGuarded();
+    logGreen("test guarded call succeed");
+  }
+  await testClose();
+  logGreen("test close succeed");
+  await testWorkerThreads();
+  logGreen("test worker threads succeed");
 };
 
-unitTest();
+unitTest()
+  .then(featureTest)
+  .catch((err) => {
+    console.error(err);
+    process.exitCode = 1;
+  });
 
 exports.unitTest = unitTest;
@@ -0,0 +1,89 @@
+import { equal, deepStrictEqual, throws } from "assert";
+import {
+  open,
+  close,
+  load,
+  sizeOf,
+  alignOf,
+  offsetOf,
+  layout,
+  arrayConstructor,
+  DataType,
+} from "../index";
+import { personType } from "./types";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const cLayoutOf = (which: number) =>
+  load({
+    library: "libsum",
+    funcName: "cLayoutOf",
+    retType: DataType.I32,
+    paramsType: [DataType.I32],
+    paramsValue: [which],
+  });
+
+const minutiaType = {
+  x: DataType.I16,
+  y: DataType.I16,
+  dir: DataType.I16,
+  kind: DataType.U8,
+};
+const minutiaeType = {
+  nNumber: DataType.I16,
+  item: arrayConstructor({
+    type: DataType.StructArray,
+    structItemType: minutiaType,
+    length: 3,
+    ffiTypeTag: DataType.StackArray,
+  }),
+};
+const vec2Type = {
+  x: DataType.Double,
+  y: DataType.Double,
+  ffiTypeTag: DataType.StackStruct,
+};
+const anchorType = {
+  origin: vec2Type,
+  id: DataType.I32,
+  ffiTypeTag: DataType.StackStruct,
+};
+
+export const testLayout = () => {
+  open({ library: "libsum", path: dynamicLib });
+  equal(sizeOf(DataType.I32), 4);
+  equal(sizeOf(DataType.Double), 8);
+  equal(alignOf(DataType.I16), 2);
+  equal(sizeOf(DataType.String), sizeOf(DataType.External));
+  // the values printed by sizeof/offsetof in c
+  equal(sizeOf(personType), cLayoutOf(0));
+  equal(alignOf(personType), cLayoutOf(1));
+  equal(offsetOf(personType, "stackStruct.age"), cLayoutOf(2));
+  equal(offsetOf(personType, "byte"), cLayoutOf(3));
+  equal(sizeOf(minutiaeType), cLayoutOf(4));
+  equal(offsetOf(minutiaeType, "item"), cLayoutOf(5));
+  equal(sizeOf(anchorType), cLayoutOf(6));
+  equal(offsetOf(anchorType, "id"), cLayoutOf(7));
+
+  deepStrictEqual(layout({ a: DataType.U8, b: DataType.I32, c: DataType.I16 }), {
+    size: 12,
+    align: 4,
+    fields: [
+      { name: "a", offset: 0, size: 1, align: 1, padding: 0 },
+      { name: "b", offset: 4, size: 4, align: 4, padding: 3 },
+      { name: "c", offset: 8, size: 2, align: 2, padding: 0 },
+    ],
+  });
+  equal(layout(minutiaeType).fields[1].size, 3 * sizeOf(minutiaType));
+  throws(
+    () => offsetOf(personType, "unknown"),
+    (err) => err.code === "ERR_FFI_TYPE",
+  );
+  // parent is a pointer, its fields are not part of the struct
+  throws(
+    () => offsetOf(personType, "parent.age"),
+    (err) => err.code === "ERR_FFI_TYPE",
+  );
+  close("libsum");
+};
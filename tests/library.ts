@@ -0,0 +1,87 @@
+import { equal, throws } from "assert";
+import { open, close, load, callPointer, DataType, isNullPointer, arrayConstructor } from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+export const testLibraryHandle = async () => {
+  const lib = open({ library: "libsum", path: dynamicLib });
+  equal(lib.path, dynamicLib);
+  equal(lib.has("sum"), true);
+  equal(lib.has("notExistFunction"), false);
+  equal(isNullPointer(lib.symbol("sum")), false);
+  throws(() => lib.symbol("notExistFunction"), /Cannot find "notExistFunction"/);
+
+  const sum = lib.func("sum", {
+    retType: DataType.I32,
+    paramsType: [DataType.I32, DataType.I32],
+  });
+  equal(sum([1, 2]), 3);
+  // open registers the name as well, so load keeps working with the same library
+  equal(
+    load({
+      library: "libsum",
+      funcName: "sum",
+      retType: DataType.I32,
+      paramsType: [DataType.I32, DataType.I32],
+      paramsValue: [2, 3],
+    }),
+    5,
+  );
+
+  const createArrayi32 = lib.func("createArrayi32", {
+    retType: arrayConstructor({ type: DataType.I32Array, length: 3 }),
+    paramsType: [DataType.I32Array, DataType.I32],
+  });
+  const doubleSum = lib.func("doubleSum", {
+    retType: DataType.Double,
+    paramsType: [DataType.Double, DataType.Double],
+    runInNewThread: true,
+  });
+
+  lib.close();
+  // functions created before close keep the library loaded
+  equal(sum([3, 4]), 7);
+  equal(createArrayi32([[1, 2, 3], 3]).join(), "1,2,3");
+  equal(await doubleSum([1.5, 2.5]), 4);
+  throws(() => lib.func("sum", { retType: DataType.I32, paramsType: [] }), /has been closed/);
+  throws(() => lib.has("sum"), /has been closed/);
+  // closing the handle also unregisters its name
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "sum",
+        retType: DataType.I32,
+        paramsType: [DataType.I32, DataType.I32],
+        paramsValue: [1, 2],
+      }),
+    /you need to open the file/,
+  );
+
+  // close(name) closes the handles of the library as well
+  const handle = open({ library: "libsum", path: dynamicLib });
+  const symbol = handle.symbol("sum");
+  close("libsum");
+  throws(() => handle.has("sum"), /has been closed/);
+  throws(() => handle.symbol("sum"), /has been closed/);
+
+  // the same name can't silently refer to another library
+  open({ library: "libsum", path: dynamicLib });
+  throws(() => open({ library: "libsum", path: "./libnotexist.so" }), /libnotexist/);
+  close("libsum");
+  open({ library: "libsum", path: "./libsum.so" });
+  throws(() => open({ library: "libsum", path: process.cwd() + "/libsum.so" }), /opened from another path/);
+  close("libsum");
+
+  // a symbol keeps the library loaded after it has been closed
+  equal(
+    callPointer({
+      funcPtr: symbol,
+      retType: DataType.I32,
+      paramsType: [DataType.I32, DataType.I32],
+      paramsValue: [6, 7],
+    }),
+    13,
+  );
+};
@@ -0,0 +1,101 @@
+import { equal, throws } from "assert";
+import {
+  createPointer,
+  unwrapPointer,
+  freePointer,
+  createExternalBuffer,
+  arrayConstructor,
+  readI8,
+  readU8,
+  writeU8,
+  readI16,
+  writeI16,
+  readU16,
+  readI32,
+  writeI32,
+  readU32,
+  readI64,
+  writeI64,
+  readU64,
+  writeU64,
+  readFloat,
+  writeFloat,
+  readDouble,
+  writeDouble,
+  readPointer,
+  writePointer,
+  readCString,
+  memcpy,
+  memset,
+  ptrAdd,
+  isNullPointer,
+  DataType,
+  PointerType,
+} from "../index";
+
+const size = 64;
+const bytesType = arrayConstructor({ type: DataType.U8Array, length: size });
+
+export const testMemory = () => {
+  const [storage] = createPointer({
+    paramsType: [bytesType],
+    paramsValue: [Buffer.alloc(size)],
+  });
+  const ptr = unwrapPointer([storage])[0];
+  const buf = createExternalBuffer(ptr, size);
+
+  writeI32(ptr, 0, -2);
+  equal(readI32(ptr, 0), -2);
+  equal(readU32(ptr), 0xfffffffe);
+  equal(buf.readInt32LE(0), -2);
+  writeI16(ptr, 4, -3);
+  equal(readI16(ptr, 4), -3);
+  equal(readU16(ptr, 4), 0xfffd);
+  writeU8(ptr, 6, 200);
+  equal(readU8(ptr, 6), 200);
+  equal(readI8(ptr, 6), -56);
+  // unaligned
+  writeDouble(ptr, 9, 1.25);
+  equal(readDouble(ptr, 9), 1.25);
+  equal(buf.readDoubleLE(9), 1.25);
+  writeFloat(ptr, 17, 0.5);
+  equal(readFloat(ptr, 17), 0.5);
+  writeI64(ptr, 24, -(2n ** 60n));
+  equal(readI64(ptr, 24), -(2n ** 60n));
+  writeU64(ptr, 24, 2n ** 64n - 1n);
+  equal(readU64(ptr, 24), 2n ** 64n - 1n);
+  throws(() => writeU64(ptr, 24, -1n), (err) => err.code === "ERR_FFI_INVALID_ARG");
+  // out of range values aren't truncated
+  throws(() => writeU8(ptr, 6, 256), (err) => err.code === "ERR_FFI_INVALID_ARG");
+  throws(() => writeI16(ptr, 4, 1.5), (err) => err.code === "ERR_FFI_INVALID_ARG");
+  throws(() => writeI32(ptr, 0, 2 ** 31), (err) => err.code === "ERR_FFI_INVALID_ARG");
+  throws(() => writeFloat(ptr, 17, 1e39), (err) => err.code === "ERR_FFI_INVALID_ARG");
+  equal(readU8(ptr, 6), 200);
+
+  writePointer(ptr, 32, ptrAdd(ptr, 40));
+  const target = readPointer(ptr, 32);
+  buf.write("hello\0", 40);
+  equal(readCString(target), "hello");
+  equal(readCString(ptr, 40, 3), "hel");
+  equal(readCString(ptrAdd(target, 1)), "ello");
+
+  memcpy(ptrAdd(ptr, 48), target, 6);
+  equal(readCString(ptr, 48), "hello");
+  memset(ptrAdd(ptr, 48), 0x41, 4n);
+  equal(readCString(ptr, 48), "AAAAo");
+  throws(() => memset(ptr, 0x41, -1), (err) => err.code === "ERR_FFI_INVALID_ARG");
+  throws(() => memset(ptr, 0x141, 1), (err) => err.code === "ERR_FFI_INVALID_ARG");
+  // the scan stops at the terminator or at maxLen
+  equal(readCString(ptr, 48, 100), "AAAAo");
+  equal(readCString(ptr, 48, 0), "");
+
+  const nullPtr = ptrAdd(ptr, 0);
+  equal(isNullPointer(nullPtr), false);
+  throws(() => readI32(readPointer(ptr, 56)), (err) => err.code === "ERR_FFI_INVALID_ARG");
+
+  freePointer({
+    paramsType: [bytesType],
+    paramsValue: [storage],
+    pointerType: PointerType.RsPointer,
+  });
+};
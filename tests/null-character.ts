@@ -0,0 +1,135 @@
+import { equal, throws } from "assert";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  arrayConstructor,
+  DataType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const isNullCharacterError = (argIndex) => (err) =>
+  err.code === "ERR_FFI_ENCODING" &&
+  err.argIndex === argIndex &&
+  /null character at index 1/.test(err.message);
+const isTypeError = (err) => err.code === "ERR_FFI_TYPE" && /StringWithLength/.test(err.message);
+
+const byteAt = (index, runInNewThread = false) =>
+  load({
+    library: "libsum",
+    funcName: "lengthDelimitedByteAt",
+    retType: DataType.I32,
+    paramsType: [DataType.StringWithLength, DataType.I32],
+    paramsValue: ["a\0b", index],
+    runInNewThread,
+  });
+
+export const testNullCharacter = async () => {
+  open({ library: "libsum", path: dynamicLib });
+
+  // c would stop reading the string at the null character
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "concatenateStrings",
+        retType: DataType.String,
+        paramsType: [DataType.String, DataType.String],
+        paramsValue: ["foo", "a\0b"],
+      }),
+    isNullCharacterError(1),
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "concatenateWideStrings",
+        retType: DataType.WString,
+        paramsType: [DataType.WString, DataType.WString],
+        paramsValue: ["a\0b", "foo"],
+      }),
+    isNullCharacterError(0),
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "createArrayString",
+        retType: arrayConstructor({ type: DataType.StringArray, length: 2 }),
+        paramsType: [arrayConstructor({ type: DataType.StringArray, length: 2 }), DataType.I32],
+        paramsValue: [["foo", "a\0b"], 2],
+      }),
+    isNullCharacterError(0),
+  );
+  throws(
+    () =>
+      createPointer({
+        paramsType: [{ name: DataType.String }],
+        paramsValue: [{ name: "a\0b" }],
+      }),
+    (err) => err.code === "ERR_FFI_ENCODING",
+  );
+
+  // the length is passed with the data so the null character is kept
+  equal(byteAt(1), 0);
+  equal(byteAt(2), "b".charCodeAt(0));
+  equal(byteAt(3), -1);
+  equal(await byteAt(2, true), "b".charCodeAt(0));
+  equal(
+    load({
+      library: "libsum",
+      funcName: "lengthDelimitedLength",
+      retType: DataType.U64,
+      paramsType: [DataType.StringWithLength],
+      paramsValue: ["é\0"],
+    }),
+    3,
+  );
+  equal(
+    load({
+      library: "libsum",
+      funcName: "lengthDelimitedLength",
+      retType: DataType.U64,
+      paramsType: [DataType.StringWithLength],
+      paramsValue: [""],
+    }),
+    0,
+  );
+
+  // StringWithLength is two arguments, it can't describe a single value
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "lengthDelimitedLength",
+        retType: DataType.StringWithLength,
+        paramsType: [DataType.StringWithLength],
+        paramsValue: ["foo"],
+      }),
+    isTypeError,
+  );
+  throws(
+    () =>
+      createPointer({
+        paramsType: [DataType.StringWithLength],
+        paramsValue: ["foo"],
+      }),
+    isTypeError,
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "lengthDelimitedLength",
+        retType: DataType.U64,
+        paramsType: [{ str: DataType.StringWithLength }],
+        paramsValue: [{ str: "foo" }],
+      }),
+    isTypeError,
+  );
+
+  close("libsum");
+};
@@ -0,0 +1,64 @@
+import { equal, ok, throws } from "assert";
+import {
+  createPointer,
+  unwrapPointer,
+  freePointer,
+  arrayConstructor,
+  pointerToAddress,
+  addressToPointer,
+  pointerToString,
+  pointerEquals,
+  pointerOffset,
+  pointerDiff,
+  ptrAdd,
+  readI32,
+  readDouble,
+  isNullPointer,
+  DataType,
+  PointerType,
+} from "../index";
+
+const itemType = { id: DataType.I32, value: DataType.Double };
+const arrayType = arrayConstructor({ type: DataType.DoubleArray, length: 4 });
+
+export const testPointerAddress = () => {
+  const [storage] = createPointer({
+    paramsType: [arrayType],
+    paramsValue: [[1.5, 2.5, 3.5, 4.5]],
+  });
+  const ptr = unwrapPointer([storage])[0];
+  const address = pointerToAddress(ptr);
+  ok(address > 0n);
+  equal(pointerToString(ptr), "0x" + address.toString(16));
+
+  const samePtr = addressToPointer(address);
+  ok(samePtr !== ptr);
+  ok(pointerEquals(samePtr, ptr));
+  ok(!pointerEquals(ptrAdd(ptr, 8), ptr));
+  // addresses can be used as map keys
+  const handles = new Map([[pointerToAddress(samePtr), "array"]]);
+  equal(handles.get(pointerToAddress(ptr)), "array");
+
+  const third = pointerOffset(ptr, 2, DataType.Double);
+  equal(readDouble(third), 3.5);
+  equal(pointerDiff(third, ptr), 16);
+  equal(pointerDiff(ptr, third), -16);
+  ok(pointerEquals(pointerOffset(ptr, 16), third));
+  // struct elements are measured by their layout
+  equal(pointerDiff(pointerOffset(ptr, 1, itemType), ptr), 16);
+
+  const invalidType: any = "double";
+  throws(
+    () => pointerOffset(ptr, 1, invalidType),
+    (err) => err.code === "ERR_FFI_TYPE",
+  );
+
+  ok(isNullPointer(addressToPointer(0n)));
+  equal(readI32(addressToPointer(address)), readI32(ptr));
+
+  freePointer({
+    paramsType: [arrayType],
+    paramsValue: [storage],
+    pointerType: PointerType.RsPointer,
+  });
+};
@@ -0,0 +1,106 @@
+import { equal, deepStrictEqual, throws } from "assert";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  restorePointer,
+  freePointer,
+  withScope,
+  arrayConstructor,
+  DataType,
+  PointerType,
+} from "../index";
+import { person, personType } from "./types";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const i32ArrayType = arrayConstructor({ type: DataType.I32Array, length: 3 });
+
+export const testScope = () => {
+  open({ library: "libsum", path: dynamicLib });
+  const result = withScope(() => {
+    const [str, arr, personPtr] = createPointer({
+      paramsType: [DataType.String, i32ArrayType, personType],
+      paramsValue: ["foo", [1, 2, 3], person],
+    });
+    const [restoredStr, restoredArr, restoredPerson] = restorePointer({
+      retType: [DataType.String, i32ArrayType, personType],
+      paramsValue: [str, arr, personPtr],
+    });
+    equal(restoredStr, "foo");
+    deepStrictEqual(restoredArr, [1, 2, 3]);
+    deepStrictEqual(restoredPerson, person);
+    // the scope owns the memory, freeing it here does nothing
+    freePointer({
+      paramsType: [DataType.String],
+      paramsValue: [str],
+      pointerType: PointerType.RsPointer,
+    });
+    const nested = withScope(() => {
+      const [num] = createPointer({ paramsType: [DataType.I32], paramsValue: [42] });
+      return restorePointer({ retType: [DataType.I32], paramsValue: [num] })[0];
+    });
+    equal(nested, 42);
+    return load({
+      library: "libsum",
+      funcName: "concatenateStrings",
+      retType: DataType.String,
+      paramsType: [DataType.String, DataType.String],
+      paramsValue: [restoredStr, "bar"],
+    });
+  });
+  equal(result, "foobar");
+
+  throws(
+    () =>
+      withScope(() => {
+        createPointer({ paramsType: [DataType.String], paramsValue: ["foo"] });
+        throw new Error("scope error");
+      }),
+    /scope error/,
+  );
+  throws(
+    () =>
+      withScope(() =>
+        createPointer({
+          paramsType: [DataType.I32],
+          paramsValue: [1],
+          finalizer: PointerType.RsPointer,
+        }),
+      ),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+
+  // outside of a scope the pointers are owned by the caller again
+  const [str] = createPointer({ paramsType: [DataType.String], paramsValue: ["baz"] });
+  equal(restorePointer({ retType: [DataType.String], paramsValue: [str] })[0], "baz");
+  freePointer({
+    paramsType: [DataType.String],
+    paramsValue: [str],
+    pointerType: PointerType.RsPointer,
+  });
+
+  // the arguments of a call are allocated in a per-call arena
+  for (let i = 0; i < 1000; i++) {
+    withScope(() => {
+      const [storage] = createPointer({
+        paramsType: [i32ArrayType],
+        paramsValue: [[i, i + 1, i + 2]],
+      });
+      equal(restorePointer({ retType: [i32ArrayType], paramsValue: [storage] })[0][2], i + 2);
+    });
+    equal(
+      load({
+        library: "libsum",
+        funcName: "concatenateStrings",
+        retType: DataType.String,
+        paramsType: [DataType.String, DataType.String],
+        paramsValue: ["a".repeat(i), "b"],
+      }).length,
+      i + 1,
+    );
+  }
+  close("libsum");
+};
@@ -0,0 +1,95 @@
+import { equal, deepStrictEqual } from "assert";
+import {
+  open,
+  close,
+  load,
+  DataType,
+  funcConstructor,
+  createPointer,
+  unwrapPointer,
+  freePointer,
+  PointerType,
+} from "../index";
+import { logGreen } from "./utils";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const vec2Type = {
+  x: DataType.Double,
+  y: DataType.Double,
+  ffiTypeTag: DataType.StackStruct,
+};
+const anchorType = {
+  origin: vec2Type,
+  id: DataType.I32,
+  ffiTypeTag: DataType.StackStruct,
+};
+
+async function testStackStructReturn() {
+  // Vec2 (*transform)(Vec2 p): struct argument and struct return in the callback.
+  // The return value only reaches C when the call runs in a new thread.
+  const transformDesc = funcConstructor({
+    paramsType: [vec2Type],
+    retType: vec2Type,
+  });
+  const transform = createPointer({
+    paramsType: [transformDesc],
+    paramsValue: [(p) => ({ x: p.x * 2, y: p.y + 0.5 })],
+  });
+  const res = await load({
+    library: "libsum",
+    funcName: "applyVec2Transform",
+    retType: vec2Type,
+    paramsType: [DataType.External, vec2Type],
+    paramsValue: [unwrapPointer(transform)[0], { x: 1.5, y: -3 }],
+    runInNewThread: true,
+  });
+  deepStrictEqual(res, { x: 3, y: -2.5 });
+  freePointer({
+    paramsType: [transformDesc],
+    paramsValue: transform,
+    pointerType: PointerType.RsPointer,
+  });
+  logGreen("test stack struct callback return succeed");
+}
+
+async function testNestedStackStructParam() {
+  const visitorDesc = funcConstructor({
+    paramsType: [anchorType],
+    retType: DataType.I32,
+  });
+  let received;
+  const visitor = createPointer({
+    paramsType: [visitorDesc],
+    paramsValue: [
+      (a) => {
+        received = a;
+        return a.id + 1;
+      },
+    ],
+  });
+  const res = await load({
+    library: "libsum",
+    funcName: "visitAnchor",
+    retType: DataType.I32,
+    paramsType: [DataType.External, anchorType],
+    paramsValue: [unwrapPointer(visitor)[0], { origin: { x: 7.25, y: 8 }, id: 41 }],
+    runInNewThread: true,
+  });
+  equal(res, 42);
+  deepStrictEqual(received, { origin: { x: 7.25, y: 8 }, id: 41 });
+  freePointer({
+    paramsType: [visitorDesc],
+    paramsValue: visitor,
+    pointerType: PointerType.RsPointer,
+  });
+  logGreen("test nested stack struct callback param succeed");
+}
+
+export const testStackStructCallback = async () => {
+  open({ library: "libsum", path: dynamicLib });
+  await testStackStructReturn();
+  await testNestedStackStructParam();
+  close("libsum");
+};
@@ -0,0 +1,205 @@
+import { equal, deepStrictEqual, throws } from "assert";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  restorePointer,
+  freePointer,
+  unwrapPointer,
+  arrayConstructor,
+  stringConstructor,
+  DataType,
+  PointerType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const isEncodingError = (err) => err.code === "ERR_FFI_ENCODING";
+
+const byteAt = (type, value, index) =>
+  load({
+    library: "libsum",
+    funcName: "byteAt",
+    retType: DataType.I32,
+    paramsType: [type, DataType.I32],
+    paramsValue: [value, index],
+  });
+
+export const testStringEncoding = () => {
+  open({ library: "libsum", path: dynamicLib });
+
+  // astral characters are surrogate pairs in utf16 and single code units in utf32
+  const u16 = load({
+    library: "libsum",
+    funcName: "concatenateU16Strings",
+    retType: DataType.U16String,
+    paramsType: [DataType.U16String, DataType.U16String],
+    paramsValue: ["héllo ", "wörld 😀"],
+  });
+  equal(u16, "héllo wörld 😀");
+  equal(
+    load({
+      library: "libsum",
+      funcName: "u16CodeUnitAt",
+      retType: DataType.I32,
+      paramsType: [DataType.U16String, DataType.I32],
+      paramsValue: ["😀", 0],
+    }),
+    0xd83d,
+  );
+  const u32 = load({
+    library: "libsum",
+    funcName: "concatenateU32Strings",
+    retType: DataType.U32String,
+    paramsType: [DataType.U32String, DataType.U32String],
+    paramsValue: ["中文 ", "😀"],
+  });
+  equal(u32, "中文 😀");
+
+  equal(byteAt(DataType.Latin1String, "é", 0), 0xe9);
+  throws(() => byteAt(DataType.Latin1String, "中", 0), isEncodingError);
+  // c would stop reading at the null character
+  throws(() => byteAt(DataType.Latin1String, "a\0b", 0), isEncodingError);
+
+  // invalid data is an error instead of replacement characters
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "unpairedSurrogateString",
+        retType: DataType.U16String,
+        paramsType: [],
+        paramsValue: [],
+      }),
+    (err) => isEncodingError(err) && /0xd800 at code unit 1/.test(err.message),
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "invalidUtf8String",
+        retType: stringConstructor({ encoding: "utf8" }),
+        paramsType: [],
+        paramsValue: [],
+      }),
+    (err) => isEncodingError(err) && /byte 1/.test(err.message),
+  );
+
+  // legacy encodings are converted by iconv
+  const gbk = stringConstructor({ encoding: "GBK" });
+  equal(
+    load({
+      library: "libsum",
+      funcName: "gbkString",
+      retType: gbk,
+      paramsType: [],
+      paramsValue: [],
+    }),
+    "中文",
+  );
+  deepStrictEqual(
+    [0, 1].map((index) => byteAt(gbk, "中", index)),
+    [0xd6, 0xd0],
+  );
+  throws(() => byteAt(gbk, "😀", 0), isEncodingError);
+  throws(
+    () => byteAt(stringConstructor({ encoding: "no-such-encoding" }), "a", 0),
+    (err) => err.code === "ERR_FFI_TYPE",
+  );
+
+  // struct fields and arrays
+  const u16Array = arrayConstructor({ type: DataType.StringArray, length: 2, encoding: "utf16" });
+  const structType = {
+    u16: DataType.U16String,
+    u32: DataType.U32String,
+    latin1: DataType.Latin1String,
+    gbk,
+    u16Array,
+  };
+  const value = {
+    u16: "😀 u16",
+    u32: "😀 u32",
+    latin1: "café",
+    gbk: "汉字",
+    u16Array: ["ä", "😀"],
+  };
+  deepStrictEqual(
+    load({
+      library: "libsum",
+      funcName: "getEncodedStrings",
+      retType: structType,
+      paramsType: [structType],
+      paramsValue: [value],
+    }),
+    value,
+  );
+  deepStrictEqual(
+    load({
+      library: "libsum",
+      funcName: "getU16StringArray",
+      retType: u16Array,
+      paramsType: [u16Array],
+      paramsValue: [["ä", "😀"]],
+    }),
+    ["ä", "😀"],
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "getU16StringArray",
+        retType: DataType.Void,
+        paramsType: [arrayConstructor({ type: DataType.I32Array, length: 1, encoding: "utf16" })],
+        paramsValue: [[1]],
+      }),
+    (err) => err.code === "ERR_FFI_TYPE",
+  );
+
+  // pointers
+  const [ptr] = createPointer({
+    paramsType: [structType],
+    paramsValue: [value],
+  });
+  deepStrictEqual(
+    restorePointer({
+      retType: [structType],
+      paramsValue: [ptr],
+    }),
+    [value],
+  );
+  freePointer({
+    paramsType: [structType],
+    paramsValue: [ptr],
+    pointerType: PointerType.RsPointer,
+  });
+  const [strPtr] = createPointer({
+    paramsType: [DataType.U32String],
+    paramsValue: ["😀"],
+  });
+  deepStrictEqual(
+    restorePointer({
+      retType: [DataType.U32String],
+      paramsValue: [strPtr],
+    }),
+    ["😀"],
+  );
+  equal(
+    load({
+      library: "libsum",
+      funcName: "concatenateU32Strings",
+      retType: DataType.U32String,
+      paramsType: [DataType.External, DataType.U32String],
+      paramsValue: [unwrapPointer([strPtr])[0], "!"],
+    }),
+    "😀!",
+  );
+  freePointer({
+    paramsType: [DataType.U32String],
+    paramsValue: [strPtr],
+    pointerType: PointerType.RsPointer,
+  });
+
+  close("libsum");
+};
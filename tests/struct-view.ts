@@ -0,0 +1,78 @@
+import { equal, deepStrictEqual } from "assert";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  restorePointer,
+  unwrapPointer,
+  freePointer,
+  createStructView,
+  DataType,
+  PointerType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const ringHeaderType = {
+  head: DataType.I32,
+  tail: DataType.I32,
+  origin: {
+    x: DataType.Double,
+    y: DataType.Double,
+    ffiTypeTag: DataType.StackStruct,
+  },
+  scale: DataType.Double,
+};
+
+export const testStructView = () => {
+  open({ library: "libsum", path: dynamicLib });
+  const [ptr] = createPointer({
+    paramsType: [ringHeaderType],
+    paramsValue: [{ head: 10, tail: 2, origin: { x: 1.5, y: 2.5 }, scale: 0.5 }],
+  });
+  const ringPtr = unwrapPointer([ptr])[0];
+  const view = createStructView(ptr, ringHeaderType);
+  equal(view.head, 10);
+  equal(view.origin.y, 2.5);
+
+  // changes made by c are visible without restoring the struct again
+  load({
+    library: "libsum",
+    funcName: "advanceRing",
+    retType: DataType.Void,
+    paramsType: [DataType.External, DataType.I32],
+    paramsValue: [ringPtr, 5],
+  });
+  equal(view.head, 15);
+  equal(view.origin.x, 6.5);
+
+  // writes from js reach c
+  view.tail = 12;
+  view.origin.y = 4;
+  equal(
+    load({
+      library: "libsum",
+      funcName: "ringSpan",
+      retType: DataType.I32,
+      paramsType: [DataType.External],
+      paramsValue: [ringPtr],
+    }),
+    3,
+  );
+  deepStrictEqual(restorePointer({ retType: [ringHeaderType], paramsValue: [ptr] })[0], {
+    head: 15,
+    tail: 12,
+    origin: { x: 6.5, y: 4 },
+    scale: 0.5,
+  });
+  deepStrictEqual(Object.keys(view), ["head", "tail", "origin", "scale"]);
+
+  freePointer({
+    paramsType: [ringHeaderType],
+    paramsValue: [ptr],
+    pointerType: PointerType.RsPointer,
+  });
+  close("libsum");
+};
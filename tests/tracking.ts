@@ -0,0 +1,121 @@
+import { equal, deepStrictEqual, ok, throws } from "assert";
+import { setFlagsFromString } from "v8";
+import { runInNewContext } from "vm";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  freePointer,
+  createCallback,
+  funcConstructor,
+  pointerToAddress,
+  setAllocationTracking,
+  getLiveAllocations,
+  DataType,
+  PointerType,
+} from "../index";
+import { person, personType } from "./types";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+setFlagsFromString("--expose-gc");
+const gc = runInNewContext("gc");
+
+const collect = async () => {
+  for (let i = 0; i < 3; i++) {
+    gc();
+    await new Promise((resolve) => setImmediate(resolve));
+  }
+};
+
+const concatenateStrings = (a: string, b: string, runInNewThread = false) =>
+  load({
+    library: "libsum",
+    funcName: "concatenateStrings",
+    retType: DataType.String,
+    paramsType: [DataType.String, DataType.String],
+    paramsValue: [a, b],
+    runInNewThread,
+  });
+
+export const testTracking = async () => {
+  open({ library: "libsum", path: dynamicLib });
+  throws(
+    () => getLiveAllocations(),
+    (err) => err.code === "ERR_FFI_INVALID_ARG",
+  );
+  setAllocationTracking(true);
+  deepStrictEqual(getLiveAllocations(), []);
+
+  const [str, personPtr] = createPointer({
+    paramsType: [DataType.String, personType],
+    paramsValue: ["foo", person],
+  });
+  const live = getLiveAllocations();
+  const strLive = live.filter(({ type }) => type === DataType.String);
+  // the storage of the pointer and the string it points to
+  equal(strLive.length, 2);
+  ok(strLive.some(({ address }) => address === pointerToAddress(str)));
+  equal(strLive[0].kind, "value");
+  equal(strLive[0].funcName, "createPointer");
+  equal(strLive[0].library, undefined);
+  // the nested strings, arrays and structs of person are tracked as well
+  const personLive = live.filter(({ type }) => type !== DataType.String);
+  ok(personLive.length > 1);
+  ok(personLive.some(({ address }) => address === pointerToAddress(personPtr)));
+  deepStrictEqual(Object.keys(personLive[0].type), Object.keys(personType));
+  freePointer({
+    paramsType: [DataType.String],
+    paramsValue: [str],
+    pointerType: PointerType.RsPointer,
+  });
+  equal(getLiveAllocations().length, personLive.length);
+  freePointer({
+    paramsType: [personType],
+    paramsValue: [personPtr],
+    pointerType: PointerType.RsPointer,
+  });
+  deepStrictEqual(getLiveAllocations(), []);
+
+  // the arguments of a call are released when it returns
+  equal(concatenateStrings("foo", "bar"), "foobar");
+  deepStrictEqual(getLiveAllocations(), []);
+  const pending = concatenateStrings("foo", "baz", true);
+  deepStrictEqual(
+    getLiveAllocations().map(({ library, funcName }) => `${library}.${funcName}`),
+    new Array(4).fill("libsum.concatenateStrings"),
+  );
+  equal(await pending, "foobaz");
+  deepStrictEqual(getLiveAllocations(), []);
+
+  // closures live until they are freed
+  load({
+    library: "libsum",
+    funcName: "registerCallback",
+    retType: DataType.Void,
+    paramsType: [
+      funcConstructor({ paramsType: [DataType.I32], retType: DataType.Void, needFree: true }),
+    ],
+    paramsValue: [() => {}],
+  });
+  deepStrictEqual(getLiveAllocations(), []);
+  let callback = createCallback(
+    () => {},
+    funcConstructor({ paramsType: [DataType.I32], retType: DataType.Void }),
+  );
+  const [closure] = getLiveAllocations();
+  equal(closure.kind, "callback");
+  equal(closure.funcName, "createCallback");
+  callback = undefined;
+  await collect();
+  deepStrictEqual(getLiveAllocations(), []);
+
+  setAllocationTracking(false);
+  createPointer({ paramsType: [DataType.I32], paramsValue: [1] });
+  setAllocationTracking(true);
+  deepStrictEqual(getLiveAllocations(), []);
+  setAllocationTracking(false);
+  close("libsum");
+};
@@ -0,0 +1,87 @@
+import { equal, ok, throws } from "assert";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  restorePointer,
+  freePointer,
+  arrayConstructor,
+  DataType,
+  PointerType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const isTypeError = (path: string) => (err) => {
+  equal(err.code, "ERR_FFI_TYPE");
+  ok(err.message.includes(path), err.message);
+  return true;
+};
+
+export const testTypeError = () => {
+  open({ library: "libsum", path: dynamicLib });
+  const badStruct = {
+    header: {
+      id: DataType.I32,
+      flags: 1000,
+    },
+  };
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "sum",
+        retType: DataType.I32,
+        paramsType: [badStruct, DataType.I32],
+        paramsValue: [{ header: { id: 1, flags: 2 } }, 2],
+      }),
+    isTypeError("paramsType[0].header.flags"),
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "sum",
+        retType: { list: arrayConstructor({ type: DataType.StructArray, length: 1 }) },
+        paramsType: [DataType.I32, DataType.I32],
+        paramsValue: [1, 2],
+      }),
+    isTypeError("retType.list.structItemType"),
+  );
+  throws(
+    () =>
+      createPointer({
+        paramsType: [DataType.I32, { value: "i32" }],
+        paramsValue: [1, { value: 1 }],
+      }),
+    isTypeError("paramsType[1].value"),
+  );
+  const [ptr] = createPointer({
+    paramsType: [DataType.I32],
+    paramsValue: [1],
+  });
+  throws(
+    () => restorePointer({ retType: [-1], paramsValue: [ptr] }),
+    isTypeError("retType[0]"),
+  );
+  equal(restorePointer({ retType: [DataType.I32], paramsValue: [ptr] })[0], 1);
+  freePointer({
+    paramsType: [DataType.I32],
+    paramsValue: [ptr],
+    pointerType: PointerType.RsPointer,
+  });
+  // the process is still usable after the errors
+  equal(
+    load({
+      library: "libsum",
+      funcName: "sum",
+      retType: DataType.I32,
+      paramsType: [DataType.I32, DataType.I32],
+      paramsValue: [1, 2],
+    }),
+    3,
+  );
+  close("libsum");
+};
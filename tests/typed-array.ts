@@ -0,0 +1,182 @@
+import { equal, deepStrictEqual, throws } from "assert";
+import {
+  open,
+  close,
+  load,
+  createPointer,
+  withScope,
+  setMemoryHardening,
+  restorePointer,
+  freePointer,
+  arrayConstructor,
+  DataType,
+  PointerType,
+} from "../index";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+
+const isInvalidArg = (err) => err.code === "ERR_FFI_INVALID_ARG";
+
+export const testTypedArray = () => {
+  open({ library: "libsum", path: dynamicLib });
+
+  // c writes into the memory of the TypedArray
+  const i32 = new Int32Array(3);
+  load({
+    library: "libsum",
+    funcName: "fillI32Array",
+    retType: DataType.Void,
+    paramsType: [DataType.I32Array, DataType.I32],
+    paramsValue: [i32, 3],
+  });
+  deepStrictEqual(Array.from(i32), [0, 2, 4]);
+
+  // a DataView is read as items of the array type
+  const view = new DataView(new ArrayBuffer(16), 4);
+  load({
+    library: "libsum",
+    funcName: "fillI32Array",
+    retType: DataType.Void,
+    paramsType: [DataType.I32Array, DataType.I32],
+    paramsValue: [view, 3],
+  });
+  deepStrictEqual([0, 4, 8].map((offset) => view.getInt32(offset, true)), [0, 2, 4]);
+
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "fillI32Array",
+        retType: DataType.Void,
+        paramsType: [DataType.I32Array, DataType.I32],
+        paramsValue: [new Float64Array(3), 3],
+      }),
+    isInvalidArg,
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "fillI32Array",
+        retType: DataType.Void,
+        paramsType: [arrayConstructor({ type: DataType.I32Array, length: 4 }), DataType.I32],
+        paramsValue: [new Int32Array(3), 3],
+      }),
+    isInvalidArg,
+  );
+
+  // returns as TypedArray
+  const doubles = load({
+    library: "libsum",
+    funcName: "createArrayDouble",
+    retType: arrayConstructor({ type: DataType.DoubleArray, length: 3, typedArray: "copy" }),
+    paramsType: [DataType.DoubleArray, DataType.I32],
+    paramsValue: [new Float64Array([1.5, 2.5, 3.5]), 3],
+    freeResultMemory: true,
+  });
+  equal(doubles instanceof Float64Array, true);
+  deepStrictEqual(Array.from(doubles), [1.5, 2.5, 3.5]);
+
+  const i64 = load({
+    library: "libsum",
+    funcName: "createArrayi64",
+    retType: arrayConstructor({ type: DataType.I64Array, length: 2, typedArray: "view" }),
+    paramsType: [DataType.I64Array, DataType.I32],
+    paramsValue: [[BigInt(1) << BigInt(40), BigInt(-1)], 2],
+  });
+  equal(i64 instanceof BigInt64Array, true);
+  deepStrictEqual(Array.from(i64), [BigInt(1) << BigInt(40), BigInt(-1)]);
+  deepStrictEqual(
+    load({
+      library: "libsum",
+      funcName: "createArrayi64",
+      retType: arrayConstructor({ type: DataType.I64Array, length: 2 }),
+      paramsType: [DataType.I64Array, DataType.I32],
+      paramsValue: [new BigInt64Array([BigInt(3), BigInt(4)]), 2],
+      freeResultMemory: true,
+    }),
+    [BigInt(3), BigInt(4)],
+  );
+
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "createArrayi32",
+        retType: arrayConstructor({ type: DataType.I32Array, length: 1, typedArray: "view" }),
+        paramsType: [DataType.I32Array, DataType.I32],
+        paramsValue: [[1], 1],
+        freeResultMemory: true,
+      }),
+    isInvalidArg,
+  );
+  throws(
+    () =>
+      load({
+        library: "libsum",
+        funcName: "createArrayString",
+        retType: arrayConstructor({ type: DataType.StringArray, length: 1, typedArray: "copy" }),
+        paramsType: [DataType.StringArray, DataType.I32],
+        paramsValue: [["foo"], 1],
+      }),
+    (err) => err.code === "ERR_FFI_TYPE" && err.message.includes("retType.typedArray"),
+  );
+
+  // a pointer owns a copy of the TypedArray
+  const source = new Int32Array([1, 2, 3]);
+  const [ptr] = createPointer({
+    paramsType: [DataType.I32Array],
+    paramsValue: [source],
+  });
+  source[0] = 42;
+  deepStrictEqual(
+    restorePointer({
+      retType: [arrayConstructor({ type: DataType.I32Array, length: 3 })],
+      paramsValue: [ptr],
+    })[0],
+    [1, 2, 3],
+  );
+  freePointer({
+    paramsType: [arrayConstructor({ type: DataType.I32Array, length: 3 })],
+    paramsValue: [ptr],
+    pointerType: PointerType.RsPointer,
+  });
+
+  // so do the pointers owned by a scope or created in hardened mode
+  const scoped = withScope(() => {
+    const [ptr] = createPointer({
+      paramsType: [DataType.I32Array],
+      paramsValue: [source],
+    });
+    source[1] = 43;
+    return restorePointer({
+      retType: [arrayConstructor({ type: DataType.I32Array, length: 3 })],
+      paramsValue: [ptr],
+    })[0];
+  });
+  deepStrictEqual(scoped, [42, 2, 3]);
+  setMemoryHardening(true);
+  try {
+    const [hardened] = createPointer({
+      paramsType: [DataType.I32Array],
+      paramsValue: [source],
+    });
+    source[2] = 44;
+    deepStrictEqual(
+      restorePointer({
+        retType: [arrayConstructor({ type: DataType.I32Array, length: 3 })],
+        paramsValue: [hardened],
+      })[0],
+      [42, 43, 3],
+    );
+    freePointer({
+      paramsType: [arrayConstructor({ type: DataType.I32Array, length: 3 })],
+      paramsValue: [hardened],
+      pointerType: PointerType.RsPointer,
+    });
+  } finally {
+    setMemoryHardening(false);
+  }
+  close("libsum");
+};
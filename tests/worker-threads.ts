@@ -0,0 +1,91 @@
+import { equal } from "assert";
+import { Worker } from "worker_threads";
+import { resolve } from "path";
+import { open, close, load, DataType } from "../index";
+import { logGreen } from "./utils";
+
+const platform = process.platform;
+const dynamicLib = platform === "win32" ? "./sum.dll" : "./libsum.so";
+const entry = resolve(__dirname, "../index");
+
+const sum = (a: number, b: number) =>
+  load({
+    library: "libsum",
+    funcName: "sum",
+    retType: DataType.I32,
+    paramsType: [DataType.I32, DataType.I32],
+    paramsValue: [a, b],
+  });
+
+// Every worker opens and closes "libsum" under the same key as the main thread.
+// Libraries are registered per env, so closing in a worker must not unload it here.
+const workerCode = `
+const { parentPort, workerData } = require("worker_threads");
+const { open, close, load, DataType } = require(workerData.entry);
+open({ library: "libsum", path: workerData.dynamicLib });
+let total = 0;
+for (let i = 0; i < 1000; i++) {
+  total += load({
+    library: "libsum",
+    funcName: "sum",
+    retType: DataType.I32,
+    paramsType: [DataType.I32, DataType.I32],
+    paramsValue: [i, workerData.index],
+  });
+}
+close("libsum");
+parentPort.postMessage(total);
+`;
+
+const runWorker = (index: number) =>
+  new Promise<number>((res, rej) => {
+    const worker = new Worker(workerCode, {
+      eval: true,
+      workerData: { entry, dynamicLib, index },
+    });
+    worker.on("message", res);
+    worker.on("error", rej);
+  });
+
+// The callback is never freed, terminating the worker must release it through
+// the env cleanup hook without affecting the main thread.
+const callbackWorkerCode = `
+const { parentPort, workerData } = require("worker_threads");
+const { open, createPointer, funcConstructor, DataType } = require(workerData.entry);
+open({ library: "libsum", path: workerData.dynamicLib });
+createPointer({
+  paramsType: [funcConstructor({ paramsType: [DataType.I32], retType: DataType.Void })],
+  paramsValue: [() => {}],
+});
+parentPort.postMessage("ready");
+`;
+
+async function testTerminateWorkerWithCallback() {
+  const worker = new Worker(callbackWorkerCode, {
+    eval: true,
+    workerData: { entry, dynamicLib },
+  });
+  await new Promise((res, rej) => {
+    worker.on("message", res);
+    worker.on("error", rej);
+  });
+  await worker.terminate();
+  equal(sum(3, 4), 7);
+  logGreen("test terminate worker with live callback succeed");
+}
+
+async function testWorkers() {
+  const totals = await Promise.all([0, 1, 2, 3].map(runWorker));
+  totals.forEach((total, index) => {
+    equal(total, (999 * 1000) / 2 + index * 1000);
+  });
+  equal(sum(1, 2), 3);
+  logGreen("test library stays open after workers close it succeed");
+}
+
+export const testWorkerThreads = async () => {
+  open({ library: "libsum", path: dynamicLib });
+  await testWorkers();
+  await testTerminateWorkerWithCallback();
+  close("libsum");
+};